@@ -4,13 +4,15 @@
 //!
 //! This CLI provides commands to place orders, query book state, and run an interactive mode.
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use order_book_core::{
     OrderBook, Side,
     format_price, format_quantity, price_to_minor_units, quantity_to_minor_units
 };
 use order_book_core::types::{Asset, Instrument};
+use order_book_core::BookSnapshot;
 use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
 use std::io::{self, Write};
 use std::str::FromStr;
 
@@ -33,6 +35,10 @@ struct Cli {
     /// Quote asset decimals (e.g., 2 for USDT cents)
     #[arg(long, default_value = "2")]
     quote_decimals: u8,
+
+    /// Session file to restore when starting interactive mode or exporting
+    #[arg(long)]
+    load: Option<String>,
     
     #[command(subcommand)]
     command: Option<Commands>,
@@ -72,6 +78,12 @@ enum Commands {
         /// Unique order ID (auto-generated if not provided)
         id: Option<u64>,
     },
+    /// Cancel a resting order (interactive mode)
+    #[command(name = "cancel")]
+    Cancel {
+        /// ID of the order to cancel
+        id: u64,
+    },
     /// Show current order book state
     #[command(name = "book", aliases = ["state", "b"])]
     Book,
@@ -91,6 +103,35 @@ enum Commands {
         #[arg(default_value = "5")]
         levels: usize,
     },
+    /// Place orders from a CSV file with `side,price,quantity,id` rows
+    #[command(name = "load")]
+    Load {
+        /// Path to the CSV file
+        path: String,
+        /// Abort at the first malformed or rejected row instead of skipping it
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Write the book's resting orders and instrument to a JSON file
+    ///
+    /// Outside interactive mode the book is the session given with `--load`.
+    #[command(name = "export")]
+    Export {
+        /// Path of the JSON file to write
+        path: String,
+    },
+    /// Save the book and next order ID to a session file (interactive mode)
+    #[command(name = "save")]
+    Save {
+        /// Path of the session file to write
+        path: String,
+    },
+    /// Replace the book with a saved session (interactive mode)
+    #[command(name = "restore")]
+    Restore {
+        /// Path of the session file to read
+        path: String,
+    },
     /// Clear the order book (interactive mode)
     #[command(name = "clear")]
     Clear,
@@ -113,7 +154,7 @@ fn main() {
     match cli.command {
         None => {
             // Default to interactive mode when no command is provided
-            run_interactive_mode(instrument);
+            run_interactive_mode(instrument, cli.load);
         }
         Some(Commands::PlaceOrder { side, price, quantity, id }) => {
             let mut book = OrderBook::new(instrument);
@@ -159,12 +200,36 @@ fn main() {
                 None => println!("No sell orders"),
             }
         }
+        Some(Commands::Load { path, strict }) => {
+            let mut book = OrderBook::new(instrument);
+            let result = load_orders(&mut book, &path, strict);
+            print_book_summary(&book);
+            if let Err(e) = result {
+                eprintln!("Error loading orders: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Export { path }) => {
+            let Some(session) = cli.load else {
+                eprintln!("Error exporting book: pass --load <session> to choose the book");
+                std::process::exit(1);
+            };
+            let result = restore_session(&instrument, &session)
+                .and_then(|(book, _)| export_book(&book, &path));
+            if let Err(e) = result {
+                eprintln!("Error exporting book: {}", e);
+                std::process::exit(1);
+            }
+            println!("Book exported to {}", path);
+        }
         Some(Commands::Interactive) => {
-            run_interactive_mode(instrument);
+            run_interactive_mode(instrument, cli.load);
         }
         // These commands are only used in interactive mode
-        Some(Commands::Buy { .. }) | Some(Commands::Sell { .. }) | Some(Commands::Book) | 
-        Some(Commands::Best) | Some(Commands::Depth { .. }) | Some(Commands::Clear) | 
+        Some(Commands::Buy { .. }) | Some(Commands::Sell { .. }) | Some(Commands::Cancel { .. }) |
+        Some(Commands::Book) | 
+        Some(Commands::Best) | Some(Commands::Depth { .. }) | Some(Commands::Clear) |
+        Some(Commands::Save { .. }) | Some(Commands::Restore { .. }) |
         Some(Commands::Quit) => {
             eprintln!("This command is only available in interactive mode.");
             eprintln!("Use: cargo run --bin order-book-cli -- interactive");
@@ -195,17 +260,21 @@ fn parse_interactive_command(input: &str) -> Result<Commands, String> {
     }
 }
 
-/// Runs the interactive REPL mode
-fn run_interactive_mode(instrument: Instrument) {
+/// Runs the interactive REPL mode, optionally starting from a saved session
+fn run_interactive_mode(instrument: Instrument, session: Option<String>) {
     println!("=== Order Book Interactive CLI ===");
     println!("Type 'help' for available commands, 'quit' to exit\n");
 
-    let mut book = OrderBook::new(instrument);
+    let (mut book, mut next_id) = match session {
+        Some(path) => restore_session(&instrument, &path).unwrap_or_else(|e| {
+            eprintln!("Error restoring session: {}", e);
+            std::process::exit(1);
+        }),
+        None => (OrderBook::new(instrument), 1u64),
+    };
 
     println!("Instrument: {}\n", book.instrument);
 
-    let mut next_id = 1u64;
-
     loop {
         print!("> ");
         io::stdout().flush().unwrap();
@@ -275,6 +344,13 @@ fn run_interactive_mode(instrument: Instrument) {
                                     Err(e) => println!("❌ Error: {}", e),
                                 }
                             }
+                            Commands::Cancel { id } => match book.cancel_order(id) {
+                                Ok(_) => {
+                                    println!("🗑️  Order {} cancelled.", id);
+                                    print_book_summary(&book);
+                                }
+                                Err(e) => println!("❌ Error: {}", e),
+                            },
                             Commands::Book => print_book_state(&book),
                             Commands::Best => print_best_prices(&book),
                             Commands::Clear => {
@@ -286,6 +362,31 @@ fn run_interactive_mode(instrument: Instrument) {
                             Commands::Depth { levels } => {
                                 print_market_depth(&book, levels);
                             }
+                            Commands::Load { path, strict } => {
+                                if let Err(e) = load_orders(&mut book, &path, strict) {
+                                    println!("❌ Error: {}", e);
+                                }
+                                print_book_summary(&book);
+                            }
+                            Commands::Save { path } => match save_session(&book, next_id, &path) {
+                                Ok(()) => println!("💾 Session saved to {}", path),
+                                Err(e) => println!("❌ Error: {}", e),
+                            },
+                            Commands::Restore { path } => {
+                                match restore_session(&book.instrument, &path) {
+                                    Ok((restored, restored_next_id)) => {
+                                        book = restored;
+                                        next_id = restored_next_id;
+                                        println!("📂 Session restored from {}", path);
+                                        print_book_summary(&book);
+                                    }
+                                    Err(e) => println!("❌ Error: {}", e),
+                                }
+                            }
+                            Commands::Export { path } => match export_book(&book, &path) {
+                                Ok(()) => println!("💾 Book exported to {}", path),
+                                Err(e) => println!("❌ Error: {}", e),
+                            },
                             // These commands shouldn't be available in interactive mode
                             Commands::PlaceOrder { .. } | Commands::BestBuy | Commands::BestSell | Commands::Interactive => {
                                 println!("❌ Command not available in interactive mode.");
@@ -318,9 +419,14 @@ fn show_help() {
     println!("📚 Available Commands:");
     println!("  buy <price> <quantity> [id]    - Place a buy order (e.g., buy 100.50 0.001)");
     println!("  sell <price> <quantity> [id]   - Place a sell order (e.g., sell 100.25 0.0015)");
+    println!("  cancel <id>                    - Cancel a resting order");
     println!("  book | state | b               - Show current order book state");
     println!("  best                           - Show best bid and ask prices");
-    println!("  depth [levels]                 - Show market depth (default: 5 levels)");
+    println!("  depth [levels]                 - Show market depth with cumulative totals (default: 5 levels)");
+    println!("  load <path> [--strict]         - Place orders from a side,price,quantity,id CSV");
+    println!("  export <path>                  - Write the book to a JSON file");
+    println!("  save <path>                    - Save the session (book and next ID)");
+    println!("  restore <path>                 - Restore a saved session");
     println!("  clear                          - Clear the order book");
     println!("  help | h                       - Show this help message");
     println!("  quit | exit | q                - Exit the CLI");
@@ -356,6 +462,87 @@ fn place_order(
         .map_err(|e| e.to_string())
 }
 
+/// Places the orders of a `side,price,quantity,id` CSV file in file order.
+///
+/// A header row is skipped. Malformed or rejected rows are reported with their
+/// line number and skipped, unless `strict` is set, which stops at the first.
+fn load_orders(book: &mut OrderBook, path: &str, strict: bool) -> Result<(), String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read {}: {}", path, e))?;
+    for (index, line) in contents.lines().enumerate() {
+        let line_number = index + 1;
+        let row = line.trim();
+        if row.is_empty() || (index == 0 && row.starts_with("side")) {
+            continue;
+        }
+        let result = parse_order_row(row).and_then(|(side, price, quantity, id)| {
+            place_order(book, side, price, quantity, id)
+        });
+        match result {
+            Ok(trades) => {
+                for trade in &trades {
+                    let price_str = format_price(trade.price, &book.instrument.quote);
+                    let qty_str = format_quantity(trade.quantity, &book.instrument.base);
+                    println!("Trade: {} @ {} (maker: {}, taker: {})",
+                        qty_str, price_str, trade.maker_id, trade.taker_id);
+                }
+            }
+            Err(e) if strict => return Err(format!("Line {}: {}", line_number, e)),
+            Err(e) => println!("Line {}: {} (skipped)", line_number, e),
+        }
+    }
+    Ok(())
+}
+
+/// Writes the book's dump as pretty-printed JSON.
+///
+/// Both sides are listed best price first, each level with its
+/// `{id, quantity, timestamp}` orders in time priority, next to the instrument.
+fn export_book(book: &OrderBook, path: &str) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(&book.dump()).map_err(|e| e.to_string())?;
+    std::fs::write(path, json).map_err(|e| format!("Cannot write {}: {}", path, e))
+}
+
+/// Interactive session state persisted by `save` and `restore`.
+#[derive(Serialize, Deserialize)]
+struct Session {
+    /// Next auto-generated order ID, so restored orders are not reused
+    next_id: u64,
+    /// Resting orders and instrument
+    book: BookSnapshot,
+}
+
+/// Writes the book and next auto-generated ID to a JSON session file.
+fn save_session(book: &OrderBook, next_id: u64, path: &str) -> Result<(), String> {
+    let session = Session { next_id, book: book.snapshot() };
+    let json = serde_json::to_string_pretty(&session).map_err(|e| e.to_string())?;
+    std::fs::write(path, json).map_err(|e| format!("Cannot write {}: {}", path, e))
+}
+
+/// Reads a session file saved for `instrument`, returning its book and next ID.
+fn restore_session(instrument: &Instrument, path: &str) -> Result<(OrderBook, u64), String> {
+    let json = std::fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read {}: {}", path, e))?;
+    let session: Session = serde_json::from_str(&json)
+        .map_err(|e| format!("Invalid session file {}: {}", path, e))?;
+    if session.book.instrument != *instrument {
+        return Err(format!("Session instrument {} does not match {}",
+            session.book.instrument, instrument));
+    }
+    Ok((OrderBook::restore(session.book), session.next_id))
+}
+
+/// Splits a CSV row into its side, price, quantity and order ID.
+fn parse_order_row(row: &str) -> Result<(Side, &str, &str, u64), String> {
+    let fields: Vec<&str> = row.split(',').map(str::trim).collect();
+    let [side, price, quantity, id] = fields[..] else {
+        return Err(format!("Expected 4 columns, found {}", fields.len()));
+    };
+    let side = Side::from_str(side, false).map_err(|_| format!("Invalid side: {}", side))?;
+    let id = id.parse().map_err(|_| format!("Invalid order ID: {}", id))?;
+    Ok((side, price, quantity, id))
+}
+
 fn print_book_state(book: &OrderBook) {
     println!("\n📊 Order Book State:");
 
@@ -400,18 +587,18 @@ fn print_best_prices(book: &OrderBook) {
     }
 }
 
+/// Prints up to `levels` levels per side with their cumulative quantity,
+/// followed by each side's total quantity.
 fn print_market_depth(book: &OrderBook, levels: usize) {
-    let buy_depth = book.depth(Side::Buy, levels);
-    let sell_depth = book.depth(Side::Sell, levels);
+    let buy_depth = book.format_depth_with(Side::Buy, levels, true);
+    let sell_depth = book.format_depth_with(Side::Sell, levels, true);
 
     if !sell_depth.is_empty() || !buy_depth.is_empty() {
         println!("  📈 Market Depth:");
 
         // Print sell side (asks) in reverse order (highest first)
-        for (price, qty) in sell_depth.iter().rev() {
-            let price_str = format_price(*price, &book.instrument.quote);
-            let qty_str = format_quantity(*qty, &book.instrument.base);
-            println!("    🔴 {} @ {}", qty_str, price_str);
+        for level in sell_depth.iter().rev() {
+            println!("    🔴 {}", level);
         }
 
         if !sell_depth.is_empty() && !buy_depth.is_empty() {
@@ -419,11 +606,13 @@ fn print_market_depth(book: &OrderBook, levels: usize) {
         }
 
         // Print buy side (bids) in normal order (highest first)
-        for (price, qty) in &buy_depth {
-            let price_str = format_price(*price, &book.instrument.quote);
-            let qty_str = format_quantity(*qty, &book.instrument.base);
-            println!("    🟢 {} @ {}", qty_str, price_str);
+        for level in &buy_depth {
+            println!("    🟢 {}", level);
         }
+
+        println!("  Total bids: {} | Total asks: {}",
+            format_quantity(book.total_quantity(Side::Buy), &book.instrument.base),
+            format_quantity(book.total_quantity(Side::Sell), &book.instrument.base));
     }
 }
 
@@ -467,7 +656,7 @@ mod tests {
     #[test]
     fn test_place_buy_order_no_match() {
         let mut cmd = get_cli_command();
-        cmd.args(&["place-order", "buy", "100", "10", "1"])
+        cmd.args(["place-order", "buy", "100", "10", "1"])
             .assert()
             .success()
             .stdout(predicate::str::contains("Order placed. No trades executed."));
@@ -476,7 +665,7 @@ mod tests {
     #[test]
     fn test_place_sell_order_no_match() {
         let mut cmd = get_cli_command();
-        cmd.args(&["place-order", "sell", "100", "10", "1"])
+        cmd.args(["place-order", "sell", "100", "10", "1"])
             .assert()
             .success()
             .stdout(predicate::str::contains("Order placed. No trades executed."));
@@ -504,13 +693,13 @@ mod tests {
     fn test_case_sensitive_side() {
         // Test that uppercase side values are rejected
         let mut cmd = get_cli_command();
-        cmd.args(&["place-order", "BUY", "100", "10", "1"])
+        cmd.args(["place-order", "BUY", "100", "10", "1"])
             .assert()
             .failure()
             .stderr(predicate::str::contains("invalid value"));
 
         let mut cmd = get_cli_command();
-        cmd.args(&["place-order", "SELL", "100", "10", "2"])
+        cmd.args(["place-order", "SELL", "100", "10", "2"])
             .assert()
             .failure()
             .stderr(predicate::str::contains("invalid value"));
@@ -519,7 +708,7 @@ mod tests {
     #[test]
     fn test_invalid_side() {
         let mut cmd = get_cli_command();
-        cmd.args(&["place-order", "invalid", "100", "10", "1"])
+        cmd.args(["place-order", "invalid", "100", "10", "1"])
             .assert()
             .failure()
             .stderr(predicate::str::contains("error"));
@@ -528,7 +717,7 @@ mod tests {
     #[test]
     fn test_invalid_price() {
         let mut cmd = get_cli_command();
-        cmd.args(&["place-order", "buy", "not_a_number", "10", "1"])
+        cmd.args(["place-order", "buy", "not_a_number", "10", "1"])
             .assert()
             .failure()
             .stderr(predicate::str::contains("Error placing order"));
@@ -537,7 +726,7 @@ mod tests {
     #[test]
     fn test_invalid_quantity() {
         let mut cmd = get_cli_command();
-        cmd.args(&["place-order", "buy", "100", "not_a_number", "1"])
+        cmd.args(["place-order", "buy", "100", "not_a_number", "1"])
             .assert()
             .failure()
             .stderr(predicate::str::contains("Error placing order"));
@@ -546,7 +735,7 @@ mod tests {
     #[test]
     fn test_invalid_id() {
         let mut cmd = get_cli_command();
-        cmd.args(&["place-order", "buy", "100", "10", "not_a_number"])
+        cmd.args(["place-order", "buy", "100", "10", "not_a_number"])
             .assert()
             .failure()
             .stderr(predicate::str::contains("error"));
@@ -555,7 +744,7 @@ mod tests {
     #[test]
     fn test_missing_arguments() {
         let mut cmd = get_cli_command();
-        cmd.args(&["place-order", "buy"])
+        cmd.args(["place-order", "buy"])
             .assert()
             .failure()
             .stderr(predicate::str::contains("error"));
@@ -604,7 +793,7 @@ mod tests {
     #[test]
     fn test_place_order_help() {
         let mut cmd = get_cli_command();
-        cmd.args(&["place-order", "--help"])
+        cmd.args(["place-order", "--help"])
             .assert()
             .success()
             .stdout(predicate::str::contains("Arguments:"))
@@ -617,7 +806,7 @@ mod tests {
     #[test]
     fn test_negative_price() {
         let mut cmd = get_cli_command();
-        cmd.args(&["place-order", "buy", "-100", "10", "1"])
+        cmd.args(["place-order", "buy", "-100", "10", "1"])
             .assert()
             .failure()
             .stderr(predicate::str::contains("error"));
@@ -626,7 +815,7 @@ mod tests {
     #[test]
     fn test_negative_quantity() {
         let mut cmd = get_cli_command();
-        cmd.args(&["place-order", "buy", "100", "-10", "1"])
+        cmd.args(["place-order", "buy", "100", "-10", "1"])
             .assert()
             .failure()
             .stderr(predicate::str::contains("error"));
@@ -635,7 +824,7 @@ mod tests {
     #[test]
     fn test_large_numbers() {
         let mut cmd = get_cli_command();
-        cmd.args(&["place-order", "buy", "1000000000", "1000000000", "1000000000"])
+        cmd.args(["place-order", "buy", "1000000000", "1000000000", "1000000000"])
             .assert()
             .success()
             .stdout(predicate::str::contains("Order placed. No trades executed."));
@@ -644,16 +833,154 @@ mod tests {
     #[test]
     fn test_zero_quantity() {
         let mut cmd = get_cli_command();
-        cmd.args(&["place-order", "buy", "100", "0", "1"])
+        cmd.args(["place-order", "buy", "100", "0", "1"])
             .assert()
             .failure()
             .stderr(predicate::str::contains("Error placing order"));
     }
 
+    #[test]
+    fn test_load_csv_places_orders() {
+        let path = std::env::temp_dir().join(format!("order-book-load-{}.csv", std::process::id()));
+        std::fs::write(
+            &path,
+            "side,price,quantity,id\nbuy,99.00,0.010,1\nsell,101.00,0.020,2\nbuy,oops,0.010,3\nsell,99.00,0.004,4\n",
+        )
+        .unwrap();
+
+        let mut cmd = get_cli_command();
+        cmd.args(["load", path.to_str().unwrap()])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Line 4: Invalid price format: oops (skipped)"))
+            .stdout(predicate::str::contains("Trade: 0.004 BTC @ 99 USDT (maker: 1, taker: 4)"))
+            .stdout(predicate::str::contains("Best: 0.006 BTC @ 99 USDT | 0.02 BTC @ 101 USDT"));
+
+        let mut cmd = get_cli_command();
+        cmd.args(["load", "--strict", path.to_str().unwrap()])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("Line 4: Invalid price format: oops"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_interactive_export_writes_json() {
+        let path = std::env::temp_dir().join(format!("order-book-export-{}.json", std::process::id()));
+        let input = format!(
+            "buy 99.50 0.010\nbuy 99.00 0.020\nsell 101.00 0.005\nexport {}\nquit\n",
+            path.display()
+        );
+
+        let mut cmd = get_cli_command();
+        cmd.write_stdin(input)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Book exported to"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(json["instrument"]["base"]["symbol"], "BTC");
+        // Best bid first, each order as {id, quantity, timestamp}
+        assert_eq!(json["buy_levels"][0]["price"], 9950);
+        assert_eq!(
+            json["buy_levels"][0]["orders"][0],
+            serde_json::json!({"id": 1, "quantity": 10000, "timestamp": 0})
+        );
+        assert_eq!(json["sell_levels"][0]["price"], 10100);
+    }
+
+    #[test]
+    fn test_export_subcommand_writes_loaded_session() {
+        let session = std::env::temp_dir()
+            .join(format!("order-book-export-session-{}.json", std::process::id()));
+        let export = std::env::temp_dir()
+            .join(format!("order-book-export-out-{}.json", std::process::id()));
+        let (session, export) = (session.to_str().unwrap(), export.to_str().unwrap());
+
+        let mut cmd = get_cli_command();
+        cmd.write_stdin(format!("buy 99.50 0.010\nsell 101.00 0.020\nsave {}\nquit\n", session))
+            .assert()
+            .success();
+
+        let mut cmd = get_cli_command();
+        cmd.args(["--load", session, "export", export])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Book exported to"));
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(export).unwrap()).unwrap();
+        std::fs::remove_file(session).unwrap();
+        std::fs::remove_file(export).unwrap();
+        assert_eq!(json["buy_levels"][0]["price"], 9950);
+        assert_eq!(json["sell_levels"][0]["orders"][0]["id"], 2);
+
+        // Without a session there is no book to export
+        let mut cmd = get_cli_command();
+        cmd.args(["export", export])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("pass --load <session>"));
+    }
+
+    #[test]
+    fn test_interactive_cancel_empties_book() {
+        let mut cmd = get_cli_command();
+        cmd.write_stdin("buy 100.00 0.010\ncancel 1\ncancel 1\nbest\nquit\n")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Order 1 cancelled."))
+            .stdout(predicate::str::contains("❌ Error:"))
+            .stdout(predicate::str::contains("📭 Order book is empty"));
+    }
+
+    #[test]
+    fn test_depth_shows_cumulative_column() {
+        let mut cmd = get_cli_command();
+        cmd.write_stdin("sell 101.00 0.005\nsell 102.00 0.010\nsell 103.00 0.020\ndepth\nquit\n")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("0.02 BTC @ 103 USDT (cumulative 0.035 BTC)"))
+            .stdout(predicate::str::contains("0.005 BTC @ 101 USDT (cumulative 0.005 BTC)"))
+            .stdout(predicate::str::contains("Total bids: 0 BTC | Total asks: 0.035 BTC"));
+    }
+
+    #[test]
+    fn test_save_and_load_session() {
+        let path = std::env::temp_dir()
+            .join(format!("order-book-session-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut cmd = get_cli_command();
+        cmd.write_stdin(format!("buy 99.50 0.010\nsell 101.00 0.020\nsave {}\nquit\n", path))
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Session saved"));
+
+        // Auto-generated IDs continue after the restored orders
+        let mut cmd = get_cli_command();
+        cmd.args(["--load", path])
+            .write_stdin("best\nbuy 98.00 0.001\nquit\n")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Best BUY:  0.01 BTC @ 99.50 USDT"))
+            .stdout(predicate::str::contains("Best SELL: 0.02 BTC @ 101 USDT"))
+            .stdout(predicate::str::contains("Order 3 placed"));
+
+        let mut cmd = get_cli_command();
+        cmd.args(["--base-asset", "ETH", "--load", path])
+            .write_stdin("quit\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("does not match"));
+        std::fs::remove_file(path).unwrap();
+    }
+
     #[test]
     fn test_zero_price() {
         let mut cmd = get_cli_command();
-        cmd.args(&["place-order", "buy", "0", "10", "1"])
+        cmd.args(["place-order", "buy", "0", "10", "1"])
             .assert()
             .success()
             .stdout(predicate::str::contains("Order placed. No trades executed."));
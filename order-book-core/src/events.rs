@@ -0,0 +1,18 @@
+use crate::types::{Id, Order, Trade};
+
+/// Receives a book's state changes as they happen, for streaming downstream.
+///
+/// Attach one with [`crate::OrderBook::with_event_sink`]. Every method has an
+/// empty default, so a sink implements only the events it cares about. Hooks
+/// run while the book is being mutated and must not call back into it.
+pub trait EventSink: Send + Sync {
+    /// An order passed validation and is about to be matched.
+    fn on_order_accepted(&mut self, _order: &Order) {}
+
+    /// A trade was executed, after the incoming order was accepted.
+    fn on_trade(&mut self, _trade: &Trade) {}
+
+    /// A resting order or pending stop was cancelled on request. Orders
+    /// leaving the book by expiry or `clear` are not reported.
+    fn on_order_cancelled(&mut self, _id: Id) {}
+}
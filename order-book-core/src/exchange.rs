@@ -0,0 +1,158 @@
+//! Routing of orders across the books of several instruments.
+
+use std::collections::HashMap;
+
+use crate::order_book::OrderBook;
+use crate::spread;
+use crate::types::{Id, Instrument, Order, OrderBookError, Price, Quantity, Side, Trades};
+
+/// A set of order books, one per instrument.
+///
+/// Books are created with default configuration on the first order for their
+/// instrument. Each book validates its own orders, so order IDs only need to
+/// be unique within an instrument.
+#[derive(Default)]
+pub struct Exchange {
+    books: HashMap<Instrument, OrderBook>,
+}
+
+impl Exchange {
+    /// Creates an exchange with no books.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Places a limit order on `instrument`'s book, creating the book if needed.
+    ///
+    /// See [`OrderBook::place_order`].
+    pub fn place_order(
+        &mut self,
+        instrument: &Instrument,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+        id: Id,
+    ) -> Result<Trades, OrderBookError> {
+        self.book_mut(instrument)
+            .place_order(side, price, quantity, id)
+    }
+
+    /// Cancels a resting order on `instrument`'s book.
+    ///
+    /// Returns `OrderBookError::UnknownOrderId` if the instrument has no book.
+    pub fn cancel_order(
+        &mut self,
+        instrument: &Instrument,
+        id: Id,
+    ) -> Result<Order, OrderBookError> {
+        match self.books.get_mut(instrument) {
+            Some(book) => book.cancel_order(id),
+            None => Err(OrderBookError::UnknownOrderId(id)),
+        }
+    }
+
+    /// Returns the best bid on `instrument`, if it has a book with bids.
+    pub fn best_buy(&self, instrument: &Instrument) -> Option<(Price, Quantity)> {
+        self.book(instrument)?.best_buy()
+    }
+
+    /// Returns the best ask on `instrument`, if it has a book with asks.
+    pub fn best_sell(&self, instrument: &Instrument) -> Option<(Price, Quantity)> {
+        self.book(instrument)?.best_sell()
+    }
+
+    /// Returns the signed best bid and ask of the synthetic spread `leg_a - leg_b`.
+    ///
+    /// See [`spread::synthetic_bbo`]. Also `None` if either leg has no book.
+    pub fn synthetic_bbo(&self, leg_a: &Instrument, leg_b: &Instrument) -> Option<(i128, i128)> {
+        spread::synthetic_bbo(self.book(leg_a)?, self.book(leg_b)?)
+    }
+
+    /// Returns `instrument`'s book, if an order has been placed on it.
+    pub fn book(&self, instrument: &Instrument) -> Option<&OrderBook> {
+        self.books.get(instrument)
+    }
+
+    /// Returns `instrument`'s book, creating an empty one if needed.
+    pub fn book_mut(&mut self, instrument: &Instrument) -> &mut OrderBook {
+        self.books
+            .entry(instrument.clone())
+            .or_insert_with(|| OrderBook::new(instrument.clone()))
+    }
+
+    /// Returns the instruments that have a book, sorted by symbol pair.
+    pub fn list_instruments(&self) -> Vec<Instrument> {
+        let mut instruments: Vec<_> = self.books.keys().cloned().collect();
+        instruments.sort_by_key(|instrument| instrument.to_string());
+        instruments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{price, quantity, std_instrument};
+    use crate::types::Asset;
+
+    #[test]
+    fn test_orders_stay_on_their_instrument() {
+        let btc = std_instrument();
+        let eth = Instrument::new(Asset::new("ETH", 6), Asset::new("USDT", 2));
+        let mut exchange = Exchange::new();
+        assert!(exchange.list_instruments().is_empty());
+
+        exchange
+            .place_order(&btc, Side::Sell, price("100.00"), quantity("0.010"), 1)
+            .unwrap();
+        // The same ID is free on another instrument
+        exchange
+            .place_order(&eth, Side::Buy, price("100.00"), quantity("0.020"), 1)
+            .unwrap();
+        assert_eq!(
+            exchange.place_order(&btc, Side::Buy, price("99.00"), quantity("0.010"), 1),
+            Err(OrderBookError::DuplicateOrderId(1))
+        );
+
+        assert_eq!(
+            exchange.best_sell(&btc),
+            Some((price("100.00"), quantity("0.010")))
+        );
+        assert_eq!(exchange.best_buy(&btc), None);
+        assert_eq!(
+            exchange.best_buy(&eth),
+            Some((price("100.00"), quantity("0.020")))
+        );
+        assert_eq!(exchange.best_sell(&eth), None);
+        assert_eq!(exchange.list_instruments(), [btc.clone(), eth.clone()]);
+
+        exchange.cancel_order(&eth, 1).unwrap();
+        assert_eq!(exchange.best_buy(&eth), None);
+        assert!(exchange.book(&btc).unwrap().contains_order(1));
+    }
+
+    #[test]
+    fn test_synthetic_bbo_across_books() {
+        let front = std_instrument();
+        let back = Instrument::new(Asset::new("BTC-DEC", 6), Asset::new("USDT", 2));
+        let mut exchange = Exchange::new();
+        let size = quantity("0.010");
+        exchange
+            .place_order(&front, Side::Buy, price("101.00"), size, 1)
+            .unwrap();
+        exchange
+            .place_order(&front, Side::Sell, price("102.00"), size, 2)
+            .unwrap();
+        // No book for the back leg yet
+        assert_eq!(exchange.synthetic_bbo(&front, &back), None);
+
+        exchange
+            .place_order(&back, Side::Buy, price("103.00"), size, 1)
+            .unwrap();
+        exchange
+            .place_order(&back, Side::Sell, price("104.00"), size, 2)
+            .unwrap();
+        // A backwardated calendar spread quotes below zero rather than vanishing
+        let (bid, ask) = (price("3.00") as i128, price("1.00") as i128);
+        assert_eq!(exchange.synthetic_bbo(&front, &back), Some((-bid, -ask)));
+    }
+}
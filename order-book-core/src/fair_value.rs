@@ -0,0 +1,114 @@
+use crate::order_book::OrderBook;
+use crate::types::Side;
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
+
+/// Estimates the fair value of a book, in quote minor units.
+///
+/// Select one with [`OrderBook::set_fair_value_estimator`] and query it
+/// through [`OrderBook::fair_value`]. Any `Fn(&OrderBook) -> Option<Decimal>`
+/// closure is an estimator too.
+pub trait FairValueEstimator: Send + Sync {
+    /// Returns the estimate, or `None` if the book cannot support one.
+    fn estimate(&self, book: &OrderBook) -> Option<Decimal>;
+}
+
+impl<F> FairValueEstimator for F
+where
+    F: Fn(&OrderBook) -> Option<Decimal> + Send + Sync,
+{
+    fn estimate(&self, book: &OrderBook) -> Option<Decimal> {
+        self(book)
+    }
+}
+
+/// Exact midpoint of the best buy and best sell prices (the default).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Mid;
+
+impl FairValueEstimator for Mid {
+    fn estimate(&self, book: &OrderBook) -> Option<Decimal> {
+        let (bid, _) = book.best_buy()?;
+        let (ask, _) = book.best_sell()?;
+        Some((Decimal::from_u128(bid)? + Decimal::from_u128(ask)?) / Decimal::TWO)
+    }
+}
+
+/// Best prices weighted by the quantity resting on the opposite side.
+///
+/// Leans towards the side that is more likely to be taken out next: a large
+/// bid against a small ask pulls the estimate towards the ask.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Microprice;
+
+impl FairValueEstimator for Microprice {
+    fn estimate(&self, book: &OrderBook) -> Option<Decimal> {
+        let (bid, bid_quantity) = book.best_buy()?;
+        let (ask, ask_quantity) = book.best_sell()?;
+        let weighted = bid
+            .checked_mul(ask_quantity)?
+            .checked_add(ask.checked_mul(bid_quantity)?)?;
+        let total_quantity = bid_quantity.checked_add(ask_quantity)?;
+        Decimal::from_u128(weighted)?.checked_div(Decimal::from_u128(total_quantity)?)
+    }
+}
+
+/// Average of each side's quantity-weighted price over its top `levels`.
+#[derive(Debug, Clone, Copy)]
+pub struct WeightedPrice {
+    /// Number of levels per side to include
+    pub levels: usize,
+}
+
+impl FairValueEstimator for WeightedPrice {
+    fn estimate(&self, book: &OrderBook) -> Option<Decimal> {
+        let bid = book.weighted_price(Side::Buy, self.levels)?;
+        let ask = book.weighted_price(Side::Sell, self.levels)?;
+        Some((bid + ask) / Decimal::TWO)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{book, new_book, price};
+    use crate::types::{Quantity, Side};
+
+    #[test]
+    fn test_switching_estimator_changes_fair_value() {
+        let mut book = book! {
+            buy 99.00 x 0.010 @id 1;
+            sell 101.00 x 0.030 @id 2;
+            sell 102.00 x 0.010 @id 3;
+        };
+        assert_eq!(book.fair_value(), Some(Decimal::from(price("100.00"))));
+
+        book.set_fair_value_estimator(Microprice);
+        assert_eq!(book.fair_value(), Some(Decimal::from(price("99.50"))));
+
+        // Asks: (0.030 @ 101.00 + 0.010 @ 102.00) / 0.040 = 101.25
+        book.set_fair_value_estimator(WeightedPrice { levels: 2 });
+        assert_eq!(book.fair_value(), Some(Decimal::new(100125, 1)));
+
+        book.set_fair_value_estimator(|book: &OrderBook| {
+            book.best_sell().map(|(ask, _)| Decimal::from(ask))
+        });
+        assert_eq!(book.fair_value(), Some(Decimal::from(price("101.00"))));
+
+        book.cancel_order(1).unwrap();
+        book.set_fair_value_estimator(Mid);
+        assert_eq!(book.fair_value(), None);
+    }
+
+    #[test]
+    fn test_microprice_is_none_when_quantities_overflow() {
+        let mut book = new_book();
+        // Zero prices keep the weighted sum small while the quantities do not
+        let huge: Quantity = (u128::MAX / 2 / 1_000 + 1) * 1_000;
+        book.place_order(Side::Buy, 0, huge, 1).unwrap();
+        book.place_order(Side::Sell, price("0.01"), huge, 2)
+            .unwrap();
+        book.set_fair_value_estimator(Microprice);
+        assert_eq!(book.fair_value(), None);
+    }
+}
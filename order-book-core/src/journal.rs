@@ -0,0 +1,99 @@
+use crate::types::{
+    BookConfig, Id, MarketPhase, Order, Price, Quantity, StopOrder, Timestamp, Trade,
+};
+
+/// The entry point an accepted order went through, so a replay takes the
+/// same path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderKind {
+    /// A limit order, resting any remainder
+    Limit,
+    /// A market order, its price being the protective limit
+    Market,
+    /// An order for the midpoint pool
+    Midpoint,
+}
+
+/// One recorded event in a [`TradeJournal`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JournalEntry {
+    /// The book's configuration, recorded before the first event and again
+    /// before the first event after it changes
+    Configured(BookConfig),
+    /// An order passed validation, recorded as submitted before matching
+    Accepted { order: Order, kind: OrderKind },
+    /// A trade was executed, with its final ID, sequence and timestamp
+    Trade(Trade),
+    /// A resting order or pending stop was cancelled on request
+    Cancelled(Id),
+    /// A stop-limit order was accepted and awaits its trigger
+    StopPlaced(StopOrder),
+    /// A resting order's quantity was amended
+    QuantityAmended { id: Id, quantity: Quantity },
+    /// A resting order was repriced; its resubmission is recorded next
+    PriceAmended { id: Id, price: Price },
+    /// The session phase was changed
+    PhaseChanged(MarketPhase),
+    /// A crossed book was uncrossed in a single-price auction
+    Uncrossed,
+    /// Good-till-date orders were expired against an outside clock
+    Expired(Timestamp),
+    /// Every resting order and pending stop was removed
+    Cleared,
+}
+
+/// Append-only log of everything that changed a book, in the order it
+/// happened.
+///
+/// Enable it with [`crate::OrderBook::with_journal`] on a fresh book and
+/// rebuild the book with [`crate::OrderBook::replay`]. Besides the events
+/// reported to [`crate::EventSink`], it records the book's configuration,
+/// amendments, stop placements, phase changes, auctions, explicit
+/// `expire_orders` calls and clears. Expiries the book performs on its own
+/// follow from those and are not recorded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TradeJournal {
+    entries: Vec<JournalEntry>,
+    /// Configuration most recently recorded
+    config: Option<BookConfig>,
+}
+
+impl TradeJournal {
+    /// Creates an empty journal.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `entry`, preceded by `config` if it differs from the
+    /// configuration last recorded.
+    pub(crate) fn record(&mut self, config: BookConfig, entry: JournalEntry) {
+        if self.config != Some(config) {
+            self.config = Some(config);
+            self.entries.push(JournalEntry::Configured(config));
+        }
+        self.entries.push(entry);
+    }
+
+    /// Returns every entry, oldest first.
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    /// Returns the journaled trades in execution order.
+    pub fn trades(&self) -> impl Iterator<Item = &Trade> + '_ {
+        self.entries.iter().filter_map(|entry| match entry {
+            JournalEntry::Trade(trade) => Some(trade),
+            _ => None,
+        })
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if nothing has been journaled.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
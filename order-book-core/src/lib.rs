@@ -28,15 +28,39 @@
 //! ```
 
 mod units;
+pub mod events;
+pub mod exchange;
+pub mod export;
+pub mod fair_value;
+pub mod journal;
 pub mod order_book;
+pub mod replay;
+#[cfg(feature = "scenario")]
+pub mod scenario;
+pub mod spread;
 #[cfg(test)]
 pub(crate) mod test_support;
 pub mod types;
+pub use events::EventSink;
+pub use exchange::Exchange;
+pub use export::write_trades_csv;
+pub use fair_value::FairValueEstimator;
+pub use journal::{JournalEntry, OrderKind, TradeJournal};
 pub use order_book::OrderBook;
-pub use types::{Order, OrderBookError, Side, Trade, Trades};
+pub use replay::{replay, replay_steps, DepthSnapshot};
+pub use spread::synthetic_bbo;
+pub use types::{
+    fills_by_taker, AlignmentPolicy, AuditRecord, BatchPreview, BboSample, BookCommand, BookConfig,
+    BookDump, BookSnapshot, BookStats, DesignatedMaker, FeeSchedule, L2Update, MarketPhase,
+    MatchingPolicy, OhlcAccumulator, Order, OrderBookError, PlaceResult, PricePolicy, RandomPeak,
+    Side, StopOrder, TimeInForce, Trade, Trades,
+};
+#[cfg(feature = "match-metrics")]
+pub use types::MatchMetrics;
 pub use units::{
-    format_price, format_quantity, price_from_minor_units, price_to_minor_units,
-    quantity_from_minor_units, quantity_to_minor_units,
+    format_price, format_quantity, notional, price_from_minor_units, price_to_minor_units,
+    price_to_minor_units_with, quantity_from_minor_units, quantity_to_minor_units,
+    quantity_to_minor_units_with, snap_to_tick, snap_to_tick_with, RoundingMode,
 };
 
 #[cfg(test)]
@@ -1,8 +1,25 @@
+use crate::events::EventSink;
+use crate::fair_value::{FairValueEstimator, Mid};
+use crate::journal::{JournalEntry, OrderKind, TradeJournal};
 use crate::types::{
-    Id, Instrument, Order, OrderBookError, Price, PriceAndQuantity, PriceLevel, Quantity, Side,
-    Timestamp, Trade, Trades,
+    AccountId, AlignmentPolicy, AuditRecord, BatchPreview, BboSample, BookCommand, BookConfig,
+    BookDump, BookSnapshot, BookStats, DesignatedMaker, FeeSchedule, Id, Instrument, L2Update,
+    LevelDump, MarketPhase, MatchingPolicy, OhlcAccumulator, Order, OrderBookError, OrderDump,
+    PlaceResult, Price, PriceAndQuantity, PriceLevel, PricePolicy, Quantity, RandomPeak, Side,
+    StopOrder, TimeInForce, Timestamp, Trade, Trades,
 };
-use std::collections::{BTreeMap, HashSet};
+use crate::units::{
+    format_price, format_quantity, mul_div_floor, notional, scale_notional, snap_to_tick,
+    snap_to_tick_with, RoundingMode,
+};
+#[cfg(feature = "match-metrics")]
+use crate::types::MatchMetrics;
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::Decimal;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use validator::Validate;
 
 /// Result of matching against a price level, indicating what cache updates are needed.
 #[derive(Debug, PartialEq)]
@@ -17,11 +34,80 @@ enum LevelMatchResult {
     EmptyBestLevel,
 }
 
+/// Counts the resting orders examined while matching, for `MatchMetrics`.
+///
+/// Zero-sized without the `match-metrics` feature, so counting is free.
+#[derive(Debug, Default, Clone, Copy)]
+struct VisitCounter {
+    #[cfg(feature = "match-metrics")]
+    count: u64,
+}
+
+impl VisitCounter {
+    /// Records `_visits` more examined orders.
+    #[inline]
+    fn add(&mut self, _visits: u64) {
+        #[cfg(feature = "match-metrics")]
+        {
+            self.count += _visits;
+        }
+    }
+}
+
+/// State the matchers share while an incoming order walks the book.
+struct MatchContext<'a> {
+    /// Trades produced so far
+    trades: &'a mut Vec<Trade>,
+    /// Trades after which matching stops
+    max_fills: usize,
+    id_index: &'a mut HashMap<Id, (Side, Price)>,
+    /// Incoming and resting orders captured just before each fill, if auditing
+    audit: Option<&'a mut Vec<(Order, Order)>>,
+    /// Lot size partial fills are trimmed to
+    lot: Quantity,
+    orders_visited: &'a mut VisitCounter,
+    /// Source of timestamps for replenished icebergs
+    clock: &'a mut Timestamp,
+}
+
+/// Callback invoked with the error and order parameters of a rejected placement.
+type RejectCallback = Box<dyn FnMut(&OrderBookError, Side, Price, Quantity, Id) + Send + Sync>;
+
+/// Callback invoked with the side and price of a level that appeared or disappeared.
+type LevelCallback = Box<dyn FnMut(Side, Price) + Send + Sync>;
+
+/// Callback invoked after the book has been cleared.
+type ClearedCallback = Box<dyn FnMut() + Send + Sync>;
+
+/// Callback invoked with every trade the book executes.
+type TradeCallback = Box<dyn FnMut(&Trade) + Send + Sync>;
+
+/// Callback invoked with an order's ID and cumulative filled quantity at its fill milestone.
+type MilestoneCallback = Box<dyn FnMut(Id, Quantity) + Send + Sync>;
+
+/// Callback invoked with the new top-of-book depth after it changes.
+type L2Callback = Box<dyn FnMut(&L2Update) + Send + Sync>;
+
+/// Pre-trade check given a proposed order and its account's net position.
+///
+/// Shared rather than boxed so detached copies of the book run it too.
+type RiskCheck = Arc<dyn Fn(&Order, i128) -> Result<(), OrderBookError> + Send + Sync>;
+
 /// A limit order book that maintains buy and sell orders.
 ///
 /// Orders are organized by price level, with price-time priority for matching.
 /// Buy orders (bids) are sorted in descending price order, sell orders (asks)
 /// in ascending price order.
+///
+/// # Concurrency
+///
+/// `OrderBook` is `Send + Sync` and is meant to be shared behind a
+/// `std::sync::RwLock`. Every mutation takes `&mut self` and finishes its
+/// bookkeeping (price levels, ID index and the cached best prices) before it
+/// returns, so a reader holding the read lock always sees the cached
+/// `best_buy`/`best_sell` agree with the price levels, and `depth` and
+/// `mid_price` are derived from one consistent state. Callbacks run while the
+/// write lock is held and must not try to lock the book again.
 pub struct OrderBook {
     /// Instrument being traded
     pub instrument: Instrument,
@@ -31,12 +117,135 @@ pub struct OrderBook {
     sell_side: BTreeMap<Price, PriceLevel>,
     /// Counter for generating order timestamps
     next_timestamp: Timestamp,
-    /// Set of order IDs currently resting in the book
-    id_index: HashSet<Id>,
+    /// Side and price level of every order resting in the lit book, by ID
+    id_index: HashMap<Id, (Side, Price)>,
     /// Cached best buy price and quantity
     best_buy: Option<PriceAndQuantity>,
     /// Cached best sell price and quantity
     best_sell: Option<PriceAndQuantity>,
+    /// Optional absolute upper bound on order prices
+    max_price: Option<Price>,
+    /// Optional absolute upper bound on order quantities
+    max_quantity: Option<Quantity>,
+    /// Optional limit, in ticks, on how far a resting order may sit from
+    /// the best price on its own side
+    max_price_gap_ticks: Option<u128>,
+    /// Callback fired whenever `place_order` rejects an order
+    on_reject: Option<RejectCallback>,
+    /// Callback fired when a new price level is created
+    on_level_added: Option<LevelCallback>,
+    /// Callback fired when a price level is removed
+    on_level_removed: Option<LevelCallback>,
+    /// Callback fired after `clear` empties the book
+    on_cleared: Option<ClearedCallback>,
+    /// Cumulative activity counters
+    stats: BookStats,
+    /// Most recent trades, oldest first, bounded by `trade_history_capacity`
+    trade_history: VecDeque<Trade>,
+    /// Maximum number of trades retained in `trade_history` (0 disables it)
+    trade_history_capacity: usize,
+    /// Minimum logical time an order must rest before it can be cancelled
+    min_resting_duration: Option<Timestamp>,
+    /// Resting midpoint buy orders (FIFO), kept apart from the lit book
+    midpoint_buys: VecDeque<Order>,
+    /// Resting midpoint sell orders (FIFO), kept apart from the lit book
+    midpoint_sells: VecDeque<Order>,
+    /// IDs of orders resting in the midpoint pools
+    midpoint_ids: HashSet<Id>,
+    /// Count of book operations (accepted placements and cancels)
+    operation_count: u64,
+    /// Number of operations after which a resting order expires
+    expire_after_ops: Option<u64>,
+    /// Resting orders awaiting operation-count expiry, in placement order
+    expiry_queue: VecDeque<PendingExpiry>,
+    /// `placed_at_op` of the latest `expiry_queue` entry for each ID; older
+    /// entries for the ID are stale
+    latest_expiry: HashMap<Id, u64>,
+    /// Current trading session phase
+    phase: MarketPhase,
+    /// Trades of recent taker orders, keyed by taker ID
+    taker_trades: HashMap<Id, Trades>,
+    /// Taker IDs in `taker_trades`, oldest first, for eviction
+    taker_trade_ids: VecDeque<Id>,
+    /// Maximum number of takers retained in `taker_trades` (0 disables it)
+    taker_trade_capacity: usize,
+    /// Best-price changes, oldest first, bounded by `bbo_history_capacity`
+    bbo_history: VecDeque<BboSample>,
+    /// Maximum number of samples retained in `bbo_history` (0 disables it)
+    bbo_history_capacity: usize,
+    /// Optional cap on the number of trades a single placement may produce
+    max_fills_per_order: Option<usize>,
+    /// Callback fired when the top `l2_levels` of either side change
+    on_l2_update: Option<L2Callback>,
+    /// Number of levels per side covered by L2 updates
+    l2_levels: usize,
+    /// Callback fired for every executed trade
+    on_trade: Option<TradeCallback>,
+    /// Check run on every proposed priced, market or midpoint order
+    risk_check: Option<RiskCheck>,
+    /// Net traded base quantity of every account that has traded
+    positions: HashMap<AccountId, i128>,
+    /// Callback fired when an order's fills first reach its milestone
+    on_fill_milestone: Option<MilestoneCallback>,
+    /// Armed fill milestones, by order ID
+    fill_milestones: HashMap<Id, FillMilestone>,
+    /// Optional lower bound on order notional, in quote minor units
+    min_notional: Option<Price>,
+    /// Optional upper bound on order notional, in quote minor units
+    max_notional: Option<Price>,
+    /// Treatment of prices and quantities off the tick and lot grid
+    alignment_policy: AlignmentPolicy,
+    /// How fills are shared among the orders at a price level
+    matching_policy: MatchingPolicy,
+    /// Account given priority allocations at each level under price-time
+    designated_maker: Option<DesignatedMaker>,
+    /// How the mid is rounded onto the tick grid for midpoint pool trades
+    midpoint_rounding: RoundingMode,
+    /// Fee rates applied to trades, if any
+    fees: Option<FeeSchedule>,
+    /// Price of the most recent trade
+    last_trade_price: Option<Price>,
+    /// OHLC and volume of trades since the last reset
+    ohlc: OhlcAccumulator,
+    /// Audit records of recent trades, oldest first, bounded by `audit_capacity`
+    audit_trail: VecDeque<AuditRecord>,
+    /// Maximum number of records retained in `audit_trail` (0 disables auditing)
+    audit_capacity: usize,
+    /// Incoming and resting order snapshots awaiting their trades in `record_trades`
+    pending_audit: Vec<(Order, Order, PricePolicy)>,
+    /// Observer receiving order, trade and cancel events
+    event_sink: Option<Box<dyn EventSink>>,
+    /// Log of accepted orders, trades and cancels, if journaling is enabled
+    journal: Option<TradeJournal>,
+    /// Estimator behind `fair_value`
+    fair_value_estimator: Box<dyn FairValueEstimator>,
+    /// Expiry and ID of resting good-till-date orders, soonest first
+    gtd_expiries: BTreeSet<(Timestamp, Id)>,
+    /// Stop-limit orders awaiting their trigger, in placement order
+    pending_stops: Vec<StopOrder>,
+    /// Buy depth last published as an L2 update
+    l2_bids: Vec<PriceAndQuantity>,
+    /// Sell depth last published as an L2 update
+    l2_asks: Vec<PriceAndQuantity>,
+}
+
+/// A resting order tracked for expiry by operation count.
+#[derive(Debug, Clone, Copy)]
+struct PendingExpiry {
+    /// Operation count right after the order was placed
+    placed_at_op: u64,
+    id: Id,
+    side: Side,
+    price: Price,
+}
+
+/// Fill progress of an order placed with a fill notification fraction.
+#[derive(Debug, Clone, Copy)]
+struct FillMilestone {
+    /// Cumulative filled quantity at which the milestone fires
+    threshold: Quantity,
+    /// Quantity filled so far
+    filled: Quantity,
 }
 
 impl OrderBook {
@@ -48,325 +257,6504 @@ impl OrderBook {
             buy_side: BTreeMap::new(),
             sell_side: BTreeMap::new(),
             next_timestamp: 0,
-            id_index: HashSet::new(),
+            id_index: HashMap::new(),
             best_buy: None,
             best_sell: None,
+            max_price: None,
+            max_quantity: None,
+            max_price_gap_ticks: None,
+            on_reject: None,
+            on_level_added: None,
+            on_level_removed: None,
+            on_cleared: None,
+            stats: BookStats::default(),
+            trade_history: VecDeque::new(),
+            trade_history_capacity: 0,
+            min_resting_duration: None,
+            midpoint_buys: VecDeque::new(),
+            midpoint_sells: VecDeque::new(),
+            midpoint_ids: HashSet::new(),
+            operation_count: 0,
+            expire_after_ops: None,
+            expiry_queue: VecDeque::new(),
+            latest_expiry: HashMap::new(),
+            phase: MarketPhase::default(),
+            taker_trades: HashMap::new(),
+            taker_trade_ids: VecDeque::new(),
+            taker_trade_capacity: 0,
+            bbo_history: VecDeque::new(),
+            bbo_history_capacity: 0,
+            max_fills_per_order: None,
+            on_l2_update: None,
+            l2_levels: 0,
+            l2_bids: Vec::new(),
+            l2_asks: Vec::new(),
+            on_trade: None,
+            risk_check: None,
+            positions: HashMap::new(),
+            on_fill_milestone: None,
+            fill_milestones: HashMap::new(),
+            min_notional: None,
+            max_notional: None,
+            alignment_policy: AlignmentPolicy::default(),
+            matching_policy: MatchingPolicy::default(),
+            designated_maker: None,
+            midpoint_rounding: RoundingMode::default(),
+            fees: None,
+            last_trade_price: None,
+            ohlc: OhlcAccumulator::default(),
+            audit_trail: VecDeque::new(),
+            audit_capacity: 0,
+            pending_audit: Vec::new(),
+            event_sink: None,
+            journal: None,
+            fair_value_estimator: Box::new(Mid),
+            gtd_expiries: BTreeSet::new(),
+            pending_stops: Vec::new(),
         }
     }
 
-    /// Places an order in the book and returns any resulting trades.
-    ///
-    /// The order will first attempt to match against existing orders on the
-    /// opposite side. Any remaining quantity will be added to the book.
-    ///
-    /// # Arguments
-    ///
-    /// * `side` - Whether this is a buy or sell order
-    /// * `price` - Price per unit
-    /// * `quantity` - Number of units to trade
-    /// * `id` - Unique identifier for the order
+    /// Sets an absolute cap on order prices.
     ///
-    /// # Returns
+    /// Orders priced above `max_price` are rejected with
+    /// `OrderBookError::ValueTooLarge` before any matching takes place. This is
+    /// a sanity guard against fat-fingered input, not a trading limit.
+    pub fn with_max_price(mut self, max_price: Price) -> Self {
+        self.max_price = Some(max_price);
+        self
+    }
+
+    /// Sets an absolute cap on order quantities.
     ///
-    /// A vector of trades that occurred as a result of this order
-    pub fn place_order(
-        &mut self,
-        side: Side,
-        price: Price,
-        quantity: Quantity,
-        id: Id,
-    ) -> Result<Trades, OrderBookError> {
-        if self.id_index.contains(&id) {
-            return Err(OrderBookError::DuplicateOrderId(id));
-        }
-        if quantity == 0 {
-            return Err(OrderBookError::ZeroQuantity { id, quantity });
-        }
+    /// Orders larger than `max_quantity` are rejected with
+    /// `OrderBookError::ValueTooLarge` before any matching takes place.
+    pub fn with_max_quantity(mut self, max_quantity: Quantity) -> Self {
+        self.max_quantity = Some(max_quantity);
+        self
+    }
 
-        let timestamp = self.next_timestamp;
-        self.next_timestamp += 1;
+    /// Rejects orders that would rest more than `ticks` ticks away from the
+    /// best price on their own side.
+    ///
+    /// Unlike a price band around a reference, the gap is measured from the
+    /// same-side best, catching quotes keyed far from where the book trades.
+    /// Such orders are rejected with `OrderBookError::PriceGapTooLarge`.
+    /// Marketable orders and orders on an empty side are not checked.
+    pub fn with_max_price_gap(mut self, ticks: u128) -> Self {
+        self.max_price_gap_ticks = Some(ticks);
+        self
+    }
 
-        let mut incoming_order = Order::new(id, side, price, quantity, timestamp);
+    /// Bounds the notional value of limit orders, inclusive at both ends.
+    ///
+    /// The notional is `price * quantity` in quote minor units, truncated,
+    /// computed in u128 before any matching takes place, so an order outside
+    /// the bounds is rejected with `OrderBookError::BelowMinNotional` or
+    /// `OrderBookError::AboveMaxNotional` without partially executing.
+    /// Market and midpoint orders carry no limit price and are not checked.
+    pub fn with_notional_bounds(mut self, min: Price, max: Price) -> Self {
+        self.min_notional = Some(min);
+        self.max_notional = Some(max);
+        self
+    }
 
-        let trades = self.match_incoming_order(&mut incoming_order);
+    /// Retains up to `capacity` of the most recent trades.
+    ///
+    /// Once full, the oldest trade is dropped for each new one. History is
+    /// disabled by default.
+    pub fn with_trade_history(mut self, capacity: usize) -> Self {
+        self.trade_history_capacity = capacity;
+        self.trade_history = VecDeque::with_capacity(capacity);
+        self
+    }
 
-        if incoming_order.quantity > 0 {
-            self.add_order_to_book(incoming_order);
-            self.id_index.insert(id);
-        }
+    /// Streams the book's state changes to `sink`.
+    ///
+    /// Every accepted placement reports `on_order_accepted` before it is
+    /// matched, then `on_trade` for each resulting trade in execution order;
+    /// cancels report `on_order_cancelled`. Without a sink, none of this work
+    /// is done.
+    pub fn with_event_sink(mut self, sink: impl EventSink + 'static) -> Self {
+        self.event_sink = Some(Box::new(sink));
+        self
+    }
 
-        Ok(trades)
+    /// Journals every change to the book for [`OrderBook::replay`].
+    ///
+    /// Accepted orders, trades and cancels are recorded along with the
+    /// book's configuration and every other mutation, see [`TradeJournal`].
+    /// Enable it before the first order so a replay starts from the same
+    /// empty book. The journal grows without bound; read it with
+    /// [`OrderBook::journal`]. Disabled by default.
+    pub fn with_journal(mut self) -> Self {
+        self.journal = Some(TradeJournal::new());
+        self
     }
 
-    /// Returns the best (highest) buy price and total quantity at that level.
+    /// Records an audit trail of up to `capacity` of the most recent trades.
     ///
-    /// # Returns
+    /// Each trade gets an [`AuditRecord`] holding both orders as they stood
+    /// just before the fill and the rule that set the price, for
+    /// reconstructing every fill from its inputs. This copies both orders per
+    /// trade, so it is heavier than [`OrderBook::with_trade_history`]. Once
+    /// full, the oldest record is dropped. Disabled by default.
+    pub fn with_audit_trail(mut self, capacity: usize) -> Self {
+        self.audit_capacity = capacity;
+        self.audit_trail = VecDeque::with_capacity(capacity);
+        self
+    }
+
+    /// Indexes the trades of up to `capacity` of the most recent taker orders.
     ///
-    /// `Some(PriceAndQuantity)` if buy orders exist, `None` otherwise
-    pub fn best_buy(&self) -> Option<PriceAndQuantity> {
-        self.best_buy
+    /// Enables [`OrderBook::trades_for_order`]. Once `capacity` takers are
+    /// held, the oldest taker's trades are dropped. Disabled by default.
+    pub fn with_taker_trade_index(mut self, capacity: usize) -> Self {
+        self.taker_trade_capacity = capacity;
+        self
     }
 
-    /// Returns the best (lowest) sell price and total quantity at that level.
+    /// Retains up to `capacity` of the most recent best-price changes.
     ///
-    /// # Returns
+    /// A sample is taken whenever a mutation changes the best buy or best
+    /// sell price. Once full, the oldest sample is dropped. Disabled by
+    /// default.
+    pub fn with_bbo_history(mut self, capacity: usize) -> Self {
+        self.bbo_history_capacity = capacity;
+        self.bbo_history = VecDeque::with_capacity(capacity);
+        self
+    }
+
+    /// Caps the number of trades a single placement may produce.
     ///
-    /// `Some(PriceAndQuantity)` if sell orders exist, `None` otherwise
-    pub fn best_sell(&self) -> Option<PriceAndQuantity> {
-        self.best_sell
+    /// Matching stops cleanly once an incoming order has `max_fills` trades,
+    /// leaving the levels it did not reach untouched. If the order could still
+    /// trade at that point its remainder is discarded rather than rested, as
+    /// resting it would cross the book; [`OrderBook::place_order_detailed`]
+    /// reports this as a truncated placement. A cap of zero is treated as one.
+    pub fn with_max_fills_per_order(mut self, max_fills: usize) -> Self {
+        self.max_fills_per_order = Some(max_fills.max(1));
+        self
     }
 
-    /// Returns market depth information for the specified side.
+    /// Sets how orders off the instrument's tick and lot grid are treated.
     ///
-    /// For buy side, returns prices in descending order (best first).
-    /// For sell side, returns prices in ascending order (best first).
+    /// Under `AlignmentPolicy::Reject` a price that is not a multiple of
+    /// `tick_size`, or a quantity that is not a multiple of `lot_size`, is
+    /// rejected with `OrderBookError::Misaligned`. Under
+    /// `AlignmentPolicy::Round` the price snaps to the nearest tick (halves
+    /// round up) and the quantity rounds down to a whole lot; a quantity
+    /// below one lot is then rejected as zero.
+    pub fn with_alignment_policy(mut self, policy: AlignmentPolicy) -> Self {
+        self.alignment_policy = policy;
+        self
+    }
+
+    /// Sets how an incoming order's fill is shared among the orders resting
+    /// at each price level it reaches.
     ///
-    /// # Arguments
+    /// Under `MatchingPolicy::ProRata` each order at a level gets a share
+    /// proportional to its visible quantity, rounded down to whole lots;
+    /// lots left over by the rounding go one at a time to the oldest orders
+    /// first. Uncrossing on [`OrderBook::set_phase`] and
+    /// [`OrderBook::uncross`] stays price-time. Defaults to
+    /// `MatchingPolicy::PriceTime`.
+    pub fn with_matching_policy(mut self, policy: MatchingPolicy) -> Self {
+        self.matching_policy = policy;
+        self
+    }
+
+    /// Grants `account` a designated market maker allocation at every price
+    /// level, ahead of time priority.
     ///
-    /// * `side` - Which side of the book to query
-    /// * `levels` - Maximum number of price levels to return
+    /// The allocation rule, applied at each level an incoming order trades
+    /// at under `MatchingPolicy::PriceTime`:
     ///
-    /// # Returns
+    /// 1. The level quantity is the lesser of the incoming order's remaining
+    ///    quantity and the level's visible quantity.
+    /// 2. The allocation is `allocation_pct` percent of the level quantity,
+    ///    rounded down to whole lots. Percentages above 100 count as 100.
+    /// 3. The account's resting orders at the level fill first, oldest
+    ///    first, until the allocation is used up. A partial fill is trimmed
+    ///    so the order keeps whole lots, as in FIFO matching.
+    /// 4. Whatever the incoming order has left then matches FIFO across all
+    ///    orders still at the level, the account's included.
     ///
-    /// Vector of (price, total_quantity) tuples
-    #[allow(dead_code)]
-    pub fn depth(&self, side: Side, levels: usize) -> Vec<PriceAndQuantity> {
-        let book_side = match side {
-            Side::Buy => &self.buy_side,
-            Side::Sell => &self.sell_side,
-        };
-
-        let iter: Box<dyn Iterator<Item = (&Price, &PriceLevel)>> = match side {
-            Side::Buy => Box::new(book_side.iter().rev()),
-            Side::Sell => Box::new(book_side.iter()),
-        };
+    /// Allocation fills count towards the fill cap like any other trade.
+    /// Pro-rata levels, uncrossing and the midpoint pool are unaffected.
+    /// Orders are assigned to accounts with [`OrderBook::place_account_order`].
+    /// Off by default.
+    pub fn with_designated_maker(mut self, account: AccountId, allocation_pct: u8) -> Self {
+        let allocation_pct = allocation_pct.min(100);
+        self.designated_maker = Some(DesignatedMaker { account, allocation_pct });
+        self
+    }
 
-        iter.take(levels)
-            .map(|(price, level)| (*price, level.total_quantity))
-            .collect()
+    /// Sets how the mid is rounded onto the tick grid when midpoint pool
+    /// orders trade, see [`OrderBook::mid_price_on_tick`]. Defaults to
+    /// `RoundingMode::Truncate`, which rounds down to the tick below.
+    pub fn with_midpoint_rounding(mut self, rounding: RoundingMode) -> Self {
+        self.midpoint_rounding = rounding;
+        self
     }
 
-    /// Returns true if the order book has no orders on either side.
-    #[allow(dead_code)]
-    pub fn is_empty(&self) -> bool {
-        self.buy_side.is_empty() && self.sell_side.is_empty()
+    /// Charges maker and taker fees on every trade.
+    ///
+    /// Each trade reports its `maker_fee` and `taker_fee` in quote minor
+    /// units, rounded toward zero and negative for a rebate; see
+    /// [`FeeSchedule::fee`]. Without a schedule the fees are zero.
+    pub fn with_fees(mut self, fees: FeeSchedule) -> Self {
+        self.fees = Some(fees);
+        self
     }
 
-    /// Updates the cached best buy price and quantity.
+    /// Requires orders to rest for at least `duration` before they can be cancelled.
     ///
-    /// Recalculates the best buy from the buy_side BTreeMap and caches the result.
-    /// This should be called whenever the buy side of the book is modified.
-    fn set_best_buy(&mut self) {
-        self.best_buy = self
-            .buy_side
-            .iter()
-            .next_back()
-            .map(|(price, level)| (*price, level.total_quantity));
+    /// The book has no wall clock: time is the logical timestamp counter,
+    /// which advances by one for every accepted placement. An order placed at
+    /// timestamp `t` can be cancelled once `duration` further orders have been
+    /// placed; earlier cancels fail with `OrderBookError::MinRestingTimeNotMet`.
+    pub fn with_min_resting_duration(mut self, duration: Timestamp) -> Self {
+        self.min_resting_duration = Some(duration);
+        self
     }
 
-    /// Updates the cached best sell price and quantity.
+    /// Expires resting orders after `ops` further book operations.
     ///
-    /// Recalculates the best sell from the sell_side BTreeMap and caches the result.
-    /// This should be called whenever the sell side of the book is modified.
-    fn update_cached_best_sell(&mut self) {
-        self.best_sell = self
-            .sell_side
-            .iter()
-            .next()
-            .map(|(price, level)| (*price, level.total_quantity));
+    /// An operation is an accepted placement (including any matching it
+    /// triggers) or a cancel; queries and rejected orders do not count. An
+    /// order placed by operation `k` stays eligible to match through
+    /// operation `k + ops` and is swept from the book at the end of that
+    /// operation. Only orders placed while this option is set are tracked.
+    /// Swept orders are removed silently apart from the level callbacks.
+    pub fn with_expire_after_ops(mut self, ops: u64) -> Self {
+        self.expire_after_ops = Some(ops);
+        self
     }
 
-    /// Attempts to match an incoming order against existing orders.
+    /// Applies every setting of `config`, replacing the current ones.
     ///
-    /// For buy orders, matches against sell orders at or below the buy price.
-    /// For sell orders, matches against buy orders at or above the sell price.
-    /// Orders are matched in price-time priority.
-    fn match_incoming_order(&mut self, incoming: &mut Order) -> Trades {
-        let mut trades = Vec::new();
+    /// Settings left unset in `config` are cleared, so this reproduces the
+    /// rules of the book `config` was read from with [`OrderBook::config`].
+    pub fn with_config(mut self, config: BookConfig) -> Self {
+        let BookConfig {
+            max_price,
+            max_quantity,
+            max_price_gap_ticks,
+            min_notional,
+            max_notional,
+            max_fills_per_order,
+            alignment_policy,
+            matching_policy,
+            designated_maker,
+            midpoint_rounding,
+            fees,
+            min_resting_duration,
+            expire_after_ops,
+        } = config;
+        self.max_price = max_price;
+        self.max_quantity = max_quantity;
+        self.max_price_gap_ticks = max_price_gap_ticks;
+        self.min_notional = min_notional;
+        self.max_notional = max_notional;
+        self.max_fills_per_order = max_fills_per_order;
+        self.alignment_policy = alignment_policy;
+        self.matching_policy = matching_policy;
+        self.designated_maker = designated_maker;
+        self.midpoint_rounding = midpoint_rounding;
+        self.fees = fees;
+        self.min_resting_duration = min_resting_duration;
+        self.expire_after_ops = expire_after_ops;
+        self
+    }
 
-        match incoming.side {
-            Side::Buy => {
-                while incoming.quantity > 0 {
-                    // Get the best matching price level
-                    let best_price = match self.sell_side.range(..=incoming.price).next() {
-                        Some((price, _)) => *price,
-                        None => break, // No more matching levels
-                    };
-                    
-                    // Process this single price level completely
-                    let match_result = Self::match_price_level(
-                        incoming,
-                        &mut trades,
-                        best_price,
-                        &mut self.sell_side,
-                        &mut self.id_index,
-                    );
+    /// Returns the settings that decide how the book validates and matches
+    /// orders.
+    pub fn config(&self) -> BookConfig {
+        BookConfig {
+            max_price: self.max_price,
+            max_quantity: self.max_quantity,
+            max_price_gap_ticks: self.max_price_gap_ticks,
+            min_notional: self.min_notional,
+            max_notional: self.max_notional,
+            max_fills_per_order: self.max_fills_per_order,
+            alignment_policy: self.alignment_policy,
+            matching_policy: self.matching_policy,
+            designated_maker: self.designated_maker,
+            midpoint_rounding: self.midpoint_rounding,
+            fees: self.fees,
+            min_resting_duration: self.min_resting_duration,
+            expire_after_ops: self.expire_after_ops,
+        }
+    }
 
-                    match match_result {
-                        LevelMatchResult::EmptyBestLevel => {
-                            self.sell_side.remove(&best_price);
-                            self.update_cached_best_sell();
-                        }
-                        LevelMatchResult::EmptyLevel => {
-                            self.sell_side.remove(&best_price);
-                        }
-                        LevelMatchResult::MatchedBestLevel => {
-                            self.update_cached_best_sell();
-                        }
-                        LevelMatchResult::Matched => {
-                            // No cache update needed
-                        }
-                    }
-                }
-            }
-            Side::Sell => {
-                while incoming.quantity > 0 {
-                    // Get the best matching price level
-                    let best_price = match self.buy_side.range(incoming.price..).next_back() {
-                        Some((price, _)) => *price,
-                        None => break, // No more matching levels
-                    };
-                    
-                    // Process this single price level completely
-                    let match_result = Self::match_price_level(
-                        incoming,
-                        &mut trades,
-                        best_price,
-                        &mut self.buy_side,
-                        &mut self.id_index,
-                    );
+    /// Registers a callback invoked whenever `place_order` rejects an order.
+    ///
+    /// The callback receives the error along with the side, price, quantity and
+    /// id of the rejected order, and fires just before the error is returned.
+    /// Registering a new callback replaces the previous one.
+    pub fn on_reject(
+        &mut self,
+        callback: impl FnMut(&OrderBookError, Side, Price, Quantity, Id) + Send + Sync + 'static,
+    ) {
+        self.on_reject = Some(Box::new(callback));
+    }
 
-                    match match_result {
-                        LevelMatchResult::EmptyBestLevel => {
-                            self.buy_side.remove(&best_price);
-                            self.set_best_buy();
-                        }
-                        LevelMatchResult::EmptyLevel => {
-                            self.buy_side.remove(&best_price);
-                        }
-                        LevelMatchResult::MatchedBestLevel => {
-                            self.set_best_buy();
-                        }
-                        // No cache update needed
-                        LevelMatchResult::Matched => {}
-                    }
-                }
-            }
-        }
+    /// Registers a callback invoked when a price level first appears on a side.
+    ///
+    /// Fires once per new level, when the first order rests at that price.
+    /// Useful for consumers that track level structure rather than orders.
+    pub fn on_level_added(&mut self, callback: impl FnMut(Side, Price) + Send + Sync + 'static) {
+        self.on_level_added = Some(Box::new(callback));
+    }
 
-        trades
+    /// Registers a callback invoked when a price level disappears from a side.
+    ///
+    /// Fires once when the last order at a price is filled or cancelled.
+    pub fn on_level_removed(&mut self, callback: impl FnMut(Side, Price) + Send + Sync + 'static) {
+        self.on_level_removed = Some(Box::new(callback));
     }
 
-    /// Helper method to match against a single price level on a specific book side.
+    /// Registers a callback invoked once each time [`OrderBook::clear`] empties the book.
     ///
-    /// This eliminates the duplication between Buy and Sell matching logic by
-    /// parameterizing the side-specific behaviors.
+    /// The callback runs after the book is empty. Mirrors can use it to reset
+    /// in one step instead of replaying a removal per level.
+    pub fn on_cleared(&mut self, callback: impl FnMut() + Send + Sync + 'static) {
+        self.on_cleared = Some(Box::new(callback));
+    }
+
+    /// Registers a callback invoked with every trade the book executes.
     ///
-    /// Returns matching result to guide cache updates.
-    fn match_price_level(
-        incoming: &mut Order,
-        trades: &mut Vec<Trade>,
-        price: Price,
-        book_side: &mut BTreeMap<Price, PriceLevel>,
-        id_index: &mut HashSet<Id>,
-    ) -> LevelMatchResult {
-        // Check if this price level is the best before modifying it
-        let level_was_best = match incoming.side {
-            Side::Buy => book_side.iter().next().map(|(p, _)| *p) == Some(price),
-            Side::Sell => book_side.iter().next_back().map(|(p, _)| *p) == Some(price),
-        };
+    /// Trades are reported once matching for an operation has finished, in
+    /// execution order and with their trade IDs assigned.
+    pub fn on_trade(&mut self, callback: impl FnMut(&Trade) + Send + Sync + 'static) {
+        self.on_trade = Some(Box::new(callback));
+    }
 
-        // compute whether this level becomes empty *inside* a block
-        let level_is_empty = if let Some(level) = book_side.get_mut(&price) {
-            Self::match_against_level(incoming, level, trades, id_index);
-            level.is_empty()
-        } else {
-            false
-        };
+    /// Registers a pre-trade check run before an order is accepted.
+    ///
+    /// The check receives the proposed order, carrying its account if it has
+    /// one, and that account's current net position (0 without an account).
+    /// Returning an error rejects the order with it, usually
+    /// `OrderBookError::RiskLimitExceeded`. It runs for limit, market and
+    /// midpoint placements, for amendments, and for stop orders when they
+    /// trigger. Registering a new check replaces the previous one.
+    pub fn set_risk_check(
+        &mut self,
+        check: impl Fn(&Order, i128) -> Result<(), OrderBookError> + Send + Sync + 'static,
+    ) {
+        self.risk_check = Some(Arc::new(check));
+    }
 
-        match (level_is_empty, level_was_best) {
-            (true, true) => LevelMatchResult::EmptyBestLevel,
-            (true, false) => LevelMatchResult::EmptyLevel,
-            (false, true) => LevelMatchResult::MatchedBestLevel,
-            (false, false) => LevelMatchResult::Matched,
-        }
+    /// Returns the net base quantity `account` has traded: bought minus sold.
+    pub fn position(&self, account: AccountId) -> i128 {
+        self.positions.get(&account).copied().unwrap_or(0)
     }
 
-    /// Matches an incoming order against a specific price level.
+    /// Registers a callback invoked when an order reaches its fill milestone.
     ///
-    /// Continues matching until either the incoming order is fully filled
-    /// or the price level is exhausted.
-    // Free/assoc fn; no &mut self here
-    fn match_against_level(
-        incoming: &mut Order,
-        level: &mut PriceLevel,
-        trades: &mut Vec<Trade>,
-        id_index: &mut HashSet<Id>,
+    /// Only orders placed with [`OrderBook::place_order_notify`] have a
+    /// milestone. The callback receives the order ID and its cumulative filled
+    /// quantity, and fires exactly once per order, right after the trade
+    /// callback has been called for the trade that crossed the milestone.
+    pub fn on_fill_milestone(
+        &mut self,
+        callback: impl FnMut(Id, Quantity) + Send + Sync + 'static,
     ) {
-        while incoming.quantity > 0 && !level.orders.is_empty() {
-            let resting = level.orders.front().expect("front exists");
-            let match_qty = incoming.quantity.min(resting.quantity);
+        self.on_fill_milestone = Some(Box::new(callback));
+    }
 
-            trades.push(Trade::new(level.price, match_qty, resting.id, incoming.id));
-            incoming.quantity -= match_qty;
+    /// Registers a callback receiving the top `levels` of depth per side
+    /// whenever they change.
+    ///
+    /// After each mutation the top levels are compared with those last
+    /// published; the callback fires only if either side differs, and only
+    /// the changed side is filled in. Changes deeper in the book produce no
+    /// update. The depth at registration counts as published.
+    pub fn on_l2_update(
+        &mut self,
+        levels: usize,
+        callback: impl FnMut(&L2Update) + Send + Sync + 'static,
+    ) {
+        self.on_l2_update = Some(Box::new(callback));
+        self.l2_levels = levels;
+        self.l2_bids = self.depth(Side::Buy, levels);
+        self.l2_asks = self.depth(Side::Sell, levels);
+    }
 
-            if match_qty == resting.quantity {
-                // fully consumed: pop & deindex
-                let removed = level.remove_order().expect("front existed");
-                id_index.remove(&removed.id);
-            } else {
-                // partial: shrink front
-                level.update_front_order_quantity(resting.quantity - match_qty);
-            }
-        }
+    /// Returns the current trading session phase.
+    pub fn phase(&self) -> MarketPhase {
+        self.phase
     }
 
-    /// Adds an order to the appropriate side of the book.
+    /// Moves the book to a new trading session phase.
     ///
-    /// Creates a new price level if one doesn't exist at the order's price.
+    /// In `PreOpen` orders rest without matching, so the book may become
+    /// crossed; taker-only orders are rejected since they could never fill.
+    /// In `Closed` every placement is rejected with
+    /// `OrderBookError::MarketClosed`, while cancels are still accepted.
+    ///
+    /// Entering `Continuous` matches any crossed orders left over from
+    /// `PreOpen` with continuous price-time rules: the older of the two front
+    /// orders is the maker and sets the trade price. Call
+    /// [`OrderBook::uncross`] first to open at a single auction price instead.
+    ///
+    /// Entering `Closed` removes every resting `TimeInForce::Day` order.
+    ///
+    /// # Returns
+    ///
+    /// The trades produced by resolving a crossed book, if any
+    pub fn set_phase(&mut self, phase: MarketPhase) -> Trades {
+        self.record_journal(JournalEntry::PhaseChanged(phase));
+        self.phase = phase;
+        let mut trades = match phase {
+            MarketPhase::Continuous => self.match_crossed_orders(None),
+            MarketPhase::Closed => {
+                self.expire_day_orders();
+                Vec::new()
+            }
+            MarketPhase::PreOpen => return Vec::new(),
+        };
+        self.record_trades(&mut trades);
+        self.assert_cache_consistent();
+        self.record_bbo();
+        self.publish_l2();
+        trades
+    }
+
+    /// Uncrosses the book in a single-price auction.
+    ///
+    /// Meant for a book whose bids and asks rested crossed during `PreOpen`.
+    /// The clearing price is the level price that maximizes the executable
+    /// volume, the lesser of the quantity bid at or above it and offered at
+    /// or below it. Ties go to the price leaving the smallest surplus
+    /// between the two, then to the lowest price. Every crossing order is
+    /// then matched in price-time priority, all at the clearing price, which
+    /// executes exactly that volume. Iceberg reserves count towards the
+    /// volume and are replenished slice by slice as during continuous
+    /// trading. The session phase is left unchanged.
+    ///
+    /// # Returns
+    ///
+    /// The clearing price and the resulting trades, or `None` and no trades
+    /// if the book is not crossed
+    pub fn uncross(&mut self) -> (Option<Price>, Trades) {
+        let Some(clearing) = self.clearing_price() else {
+            return (None, Vec::new());
+        };
+        self.record_journal(JournalEntry::Uncrossed);
+        let mut trades = self.match_crossed_orders(Some(clearing));
+        self.record_trades(&mut trades);
+        self.assert_cache_consistent();
+        self.record_bbo();
+        self.publish_l2();
+        (Some(clearing), trades)
+    }
+
+    /// Returns the auction clearing price of a crossed book, see `uncross`.
+    fn clearing_price(&self) -> Option<Price> {
+        let (bid, ask) = (self.best_buy?.0, self.best_sell?.0);
+        if bid < ask {
+            return None;
+        }
+        let bid_prices = self.buy_side.range(ask..).map(|(price, _)| *price);
+        let ask_prices = self.sell_side.range(..=bid).map(|(price, _)| *price);
+        bid_prices
+            .chain(ask_prices)
+            .map(|price| {
+                let demand: Quantity =
+                    self.buy_side.range(price..).map(|(_, level)| level.full_quantity()).sum();
+                let supply: Quantity =
+                    self.sell_side.range(..=price).map(|(_, level)| level.full_quantity()).sum();
+                (Reverse(demand.min(supply)), demand.abs_diff(supply), price)
+            })
+            .min()
+            .map(|(_, _, price)| price)
+    }
+
+    /// Rebinds the book to a new instrument, keeping every resting order.
+    ///
+    /// Meant for operational events such as a symbol change. Prices and
+    /// quantities are stored in minor units, so the new base and quote assets
+    /// must keep the current decimals; rescaling resting orders is not
+    /// supported. An instrument that fails [`Instrument::try_new`] validation
+    /// is rejected as well.
+    ///
+    /// # Returns
+    ///
+    /// `OrderBookError::IncompatibleInstrument` if the decimals differ or the
+    /// instrument is invalid, leaving the book unchanged
+    pub fn set_instrument(&mut self, instrument: Instrument) -> Result<(), OrderBookError> {
+        let same_units = instrument.base.decimals == self.instrument.base.decimals
+            && instrument.quote.decimals == self.instrument.quote.decimals
+            && instrument.tick_size == self.instrument.tick_size
+            && instrument.lot_size == self.instrument.lot_size;
+        if !same_units || instrument.validate().is_err() {
+            return Err(OrderBookError::IncompatibleInstrument(Box::new(instrument)));
+        }
+        self.instrument = instrument;
+        Ok(())
+    }
+
+    /// Places an order in the book and returns any resulting trades.
+    ///
+    /// The order will first attempt to match against existing orders on the
+    /// opposite side. Any remaining quantity will be added to the book.
+    ///
+    /// # Arguments
+    ///
+    /// * `side` - Whether this is a buy or sell order
+    /// * `price` - Price per unit
+    /// * `quantity` - Number of units to trade
+    /// * `id` - Unique identifier for the order
+    ///
+    /// # Returns
+    ///
+    /// A vector of trades that occurred as a result of this order, or
+    /// `OrderBookError::NotionalOverflow` if `price * quantity` does not fit
+    /// in a `u128`, in which case the book is unchanged
+    pub fn place_order(
+        &mut self,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+        id: Id,
+    ) -> Result<Trades, OrderBookError> {
+        let result = self
+            .validate_order(side, price, quantity, id)
+            .map(|(price, quantity)| self.execute_new_order(side, price, quantity, id, None));
+        self.report_rejection(result, side, price, quantity, id)
+    }
+
+    /// Places an order like [`OrderBook::place_order`], also reporting whether
+    /// matching was cut short by the per-order fill cap and what rested.
+    ///
+    /// See [`OrderBook::with_max_fills_per_order`].
+    pub fn place_order_detailed(
+        &mut self,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+        id: Id,
+    ) -> Result<PlaceResult, OrderBookError> {
+        let result = self.validate_order(side, price, quantity, id).map(|(price, quantity)| {
+            let order = self.stamp_order(side, price, quantity, id);
+            self.stats.orders_placed += 1;
+            self.execute_order_detailed(order)
+        });
+        self.report_rejection(result, side, price, quantity, id)
+    }
+
+    /// Places an order like [`OrderBook::place_order`] that notifies once a
+    /// fraction of it has filled.
+    ///
+    /// When the order's cumulative fills, as taker on arrival or as maker
+    /// while resting, first reach `notify_at_fill_fraction` of `quantity`
+    /// (rounded up to whole minor units), the fill milestone callback fires.
+    /// Clients that only care about progress can use it instead of handling
+    /// every trade. See [`OrderBook::on_fill_milestone`].
+    ///
+    /// # Returns
+    ///
+    /// The trades that resulted from the order, or
+    /// `OrderBookError::InvalidFillFraction` unless `0 < fraction <= 1`
+    pub fn place_order_notify(
+        &mut self,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+        id: Id,
+        notify_at_fill_fraction: Decimal,
+    ) -> Result<Trades, OrderBookError> {
+        let fraction = notify_at_fill_fraction;
+        let result = self.validate_order(side, price, quantity, id).and_then(|(price, quantity)| {
+            if fraction <= Decimal::ZERO || fraction > Decimal::ONE {
+                return Err(OrderBookError::InvalidFillFraction { id, fraction });
+            }
+            let threshold = Decimal::from_u128(quantity)
+                .and_then(|q| q.checked_mul(fraction))
+                .and_then(|t| t.ceil().to_u128())
+                .unwrap_or(quantity);
+            self.fill_milestones.insert(id, FillMilestone { threshold, filled: 0 });
+            Ok(self.execute_new_order(side, price, quantity, id, None))
+        });
+        self.report_rejection(result, side, price, quantity, id)
+    }
+
+    /// Places an order carrying an externally supplied timestamp, for imports.
+    ///
+    /// The order matches like [`OrderBook::place_order`], but any remainder
+    /// takes its place in the level's queue by `timestamp`, so an order older
+    /// than those already resting at its price gains priority over them. The
+    /// book's own sequence is advanced past `timestamp` when needed, so later
+    /// placements are still stamped after every imported order.
+    pub fn place_order_at(
+        &mut self,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+        id: Id,
+        timestamp: Timestamp,
+    ) -> Result<Trades, OrderBookError> {
+        let result = self.validate_order(side, price, quantity, id).map(|(price, quantity)| {
+            self.next_timestamp = self.next_timestamp.max(timestamp.saturating_add(1));
+            self.stats.orders_placed += 1;
+            self.execute_order(Order::new(id, side, price, quantity, timestamp))
+        });
+        self.report_rejection(result, side, price, quantity, id)
+    }
+
+    /// Places an order carrying a client tag, such as a strategy ID.
+    ///
+    /// Behaves exactly like [`OrderBook::place_order`]; the tag is stored on
+    /// the resting order and reported on every trade the order takes part
+    /// in, as `taker_tag` or `maker_tag`.
+    pub fn place_tagged_order(
+        &mut self,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+        id: Id,
+        tag: u32,
+    ) -> Result<Trades, OrderBookError> {
+        let result = self
+            .validate_order(side, price, quantity, id)
+            .map(|(price, quantity)| self.execute_new_order(side, price, quantity, id, Some(tag)));
+        self.report_rejection(result, side, price, quantity, id)
+    }
+
+    /// Places an order on behalf of an account.
+    ///
+    /// Behaves exactly like [`OrderBook::place_order`]; the account is stored
+    /// on the resting order, where a designated market maker allocation
+    /// looks for it, see [`OrderBook::with_designated_maker`].
+    pub fn place_account_order(
+        &mut self,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+        id: Id,
+        account: AccountId,
+    ) -> Result<Trades, OrderBookError> {
+        let validated = self.validate_account_order(side, price, quantity, id, Some(account));
+        let result = validated.map(|(price, quantity)| {
+            let order = self.stamp_order(side, price, quantity, id).with_account(Some(account));
+            self.stats.orders_placed += 1;
+            self.execute_order(order)
+        });
+        self.report_rejection(result, side, price, quantity, id)
+    }
+
+    /// Applies a logged command to the book.
+    ///
+    /// # Returns
+    ///
+    /// The trades produced by the command
+    pub fn apply(&mut self, command: &BookCommand) -> Result<Trades, OrderBookError> {
+        match *command {
+            BookCommand::Place { side, price, quantity, id } => {
+                self.place_order(side, price, quantity, id)
+            }
+            BookCommand::Cancel { id } => self.cancel_order(id).map(|_| Vec::new()),
+            BookCommand::Amend { id, quantity } => {
+                self.amend_quantity(id, quantity).map(|()| Vec::new())
+            }
+        }
+    }
+
+    /// Simulates applying `commands` in order, without mutating the book.
+    ///
+    /// The commands run against a detached copy of the book, so callbacks do
+    /// not fire. A rejected command is recorded and the batch carries on, as
+    /// it would if applied for real.
+    ///
+    /// # Returns
+    ///
+    /// The trades, net resting quantity change per side and errors that
+    /// applying the batch would produce
+    pub fn preview_batch(&self, commands: &[BookCommand]) -> BatchPreview {
+        let mut book = self.detached_clone();
+        let mut preview = BatchPreview::default();
+        for (index, command) in commands.iter().enumerate() {
+            match book.apply(command) {
+                Ok(trades) => preview.trades.extend(trades),
+                Err(error) => preview.errors.push((index, error)),
+            }
+        }
+        let delta = |side| book.total_quantity(side) as i128 - self.total_quantity(side) as i128;
+        preview.buy_quantity_delta = delta(Side::Buy);
+        preview.sell_quantity_delta = delta(Side::Sell);
+        preview
+    }
+
+    /// Places `orders` in order, stopping at the first rejected one.
+    ///
+    /// Orders before the rejected one stay applied; see
+    /// [`OrderBook::place_orders_atomic`] for all-or-nothing placement.
+    ///
+    /// # Returns
+    ///
+    /// The trades produced by each order, or the first rejection
+    pub fn place_orders(
+        &mut self,
+        orders: &[(Side, Price, Quantity, Id)],
+    ) -> Result<Vec<Trades>, OrderBookError> {
+        orders
+            .iter()
+            .map(|&(side, price, quantity, id)| self.place_order(side, price, quantity, id))
+            .collect()
+    }
+
+    /// Places `orders` in order only if every one of them is accepted.
+    ///
+    /// The batch is first run against a detached copy of the book. If any
+    /// order is rejected, the book is left exactly as it was, no callbacks
+    /// fire and the first rejection is returned. Otherwise the batch is
+    /// applied for real, which reproduces the same outcome since matching is
+    /// deterministic.
+    ///
+    /// # Returns
+    ///
+    /// The trades produced by each order, or the first rejection
+    pub fn place_orders_atomic(
+        &mut self,
+        orders: &[(Side, Price, Quantity, Id)],
+    ) -> Result<Vec<Trades>, OrderBookError> {
+        self.detached_clone().place_orders(orders)?;
+        self.place_orders(orders)
+    }
+
+    /// Copies the book's state and configuration without its callbacks.
+    ///
+    /// The copy uses the default fair value estimator, since estimators
+    /// cannot be cloned.
+    fn detached_clone(&self) -> OrderBook {
+        OrderBook {
+            instrument: self.instrument.clone(),
+            buy_side: self.buy_side.clone(),
+            sell_side: self.sell_side.clone(),
+            next_timestamp: self.next_timestamp,
+            id_index: self.id_index.clone(),
+            best_buy: self.best_buy,
+            best_sell: self.best_sell,
+            max_price: self.max_price,
+            max_quantity: self.max_quantity,
+            max_price_gap_ticks: self.max_price_gap_ticks,
+            on_reject: None,
+            on_level_added: None,
+            on_level_removed: None,
+            on_cleared: None,
+            stats: self.stats.clone(),
+            trade_history: self.trade_history.clone(),
+            trade_history_capacity: self.trade_history_capacity,
+            min_resting_duration: self.min_resting_duration,
+            midpoint_buys: self.midpoint_buys.clone(),
+            midpoint_sells: self.midpoint_sells.clone(),
+            midpoint_ids: self.midpoint_ids.clone(),
+            operation_count: self.operation_count,
+            expire_after_ops: self.expire_after_ops,
+            expiry_queue: self.expiry_queue.clone(),
+            latest_expiry: self.latest_expiry.clone(),
+            phase: self.phase,
+            taker_trades: self.taker_trades.clone(),
+            taker_trade_ids: self.taker_trade_ids.clone(),
+            taker_trade_capacity: self.taker_trade_capacity,
+            bbo_history: self.bbo_history.clone(),
+            bbo_history_capacity: self.bbo_history_capacity,
+            max_fills_per_order: self.max_fills_per_order,
+            on_l2_update: None,
+            l2_levels: self.l2_levels,
+            l2_bids: self.l2_bids.clone(),
+            l2_asks: self.l2_asks.clone(),
+            on_trade: None,
+            risk_check: self.risk_check.clone(),
+            positions: self.positions.clone(),
+            on_fill_milestone: None,
+            fill_milestones: self.fill_milestones.clone(),
+            gtd_expiries: self.gtd_expiries.clone(),
+            pending_stops: self.pending_stops.clone(),
+            min_notional: self.min_notional,
+            max_notional: self.max_notional,
+            alignment_policy: self.alignment_policy,
+            matching_policy: self.matching_policy,
+            designated_maker: self.designated_maker,
+            midpoint_rounding: self.midpoint_rounding,
+            fees: self.fees,
+            last_trade_price: self.last_trade_price,
+            ohlc: self.ohlc.clone(),
+            audit_trail: self.audit_trail.clone(),
+            audit_capacity: self.audit_capacity,
+            pending_audit: Vec::new(),
+            event_sink: None,
+            journal: None,
+            fair_value_estimator: Box::new(Mid),
+        }
+    }
+
+    /// Places an order with an explicit time in force.
+    ///
+    /// `TimeInForce::Gtc` behaves exactly like [`OrderBook::place_order`].
+    ///
+    /// `TimeInForce::Ioc` orders match what they can on arrival, up to
+    /// `price`, and discard any remainder instead of resting it. Nothing
+    /// trades in `PreOpen`, where they are discarded untouched.
+    ///
+    /// `TimeInForce::Fok` orders fill completely in one shot or not at all.
+    /// Before any matching, the opposite side is walked to sum the quantity
+    /// available at prices crossing `price`, within the fill cap set by
+    /// [`OrderBook::with_max_fills_per_order`]. If it falls short of
+    /// `quantity` the order is killed: `Ok` with no trades is returned and the book,
+    /// including its statistics and sequence number, is left untouched.
+    ///
+    /// `TimeInForce::Gtd` orders match like `Gtc` and rest any remainder,
+    /// which may trade with orders stamped up to and including the given
+    /// timestamp; it is removed before any later order is matched.
+    ///
+    /// `TimeInForce::Day` orders rest like `Gtc` until the book is moved to
+    /// `MarketPhase::Closed`, which removes them.
+    ///
+    /// # Returns
+    ///
+    /// The trades that resulted from the order
+    pub fn place_order_tif(
+        &mut self,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+        id: Id,
+        tif: TimeInForce,
+    ) -> Result<Trades, OrderBookError> {
+        let validated = self.validate_order(side, price, quantity, id);
+        let result = validated.map(|(price, quantity)| match tif {
+            TimeInForce::Gtc => self.execute_new_order(side, price, quantity, id, None),
+            TimeInForce::Ioc => {
+                if self.phase == MarketPhase::PreOpen {
+                    return Vec::new();
+                }
+                self.execute_market_order(side, price, quantity, id)
+            }
+            TimeInForce::Fok => {
+                let fillable = self.phase != MarketPhase::PreOpen
+                    && self.fillable_quantity(side, price, quantity) == quantity;
+                if !fillable {
+                    return Vec::new();
+                }
+                self.execute_new_order(side, price, quantity, id, None)
+            }
+            TimeInForce::Gtd(_) | TimeInForce::Day => {
+                let order = self.stamp_order(side, price, quantity, id).with_time_in_force(tif);
+                self.stats.orders_placed += 1;
+                self.execute_order(order)
+            }
+        });
+        self.report_rejection(result, side, price, quantity, id)
+    }
+
+    /// Places a good-till-date order, shorthand for [`OrderBook::place_order_tif`]
+    /// with `TimeInForce::Gtd(expires_at)`.
+    pub fn place_order_gtd(
+        &mut self,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+        id: Id,
+        expires_at: Timestamp,
+    ) -> Result<Trades, OrderBookError> {
+        self.place_order_tif(side, price, quantity, id, TimeInForce::Gtd(expires_at))
+    }
+
+    /// Places an order that must execute in full immediately.
+    ///
+    /// Before any matching, the opposite side is checked for enough crossable
+    /// quantity at or better than `price`, within the fill cap set by
+    /// [`OrderBook::with_max_fills_per_order`]. If any quantity would be left
+    /// to rest, the order is rejected with `OrderBookError::WouldRest` and no
+    /// fills occur, so the book is left untouched. Otherwise the order is
+    /// matched exactly like `place_order`.
+    ///
+    /// # Returns
+    ///
+    /// The trades that fully filled the order
+    pub fn place_taker_only(
+        &mut self,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+        id: Id,
+    ) -> Result<Trades, OrderBookError> {
+        let result = self.validate_order(side, price, quantity, id).and_then(|(price, quantity)| {
+            let available = match self.phase {
+                MarketPhase::PreOpen => 0,
+                _ => self.fillable_quantity(side, price, quantity),
+            };
+            if available < quantity {
+                return Err(OrderBookError::WouldRest { id, remaining: quantity - available });
+            }
+            Ok(self.execute_new_order(side, price, quantity, id, None))
+        });
+        self.report_rejection(result, side, price, quantity, id)
+    }
+
+    /// Places a market order that takes liquidity at any price.
+    ///
+    /// Walks the opposite side from the best price outward until `quantity`
+    /// is filled or the side runs dry. An unfilled remainder never rests; it
+    /// is discarded, so a partial fill is signalled by trades summing to less
+    /// than `quantity`. Market orders do not trade in `PreOpen`.
+    ///
+    /// # Returns
+    ///
+    /// The trades executed, or `OrderBookError::InsufficientLiquidity` if
+    /// nothing at all could fill, in which case the book is unchanged
+    pub fn place_market_order(
+        &mut self,
+        side: Side,
+        quantity: Quantity,
+        id: Id,
+    ) -> Result<Trades, OrderBookError> {
+        // Market orders carry no limit price, so the price cap and notional
+        // bounds do not apply
+        let limit = match side {
+            Side::Buy => Price::MAX,
+            Side::Sell => 0,
+        };
+        let result = self.validate_unpriced_order(side, limit, quantity, id).and_then(|quantity| {
+            let opposite_empty = match side {
+                Side::Buy => self.sell_side.is_empty(),
+                Side::Sell => self.buy_side.is_empty(),
+            };
+            if opposite_empty || self.phase == MarketPhase::PreOpen {
+                return Err(OrderBookError::InsufficientLiquidity {
+                    requested: quantity,
+                    filled: 0,
+                });
+            }
+            Ok(self.execute_market_order(side, limit, quantity, id))
+        });
+        self.report_rejection(result, side, limit, quantity, id)
+    }
+
+    /// Matches a validated order up to `limit` and discards any remainder.
+    fn execute_market_order(
+        &mut self,
+        side: Side,
+        limit: Price,
+        quantity: Quantity,
+        id: Id,
+    ) -> Trades {
+        let timestamp = self.next_timestamp;
+        self.next_timestamp += 1;
+
+        self.expire_gtd_orders_before(timestamp);
+        let mut incoming = Order::new(id, side, limit, quantity, timestamp);
+        self.report_accepted(&incoming, OrderKind::Market);
+        let mut trades = self.match_incoming_order(&mut incoming);
+        self.stats.orders_placed += 1;
+        self.record_trades(&mut trades);
+
+        self.operation_count += 1;
+        self.sweep_expired_by_ops();
+        self.assert_cache_consistent();
+        self.record_bbo();
+        self.publish_l2();
+
+        if let Some(last) = trades.last() {
+            let cascade = self.trigger_stops(last.price);
+            trades.extend(cascade);
+        }
+        trades
+    }
+
+    /// Places a stop-limit order, held aside until a trade reaches `stop_price`.
+    ///
+    /// The order is validated as a limit order at `limit_price` but does not
+    /// enter the book. When a limit or market order trades, the price of its
+    /// last trade is checked against every pending stop: a buy stop triggers
+    /// once it is at or above `stop_price`, a sell stop once it is at or
+    /// below. Triggered stops are then submitted, in placement order, as
+    /// limit orders stamped at that point, and any trades they cause, which
+    /// may trigger further stops, are appended to the trades returned for
+    /// the order that set them off. A stop whose limit order no longer passes
+    /// validation when triggered is dropped.
+    pub fn place_stop_limit(
+        &mut self,
+        side: Side,
+        stop_price: Price,
+        limit_price: Price,
+        quantity: Quantity,
+        id: Id,
+    ) -> Result<(), OrderBookError> {
+        // The price-gap check waits until the stop triggers, when the book
+        // it would rest in is known
+        let result = self
+            .validate_order_fields(limit_price, quantity, id)
+            .and_then(|(limit_price, quantity)| {
+                self.check_notional(limit_price, quantity, id).map(|()| (limit_price, quantity))
+            })
+            .map(|(limit_price, quantity)| {
+                let stop = StopOrder { id, side, stop_price, limit_price, quantity };
+                self.record_journal(JournalEntry::StopPlaced(stop.clone()));
+                self.pending_stops.push(stop);
+            });
+        self.report_rejection(result, side, limit_price, quantity, id)
+    }
+
+    /// Returns the stop-limit orders awaiting their trigger, in placement order.
+    pub fn pending_stops(&self) -> &[StopOrder] {
+        &self.pending_stops
+    }
+
+    /// Submits every pending stop triggered by a trade at `last_price`.
+    fn trigger_stops(&mut self, last_price: Price) -> Trades {
+        if self.pending_stops.is_empty() {
+            return Vec::new();
+        }
+        let (triggered, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.pending_stops)
+            .into_iter()
+            .partition(|stop| stop.is_triggered(last_price));
+        self.pending_stops = pending;
+
+        let mut trades = Vec::new();
+        for StopOrder { id, side, limit_price, quantity, .. } in triggered {
+            if let Ok((price, quantity)) = self.validate_order(side, limit_price, quantity, id) {
+                trades.extend(self.execute_new_order(side, price, quantity, id, None));
+            }
+        }
+        trades
+    }
+
+    /// Places a post-only order, which must rest and never take liquidity.
+    ///
+    /// If the order would match on arrival (a buy at or above the best sell,
+    /// a sell at or below the best buy) it is rejected with
+    /// `OrderBookError::WouldCross` and the book is not modified. Otherwise
+    /// it rests in full.
+    pub fn place_post_only(
+        &mut self,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+        id: Id,
+    ) -> Result<(), OrderBookError> {
+        let result = self.validate_order(side, price, quantity, id).and_then(|(price, quantity)| {
+            if self.is_marketable(side, price) {
+                let (best_opposite, _) = match side {
+                    Side::Buy => self.best_sell,
+                    Side::Sell => self.best_buy,
+                }
+                .expect("marketable implies an opposite best");
+                return Err(OrderBookError::WouldCross { price, best_opposite });
+            }
+            // Not marketable, so this only rests the order
+            self.execute_new_order(side, price, quantity, id, None);
+            Ok(())
+        });
+        self.report_rejection(result, side, price, quantity, id)
+    }
+
+    /// Places an iceberg order that shows only `display_quantity` at a time.
+    ///
+    /// On arrival the order trades its full `total_quantity` like
+    /// [`OrderBook::place_order`]. Any remainder rests with a visible slice of
+    /// at most `display_quantity`; the rest is held in reserve and appears in
+    /// neither `depth`, the best prices nor the level totals, only in
+    /// [`OrderBook::hidden_volume`]. Each time the visible slice is fully
+    /// filled, a new slice is replenished from the reserve and the order
+    /// moves to the back of its level's queue with a fresh timestamp, behind
+    /// orders that arrived after it. Matching continues against the new
+    /// slice within the same sweep.
+    ///
+    /// # Returns
+    ///
+    /// The trades that resulted from the order, or
+    /// `OrderBookError::ZeroQuantity` for a zero `display_quantity`. The
+    /// display quantity is lot-aligned like the total, so every slice rests
+    /// on a whole lot.
+    pub fn place_iceberg(
+        &mut self,
+        side: Side,
+        price: Price,
+        display_quantity: Quantity,
+        total_quantity: Quantity,
+        id: Id,
+    ) -> Result<Trades, OrderBookError> {
+        let validated = self.validate_order(side, price, total_quantity, id);
+        let result = validated.and_then(|(price, total_quantity)| {
+            let display_quantity = self.align_quantity(display_quantity)?;
+            if display_quantity == 0 {
+                return Err(OrderBookError::ZeroQuantity { id, quantity: display_quantity });
+            }
+            let order = self
+                .stamp_order(side, price, total_quantity, id)
+                .with_display_quantity(display_quantity);
+            self.stats.orders_placed += 1;
+            Ok(self.execute_order(order))
+        });
+        self.report_rejection(result, side, price, total_quantity, id)
+    }
+
+    /// Places an iceberg order whose every slice shows a randomized size.
+    ///
+    /// Behaves like [`OrderBook::place_iceberg`], except that the first
+    /// slice and each replenished one are drawn from `peak`, so the displayed
+    /// size does not give the order away. Both ends of the range are
+    /// lot-aligned like any quantity.
+    ///
+    /// # Returns
+    ///
+    /// The trades that resulted from the order, `OrderBookError::ZeroQuantity`
+    /// for a zero `min_peak`, or `OrderBookError::InvalidPeakRange` if
+    /// `min_peak` exceeds `max_peak`
+    pub fn place_iceberg_randomized(
+        &mut self,
+        side: Side,
+        price: Price,
+        peak: RandomPeak,
+        total_quantity: Quantity,
+        id: Id,
+    ) -> Result<Trades, OrderBookError> {
+        let validated = self.validate_order(side, price, total_quantity, id);
+        let result = validated.and_then(|(price, total_quantity)| {
+            let min = self.align_quantity(peak.min_peak)?;
+            let max = self.align_quantity(peak.max_peak)?;
+            if min == 0 {
+                return Err(OrderBookError::ZeroQuantity { id, quantity: min });
+            }
+            if min > max {
+                return Err(OrderBookError::InvalidPeakRange { min, max });
+            }
+            let lot = self.instrument.lot_size;
+            let mut peak = RandomPeak { min_peak: min, max_peak: max, lot, ..peak };
+            let order = self
+                .stamp_order(side, price, total_quantity, id)
+                .with_display_quantity(peak.next_peak())
+                .with_random_peak(Some(peak));
+            self.stats.orders_placed += 1;
+            Ok(self.execute_order(order))
+        });
+        self.report_rejection(result, side, price, total_quantity, id)
+    }
+
+    /// Places a maker order that must join or improve the best price on its own side.
+    ///
+    /// The order is accepted only if both hold:
+    /// - it is not marketable (see [`OrderBook::is_marketable`]), otherwise it
+    ///   is rejected with `OrderBookError::WouldTake`;
+    /// - its own side is empty, or its price is at or better than that side's
+    ///   best (at or above the best buy, at or below the best sell), otherwise
+    ///   it is rejected with `OrderBookError::BehindBest`.
+    ///
+    /// An accepted order rests in full and never produces trades.
+    pub fn place_maker_improving(
+        &mut self,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+        id: Id,
+    ) -> Result<(), OrderBookError> {
+        let result = self.validate_order(side, price, quantity, id).and_then(|(price, quantity)| {
+            if self.is_marketable(side, price) {
+                return Err(OrderBookError::WouldTake { id });
+            }
+            let behind = match side {
+                Side::Buy => self.best_buy.filter(|&(best, _)| price < best),
+                Side::Sell => self.best_sell.filter(|&(best, _)| price > best),
+            };
+            if let Some((best, _)) = behind {
+                return Err(OrderBookError::BehindBest { id, price, best });
+            }
+            self.execute_new_order(side, price, quantity, id, None);
+            Ok(())
+        });
+        self.report_rejection(result, side, price, quantity, id)
+    }
+
+    /// Places a midpoint-pegged order that only executes at the current mid price.
+    ///
+    /// Midpoint orders live in a separate pool and never interact with the
+    /// lit book: they match only against resting midpoint orders on the
+    /// opposite side, FIFO, at the mid price. Any remainder rests in
+    /// the pool. When the lit book is one-sided there is no mid, so the order
+    /// rests without matching. Matching is only attempted when a midpoint
+    /// order is placed.
+    ///
+    /// The mid is rounded onto the tick grid with the book's midpoint rounding
+    /// mode, see [`OrderBook::with_midpoint_rounding`], so midpoint trades
+    /// always print at a price an order could rest at.
+    ///
+    /// # Returns
+    ///
+    /// The trades executed at the mid price
+    pub fn place_midpoint_order(
+        &mut self,
+        side: Side,
+        quantity: Quantity,
+        id: Id,
+    ) -> Result<Trades, OrderBookError> {
+        let mid = self.midpoint_execution_price();
+        let result = self
+            .validate_unpriced_order(side, mid.unwrap_or(0), quantity, id)
+            .map(|quantity| self.execute_midpoint_order(side, quantity, id, mid));
+        self.report_rejection(result, side, mid.unwrap_or(0), quantity, id)
+    }
+
+    /// The price midpoint pool orders trade at, if the book is continuous
+    /// and two-sided.
+    fn midpoint_execution_price(&self) -> Option<Price> {
+        self.mid_price_on_tick(self.midpoint_rounding)
+            .filter(|_| self.phase == MarketPhase::Continuous)
+    }
+
+    /// Returns the total quantity resting in the midpoint pool for `side`.
+    pub fn midpoint_quantity(&self, side: Side) -> Quantity {
+        let pool = match side {
+            Side::Buy => &self.midpoint_buys,
+            Side::Sell => &self.midpoint_sells,
+        };
+        pool.iter().map(|order| order.quantity).sum()
+    }
+
+    /// Returns the total quantity visible in the lit price levels on `side`.
+    pub fn displayed_volume(&self, side: Side) -> Quantity {
+        let book_side = match side {
+            Side::Buy => &self.buy_side,
+            Side::Sell => &self.sell_side,
+        };
+        book_side.values().map(|level| level.total_quantity).sum()
+    }
+
+    /// Returns the total resting quantity on `side` that is not shown in depth.
+    ///
+    /// This is the liquidity resting in the midpoint pool, which never appears
+    /// in the lit levels, plus the undisplayed reserve of iceberg orders.
+    /// Together with [`OrderBook::displayed_volume`] it accounts for
+    /// everything resting on the side.
+    pub fn hidden_volume(&self, side: Side) -> Quantity {
+        let book_side = match side {
+            Side::Buy => &self.buy_side,
+            Side::Sell => &self.sell_side,
+        };
+        let reserve: Quantity = book_side
+            .values()
+            .flat_map(|level| level.orders.iter())
+            .map(|order| order.hidden_remaining)
+            .sum();
+        self.midpoint_quantity(side) + reserve
+    }
+
+    /// Returns the total quantity resting on `side`, displayed and hidden.
+    pub fn total_quantity(&self, side: Side) -> Quantity {
+        self.displayed_volume(side) + self.hidden_volume(side)
+    }
+
+    /// Returns the displayed quantity an order of `side` at `limit_price` could
+    /// match against: asks at or below a buy price, bids at or above a sell
+    /// price.
+    ///
+    /// This is the check `TimeInForce::Fok` orders run before matching. Only
+    /// the crossable levels are visited.
+    pub fn quantity_available(&self, side: Side, limit_price: Price) -> Quantity {
+        self.crossable_quantity(side, limit_price, Quantity::MAX)
+    }
+
+    /// Returns the number of individual orders resting on `side`, including
+    /// those in the midpoint pool.
+    pub fn order_count(&self, side: Side) -> usize {
+        let (book_side, pool) = match side {
+            Side::Buy => (&self.buy_side, &self.midpoint_buys),
+            Side::Sell => (&self.sell_side, &self.midpoint_sells),
+        };
+        book_side.values().map(|level| level.orders.len()).sum::<usize>() + pool.len()
+    }
+
+    /// Matches a validated midpoint order against the opposite pool and rests the remainder.
+    fn execute_midpoint_order(
+        &mut self,
+        side: Side,
+        quantity: Quantity,
+        id: Id,
+        mid: Option<Price>,
+    ) -> Trades {
+        let timestamp = self.next_timestamp;
+        self.next_timestamp += 1;
+
+        // Pool orders have no limit price of their own
+        let mut incoming = Order::new(id, side, 0, quantity, timestamp);
+        self.report_accepted(&incoming, OrderKind::Midpoint);
+        let mut trades = Vec::new();
+        if let Some(mid) = mid {
+            let pool = match side {
+                Side::Buy => &mut self.midpoint_sells,
+                Side::Sell => &mut self.midpoint_buys,
+            };
+            let lot = self.instrument.lot_size.max(1);
+            while incoming.quantity > 0 {
+                let Some(resting) = pool.front_mut() else { break };
+                let mut match_qty = incoming.quantity.min(resting.quantity);
+                // As in the lit book, a partial fill leaves whole lots resting
+                match_qty -= (resting.quantity - match_qty) % lot;
+                if match_qty == 0 {
+                    incoming.quantity = 0;
+                    break;
+                }
+                if self.audit_capacity > 0 {
+                    let snapshot = (incoming.clone(), resting.clone(), PricePolicy::Midpoint);
+                    self.pending_audit.push(snapshot);
+                }
+                let mut trade = Trade::new(mid, match_qty, resting.id, incoming.id)
+                    .with_tags(resting.tag, incoming.tag)
+                    .with_accounts_of(resting, &incoming);
+                trade.timestamp = incoming.timestamp;
+                trades.push(trade);
+                incoming.quantity -= match_qty;
+                resting.quantity -= match_qty;
+                if resting.quantity == 0 {
+                    let filled = pool.pop_front().expect("front exists");
+                    self.midpoint_ids.remove(&filled.id);
+                }
+            }
+        }
+        self.stats.orders_placed += 1;
+        self.record_trades(&mut trades);
+
+        if incoming.quantity > 0 {
+            self.midpoint_ids.insert(id);
+            match side {
+                Side::Buy => self.midpoint_buys.push_back(incoming),
+                Side::Sell => self.midpoint_sells.push_back(incoming),
+            }
+        }
+        self.operation_count += 1;
+        self.sweep_expired_by_ops();
+        self.assert_cache_consistent();
+        self.record_bbo();
+        self.publish_l2();
+        trades
+    }
+
+    /// Fires the reject callback if `result` is an error, then passes it through.
+    fn report_rejection<T>(
+        &mut self,
+        result: Result<T, OrderBookError>,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+        id: Id,
+    ) -> Result<T, OrderBookError> {
+        if let (Err(error), Some(callback)) = (&result, self.on_reject.as_mut()) {
+            callback(error, side, price, quantity, id);
+        }
+        result
+    }
+
+    /// Checks an incoming limit order against the book's acceptance rules.
+    ///
+    /// Returns the price and quantity to place, aligned to the instrument's
+    /// tick and lot size under `AlignmentPolicy::Round`. Orders whose raw
+    /// notional overflows are rejected so later fee and VWAP sums cannot.
+    fn validate_order(
+        &self,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+        id: Id,
+    ) -> Result<(Price, Quantity), OrderBookError> {
+        self.validate_account_order(side, price, quantity, id, None)
+    }
+
+    /// [`OrderBook::validate_order`] for an order placed on behalf of
+    /// `account`, whose position the risk check sees.
+    fn validate_account_order(
+        &self,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+        id: Id,
+        account: Option<AccountId>,
+    ) -> Result<(Price, Quantity), OrderBookError> {
+        let (price, quantity) = self.validate_order_fields(price, quantity, id)?;
+        self.check_notional(price, quantity, id)?;
+        self.check_price_gap(side, price, id)?;
+        self.check_risk(side, price, quantity, id, account)?;
+        Ok((price, quantity))
+    }
+
+    /// Runs the registered risk check, if any, on the proposed order.
+    fn check_risk(
+        &self,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+        id: Id,
+        account: Option<AccountId>,
+    ) -> Result<(), OrderBookError> {
+        let Some(check) = self.risk_check.as_ref() else {
+            return Ok(());
+        };
+        let order =
+            Order::new(id, side, price, quantity, self.next_timestamp).with_account(account);
+        check(&order, account.map_or(0, |account| self.position(account)))
+    }
+
+    /// Rejects a non-marketable order priced more than the configured number
+    /// of ticks away from the best price on its own side.
+    fn check_price_gap(&self, side: Side, price: Price, id: Id) -> Result<(), OrderBookError> {
+        let best = match side {
+            Side::Buy => self.best_buy,
+            Side::Sell => self.best_sell,
+        };
+        self.check_price_gap_from(side, price, id, best.map(|(best, _)| best))
+    }
+
+    /// [`OrderBook::check_price_gap`] against a given best price on `side`.
+    fn check_price_gap_from(
+        &self,
+        side: Side,
+        price: Price,
+        id: Id,
+        best: Option<Price>,
+    ) -> Result<(), OrderBookError> {
+        let Some(max_ticks) = self.max_price_gap_ticks else {
+            return Ok(());
+        };
+        let Some(best) = best.filter(|_| !self.is_marketable(side, price)) else {
+            return Ok(());
+        };
+        let ticks = price.abs_diff(best) / self.instrument.tick_size;
+        if ticks > max_ticks {
+            return Err(OrderBookError::PriceGapTooLarge { id, price, best, max_ticks });
+        }
+        Ok(())
+    }
+
+    /// Checks new terms for the resting order `id` against the same rules as
+    /// a fresh placement, except ID uniqueness and the price gap, and returns
+    /// them aligned. `amend_price` checks the gap itself, as the order's own
+    /// level must not count as the best price.
+    fn validate_amendment(
+        &self,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+        id: Id,
+        account: Option<AccountId>,
+    ) -> Result<(Price, Quantity), OrderBookError> {
+        if self.phase == MarketPhase::Closed {
+            return Err(OrderBookError::MarketClosed { id });
+        }
+        let (price, quantity) = self.validate_terms(price, quantity, id)?;
+        self.check_notional(price, quantity, id)?;
+        self.check_risk(side, price, quantity, id, account)?;
+        Ok((price, quantity))
+    }
+
+    /// Rejects a `price * quantity` that overflows or falls outside the
+    /// configured notional bounds.
+    fn check_notional(
+        &self,
+        price: Price,
+        quantity: Quantity,
+        id: Id,
+    ) -> Result<(), OrderBookError> {
+        if price.checked_mul(quantity).is_none() {
+            return Err(OrderBookError::NotionalOverflow { price, quantity });
+        }
+        if self.min_notional.is_some() || self.max_notional.is_some() {
+            let notional = notional(price, quantity, &self.instrument.base).unwrap_or(Price::MAX);
+            if let Some(min) = self.min_notional.filter(|min| notional < *min) {
+                return Err(OrderBookError::BelowMinNotional { id, notional, min });
+            }
+            if let Some(max) = self.max_notional.filter(|max| notional > *max) {
+                return Err(OrderBookError::AboveMaxNotional { id, notional, max });
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks an order without a limit price (market or midpoint), which has
+    /// no notional to bound, and returns its aligned quantity. `price` is
+    /// only shown to the risk check.
+    fn validate_unpriced_order(
+        &self,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+        id: Id,
+    ) -> Result<Quantity, OrderBookError> {
+        let (_, quantity) = self.validate_order_fields(0, quantity, id)?;
+        self.check_risk(side, price, quantity, id, None)?;
+        Ok(quantity)
+    }
+
+    /// Checks the rules shared by every placement: session phase, ID
+    /// uniqueness, alignment and the value caps.
+    fn validate_order_fields(
+        &self,
+        price: Price,
+        quantity: Quantity,
+        id: Id,
+    ) -> Result<(Price, Quantity), OrderBookError> {
+        if self.phase == MarketPhase::Closed {
+            return Err(OrderBookError::MarketClosed { id });
+        }
+        if self.id_index.contains_key(&id)
+            || self.midpoint_ids.contains(&id)
+            || self.pending_stops.iter().any(|stop| stop.id == id)
+        {
+            return Err(OrderBookError::DuplicateOrderId(id));
+        }
+        self.validate_terms(price, quantity, id)
+    }
+
+    /// Aligns a price and quantity to the grid and checks them against the
+    /// zero-quantity rule and the value caps.
+    fn validate_terms(
+        &self,
+        price: Price,
+        quantity: Quantity,
+        id: Id,
+    ) -> Result<(Price, Quantity), OrderBookError> {
+        let price = self.align_price(price)?;
+        let quantity = self.align_quantity(quantity)?;
+        if quantity == 0 {
+            return Err(OrderBookError::ZeroQuantity { id, quantity });
+        }
+        if let Some(max) = self.max_price.filter(|max| price > *max) {
+            return Err(OrderBookError::ValueTooLarge { id, value: price, max });
+        }
+        if let Some(max) = self.max_quantity.filter(|max| quantity > *max) {
+            return Err(OrderBookError::ValueTooLarge { id, value: quantity, max });
+        }
+        Ok((price, quantity))
+    }
+
+    /// Aligns `price` to the instrument's tick size per the alignment policy.
+    fn align_price(&self, price: Price) -> Result<Price, OrderBookError> {
+        let tick = self.instrument.tick_size;
+        match self.alignment_policy {
+            AlignmentPolicy::Reject if !price.is_multiple_of(tick) => {
+                Err(OrderBookError::Misaligned { value: price, step: tick })
+            }
+            AlignmentPolicy::Reject => Ok(price),
+            AlignmentPolicy::Round => Ok(snap_to_tick(price, tick)),
+        }
+    }
+
+    /// Aligns `quantity` to the instrument's lot size per the alignment policy,
+    /// rounding down under `AlignmentPolicy::Round`.
+    fn align_quantity(&self, quantity: Quantity) -> Result<Quantity, OrderBookError> {
+        let lot = self.instrument.lot_size;
+        match self.alignment_policy {
+            AlignmentPolicy::Reject if !quantity.is_multiple_of(lot) => {
+                Err(OrderBookError::Misaligned { value: quantity, step: lot })
+            }
+            AlignmentPolicy::Reject => Ok(quantity),
+            AlignmentPolicy::Round => Ok(quantity - quantity % lot),
+        }
+    }
+
+    /// Stamps and counts a newly accepted order, then executes it.
+    fn execute_new_order(
+        &mut self,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+        id: Id,
+        tag: Option<u32>,
+    ) -> Trades {
+        let order = self.stamp_order(side, price, quantity, id).with_tag(tag);
+        self.stats.orders_placed += 1;
+        self.execute_order(order)
+    }
+
+    /// Builds an order stamped with the next timestamp in the sequence.
+    fn stamp_order(&mut self, side: Side, price: Price, quantity: Quantity, id: Id) -> Order {
+        let timestamp = self.next_timestamp;
+        self.next_timestamp += 1;
+        Order::new(id, side, price, quantity, timestamp)
+    }
+
+    /// Matches a validated, timestamped order and rests any remainder in the book.
+    fn execute_order(&mut self, incoming_order: Order) -> Trades {
+        self.execute_order_detailed(incoming_order).trades
+    }
+
+    /// Like `execute_order`, also reporting whether the fill cap cut matching short.
+    fn execute_order_detailed(&mut self, mut incoming_order: Order) -> PlaceResult {
+        let Order { id, side, price, timestamp, time_in_force, .. } = incoming_order;
+        self.report_accepted(&incoming_order, OrderKind::Limit);
+
+        self.expire_gtd_orders_before(timestamp);
+        let mut trades = match self.phase {
+            MarketPhase::PreOpen => Vec::new(),
+            _ => self.match_incoming_order(&mut incoming_order),
+        };
+        self.record_trades(&mut trades);
+
+        // A remainder still crossing the book after the cap must not rest
+        let truncated = incoming_order.quantity > 0
+            && self.max_fills_per_order.is_some_and(|cap| trades.len() >= cap)
+            && self.phase != MarketPhase::PreOpen
+            && self.is_marketable(side, price);
+        let resting = incoming_order.quantity > 0 && !truncated;
+        // Icebergs trade their full size on arrival but rest only a slice
+        if incoming_order.display_quantity > 0
+            && incoming_order.quantity > incoming_order.display_quantity
+        {
+            incoming_order.hidden_remaining =
+                incoming_order.quantity - incoming_order.display_quantity;
+            incoming_order.quantity = incoming_order.display_quantity;
+        }
+        if resting {
+            self.add_order_to_book(incoming_order);
+            self.id_index.insert(id, (side, price));
+            if let TimeInForce::Gtd(expiry) = time_in_force {
+                self.gtd_expiries.insert((expiry, id));
+            }
+        } else {
+            self.fill_milestones.remove(&id);
+        }
+
+        self.operation_count += 1;
+        if resting && self.expire_after_ops.is_some() {
+            self.expiry_queue.push_back(PendingExpiry {
+                placed_at_op: self.operation_count,
+                id,
+                side,
+                price,
+            });
+            self.latest_expiry.insert(id, self.operation_count);
+        }
+        self.sweep_expired_by_ops();
+        self.assert_cache_consistent();
+        self.record_bbo();
+        self.publish_l2();
+
+        if let Some(last) = trades.last() {
+            let cascade = self.trigger_stops(last.price);
+            trades.extend(cascade);
+        }
+        // Looked up last, as stops triggered above may have traded against it
+        let resting = if resting { self.get_order(id).cloned() } else { None };
+        PlaceResult { trades, truncated, resting }
+    }
+
+    /// Removes every resting good-till-date order expiring at or before `now`.
+    ///
+    /// Cleans up emptied levels, the ID index and the best-price caches, and
+    /// returns the removed orders in expiry order. The book drops orders
+    /// whose expiry lies before an incoming order's timestamp on its own;
+    /// call this to expire orders against an outside clock instead.
+    pub fn expire_orders(&mut self, now: Timestamp) -> Vec<Order> {
+        self.record_journal(JournalEntry::Expired(now));
+        let expired = self.expire_gtd_orders_through(now);
+        self.assert_cache_consistent();
+        self.record_bbo();
+        self.publish_l2();
+        expired
+    }
+
+    /// Removes good-till-date orders that expired before sequence number `now`.
+    fn expire_gtd_orders_before(&mut self, now: Timestamp) {
+        if let Some(last) = now.checked_sub(1) {
+            self.expire_gtd_orders_through(last);
+        }
+    }
+
+    /// Removes good-till-date orders expiring at or before `now`.
+    fn expire_gtd_orders_through(&mut self, now: Timestamp) -> Vec<Order> {
+        let mut expired = Vec::new();
+        while let Some(&(expiry, id)) = self.gtd_expiries.first() {
+            if expiry > now {
+                break;
+            }
+            self.gtd_expiries.pop_first();
+            // The order may already have been filled or cancelled, and its ID reused
+            let Some(&(side, price)) = self.id_index.get(&id) else {
+                continue;
+            };
+            let book_side = match side {
+                Side::Buy => &self.buy_side,
+                Side::Sell => &self.sell_side,
+            };
+            let index = book_side[&price]
+                .orders
+                .iter()
+                .position(|o| o.id == id && o.time_in_force == TimeInForce::Gtd(expiry));
+            if let Some(index) = index {
+                expired.push(self.remove_resting_order(side, price, index));
+            }
+        }
+        expired
+    }
+
+    /// Removes every resting day order, at the close of the session.
+    fn expire_day_orders(&mut self) {
+        let expired: Vec<(Side, Price, Id)> = self
+            .buy_side
+            .values()
+            .chain(self.sell_side.values())
+            .flat_map(|level| level.orders.iter())
+            .filter(|o| o.time_in_force == TimeInForce::Day)
+            .map(|o| (o.side, o.price, o.id))
+            .collect();
+        for (side, price, id) in expired {
+            let book_side = match side {
+                Side::Buy => &self.buy_side,
+                Side::Sell => &self.sell_side,
+            };
+            let index = book_side[&price]
+                .orders
+                .iter()
+                .position(|o| o.id == id)
+                .expect("collected order rests at its level");
+            self.remove_resting_order(side, price, index);
+        }
+    }
+
+    /// Removes resting orders whose operation-count lifetime has elapsed.
+    fn sweep_expired_by_ops(&mut self) {
+        let Some(ops) = self.expire_after_ops else {
+            return;
+        };
+        while let Some(pending) = self.expiry_queue.front().copied() {
+            if self.operation_count - pending.placed_at_op < ops {
+                break;
+            }
+            self.expiry_queue.pop_front();
+            // A later entry for the ID means this order left the book and the
+            // ID was reused, or the order was repriced and tracked afresh
+            if self.latest_expiry.get(&pending.id) != Some(&pending.placed_at_op) {
+                continue;
+            }
+            self.latest_expiry.remove(&pending.id);
+            let book_side = match pending.side {
+                Side::Buy => &self.buy_side,
+                Side::Sell => &self.sell_side,
+            };
+            // The order may already have been filled or cancelled
+            let index = book_side
+                .get(&pending.price)
+                .and_then(|level| level.orders.iter().position(|o| o.id == pending.id));
+            if let Some(index) = index {
+                self.remove_resting_order(pending.side, pending.price, index);
+            }
+        }
+    }
+
+    /// Cancels the resting order with the given ID.
+    ///
+    /// The order is located through the ID index, so no level scan across the
+    /// book is needed. Removing it updates the level's total quantity, drops
+    /// the level if it empties and refreshes the best-price cache when the
+    /// order was at the top of book. Orders resting in the midpoint pool and
+    /// pending stop-limit orders can be cancelled the same way.
+    ///
+    /// # Returns
+    ///
+    /// The removed order, or `OrderBookError::UnknownOrderId` if no order with
+    /// this ID is resting or pending (it was never placed, already filled or
+    /// cancelled). A pending stop is returned as the limit order it would
+    /// have become, with timestamp 0 as it was never stamped.
+    pub fn cancel_order(&mut self, id: Id) -> Result<Order, OrderBookError> {
+        if self.midpoint_ids.contains(&id) {
+            return self.cancel_midpoint_order(id);
+        }
+        if let Some(index) = self.pending_stops.iter().position(|stop| stop.id == id) {
+            return Ok(self.cancel_pending_stop(index));
+        }
+        let &(side, price) = self
+            .id_index
+            .get(&id)
+            .ok_or(OrderBookError::UnknownOrderId(id))?;
+        let book_side = match side {
+            Side::Buy => &self.buy_side,
+            Side::Sell => &self.sell_side,
+        };
+        let index = book_side[&price]
+            .orders
+            .iter()
+            .position(|o| o.id == id)
+            .expect("indexed order rests at its level");
+        self.cancel_resting_order(side, price, index)
+    }
+
+    /// Withdraws the pending stop at `index`, freeing its ID.
+    fn cancel_pending_stop(&mut self, index: usize) -> Order {
+        let StopOrder { id, side, limit_price, quantity, .. } = self.pending_stops.remove(index);
+        self.report_cancelled(id);
+
+        self.stats.orders_cancelled += 1;
+        self.operation_count += 1;
+        self.sweep_expired_by_ops();
+        self.assert_cache_consistent();
+        self.record_bbo();
+        self.publish_l2();
+        Order::new(id, side, limit_price, quantity, 0)
+    }
+
+    /// Cancels an order resting in one of the midpoint pools.
+    fn cancel_midpoint_order(&mut self, id: Id) -> Result<Order, OrderBookError> {
+        let (side, index) = [(Side::Buy, &self.midpoint_buys), (Side::Sell, &self.midpoint_sells)]
+            .into_iter()
+            .find_map(|(side, pool)| pool.iter().position(|o| o.id == id).map(|i| (side, i)))
+            .expect("midpoint id rests in a pool");
+        let pool = match side {
+            Side::Buy => &self.midpoint_buys,
+            Side::Sell => &self.midpoint_sells,
+        };
+        self.check_min_resting_time(&pool[index])?;
+        let pool = match side {
+            Side::Buy => &mut self.midpoint_buys,
+            Side::Sell => &mut self.midpoint_sells,
+        };
+        let order = pool.remove(index).expect("order exists");
+        self.midpoint_ids.remove(&id);
+        self.report_cancelled(id);
+
+        self.stats.orders_cancelled += 1;
+        self.operation_count += 1;
+        self.sweep_expired_by_ops();
+        self.assert_cache_consistent();
+        self.record_bbo();
+        self.publish_l2();
+        Ok(order)
+    }
+
+    /// Changes the quantity of a resting order.
+    ///
+    /// The new quantity is validated like a fresh placement at the order's
+    /// price: it is aligned to the lot per the alignment policy and checked
+    /// against the quantity cap and the notional bounds. For an iceberg it is
+    /// the total remaining size; the visible slice is kept, capped at the new
+    /// total, and the rest becomes the reserve.
+    ///
+    /// Reducing the quantity keeps the order's place in its level's queue.
+    /// Increasing it costs time priority: the order moves to the back of the
+    /// queue and receives a fresh timestamp, as if it had just been placed.
+    /// Its operation-count expiry, if any, still runs from the original
+    /// placement.
+    ///
+    /// # Returns
+    ///
+    /// `OrderBookError::UnknownOrderId` if the order is not resting in the
+    /// lit book, or the error a new placement of `new_quantity` at the
+    /// order's price would get, e.g. `OrderBookError::ZeroQuantity` (cancel
+    /// instead)
+    pub fn amend_quantity(&mut self, id: Id, new_quantity: Quantity) -> Result<(), OrderBookError> {
+        let &(side, price) = self
+            .id_index
+            .get(&id)
+            .ok_or(OrderBookError::UnknownOrderId(id))?;
+        let account = self.get_order(id).and_then(|order| order.account);
+        let (_, new_quantity) = self.validate_amendment(side, price, new_quantity, id, account)?;
+        let book_side = match side {
+            Side::Buy => &mut self.buy_side,
+            Side::Sell => &mut self.sell_side,
+        };
+        let level = book_side.get_mut(&price).expect("indexed level exists");
+        let index = level
+            .orders
+            .iter()
+            .position(|o| o.id == id)
+            .expect("indexed order rests at its level");
+
+        let resting = &level.orders[index];
+        let visible = match resting.display_quantity {
+            0 => new_quantity,
+            _ => resting.quantity.min(new_quantity),
+        };
+        if new_quantity <= resting.quantity + resting.hidden_remaining {
+            level.update_order_quantity(index, visible);
+            level.orders[index].hidden_remaining = new_quantity - visible;
+        } else {
+            let mut order = level.remove_order_at(index).expect("order exists");
+            order.quantity = visible;
+            order.hidden_remaining = new_quantity - visible;
+            order.timestamp = self.next_timestamp;
+            self.next_timestamp += 1;
+            level.add_order(order);
+        }
+
+        match side {
+            Side::Buy => self.set_best_buy(),
+            Side::Sell => self.update_cached_best_sell(),
+        }
+        self.stats.orders_amended += 1;
+        self.record_journal(JournalEntry::QuantityAmended { id, quantity: new_quantity });
+        self.assert_cache_consistent();
+        self.record_bbo();
+        self.publish_l2();
+        Ok(())
+    }
+
+    /// Moves a resting order to a new price as a cancel-and-replace.
+    ///
+    /// The order is removed from its current level and resubmitted with its
+    /// side, remaining quantity and tag at `new_price`, receiving a fresh
+    /// timestamp. If the new price crosses the opposite side it trades like
+    /// a new order; only a remainder rests and stays addressable by `id`.
+    /// The order always loses time priority, even at an unchanged price.
+    ///
+    /// # Returns
+    ///
+    /// The trades produced at the new price, `OrderBookError::UnknownOrderId`
+    /// if the order is not resting in the lit book,
+    /// `OrderBookError::MinRestingTimeNotMet` if it could not yet be
+    /// cancelled, or the same errors a new placement of its remaining
+    /// quantity at `new_price` would get (closed market, tick alignment,
+    /// price cap, notional bounds, `OrderBookError::PriceGapTooLarge` measured
+    /// from the best price on its side without the order)
+    pub fn amend_price(&mut self, id: Id, new_price: Price) -> Result<Trades, OrderBookError> {
+        let &(side, price) = self
+            .id_index
+            .get(&id)
+            .ok_or(OrderBookError::UnknownOrderId(id))?;
+        let book_side = match side {
+            Side::Buy => &self.buy_side,
+            Side::Sell => &self.sell_side,
+        };
+        let index = book_side[&price]
+            .orders
+            .iter()
+            .position(|o| o.id == id)
+            .expect("indexed order rests at its level");
+        let resting = &book_side[&price].orders[index];
+        self.check_min_resting_time(resting)?;
+        let remaining = resting.quantity + resting.hidden_remaining;
+        let account = resting.account;
+        let (new_price, _) = self.validate_amendment(side, new_price, remaining, id, account)?;
+        let alone = book_side[&price].orders.len() == 1;
+        let best = match side {
+            Side::Buy => book_side.keys().rev().find(|&&p| p != price || !alone),
+            Side::Sell => book_side.keys().find(|&&p| p != price || !alone),
+        };
+        self.check_price_gap_from(side, new_price, id, best.copied())?;
+
+        // The repriced order keeps its fill progress
+        let milestone = self.fill_milestones.get(&id).copied();
+        let order = self.remove_resting_order(side, price, index);
+        if let Some(milestone) = milestone {
+            self.fill_milestones.insert(id, milestone);
+        }
+        self.stats.orders_amended += 1;
+        self.record_journal(JournalEntry::PriceAmended { id, price: new_price });
+        let replacement = self
+            .stamp_order(side, new_price, remaining, id)
+            .with_tag(order.tag)
+            .with_account(order.account)
+            .with_time_in_force(order.time_in_force)
+            .with_display_quantity(order.display_quantity)
+            .with_random_peak(order.random_peak);
+        Ok(self.execute_order(replacement))
+    }
+
+    /// Cancels a resting order identified by its attributes rather than its ID.
+    ///
+    /// This is a best-effort cancel for feeds that do not carry order IDs. It
+    /// removes the oldest resting order on `side` at `price` whose remaining
+    /// quantity is exactly `quantity`. When several identical orders rest at
+    /// that price, FIFO order breaks the tie: the one with time priority is
+    /// cancelled and the others are left intact.
+    ///
+    /// # Returns
+    ///
+    /// The removed order, or `OrderBookError::NoMatchingOrder` if none matches
+    pub fn cancel_by_attributes(
+        &mut self,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+    ) -> Result<Order, OrderBookError> {
+        let book_side = match side {
+            Side::Buy => &self.buy_side,
+            Side::Sell => &self.sell_side,
+        };
+        let level = book_side
+            .get(&price)
+            .ok_or(OrderBookError::NoMatchingOrder { side, price, quantity })?;
+        let index = level
+            .orders
+            .iter()
+            .position(|o| o.quantity == quantity)
+            .ok_or(OrderBookError::NoMatchingOrder { side, price, quantity })?;
+        self.cancel_resting_order(side, price, index)
+    }
+
+    /// Cancels the order `id`, which must rest at the best price on `side`.
+    ///
+    /// A fast path for requoting market makers: only the cached best level is
+    /// searched, so no ID lookup across the book is needed.
+    ///
+    /// # Returns
+    ///
+    /// The removed order, `OrderBookError::NotAtBest` if the order rests
+    /// deeper in the book or on the other side, or
+    /// `OrderBookError::UnknownOrderId` if it is not resting at all
+    pub fn cancel_best(&mut self, side: Side, id: Id) -> Result<Order, OrderBookError> {
+        let (book_side, best) = match side {
+            Side::Buy => (&self.buy_side, self.best_buy),
+            Side::Sell => (&self.sell_side, self.best_sell),
+        };
+        let located = best.and_then(|(price, _)| {
+            let level = book_side.get(&price)?;
+            let index = level.orders.iter().position(|o| o.id == id)?;
+            Some((price, index))
+        });
+        let Some((price, index)) = located else {
+            return Err(if self.id_index.contains_key(&id) {
+                OrderBookError::NotAtBest { id, side }
+            } else {
+                OrderBookError::UnknownOrderId(id)
+            });
+        };
+        self.cancel_resting_order(side, price, index)
+    }
+
+    /// Cancels the located resting order, enforcing the minimum resting time.
+    fn cancel_resting_order(
+        &mut self,
+        side: Side,
+        price: Price,
+        index: usize,
+    ) -> Result<Order, OrderBookError> {
+        let book_side = match side {
+            Side::Buy => &self.buy_side,
+            Side::Sell => &self.sell_side,
+        };
+        self.check_min_resting_time(&book_side[&price].orders[index])?;
+
+        self.stats.orders_cancelled += 1;
+        let order = self.remove_resting_order(side, price, index);
+        self.report_cancelled(order.id);
+        self.operation_count += 1;
+        self.sweep_expired_by_ops();
+        self.assert_cache_consistent();
+        self.record_bbo();
+        self.publish_l2();
+        Ok(order)
+    }
+
+    /// Removes every resting order, lit and midpoint, and every pending stop
+    /// in one step.
+    ///
+    /// Level-removed callbacks are not fired for the dropped levels; instead
+    /// the cleared callback fires exactly once, after the book is empty.
+    /// Configuration, the session phase, statistics, trade history and the
+    /// timestamp sequence are kept.
+    pub fn clear(&mut self) {
+        self.record_journal(JournalEntry::Cleared);
+        self.buy_side.clear();
+        self.sell_side.clear();
+        self.id_index.clear();
+        self.midpoint_buys.clear();
+        self.midpoint_sells.clear();
+        self.midpoint_ids.clear();
+        self.expiry_queue.clear();
+        self.latest_expiry.clear();
+        self.gtd_expiries.clear();
+        self.pending_stops.clear();
+        self.fill_milestones.clear();
+        self.recompute_caches();
+        self.record_bbo();
+        self.publish_l2();
+        if let Some(callback) = self.on_cleared.as_mut() {
+            callback();
+        }
+    }
+
+    /// Returns the price of the most recent trade, `None` before any trade.
+    pub fn last_trade_price(&self) -> Option<Price> {
+        self.last_trade_price
+    }
+
+    /// Returns the open, high, low, close and volume of trades since the
+    /// book was created or [`OrderBook::reset_ohlc`] was last called.
+    pub fn ohlc(&self) -> &OhlcAccumulator {
+        &self.ohlc
+    }
+
+    /// Starts a new OHLC window, e.g. at the start of each ticker interval.
+    pub fn reset_ohlc(&mut self) {
+        self.ohlc.reset();
+    }
+
+    /// Returns the cumulative activity counters for this book.
+    pub fn stats(&self) -> &BookStats {
+        &self.stats
+    }
+
+    /// Returns the retained trade history, oldest first.
+    ///
+    /// Empty unless the book was built with [`OrderBook::with_trade_history`].
+    pub fn trade_history(&self) -> &VecDeque<Trade> {
+        &self.trade_history
+    }
+
+    /// Returns the journal, if the book was built with [`OrderBook::with_journal`].
+    pub fn journal(&self) -> Option<&TradeJournal> {
+        self.journal.as_ref()
+    }
+
+    /// Returns the retained audit records, oldest first.
+    ///
+    /// Empty unless the book was built with [`OrderBook::with_audit_trail`].
+    pub fn audit_trail(&self) -> &VecDeque<AuditRecord> {
+        &self.audit_trail
+    }
+
+    /// Returns the trades in which `taker_id` was the taker, in execution order.
+    ///
+    /// Empty unless the book was built with
+    /// [`OrderBook::with_taker_trade_index`], and for takers that produced no
+    /// trades or have been evicted from the bounded index.
+    pub fn trades_for_order(&self, taker_id: Id) -> Vec<Trade> {
+        self.taker_trades.get(&taker_id).cloned().unwrap_or_default()
+    }
+
+    /// Returns the retained best-price samples, oldest first.
+    ///
+    /// Empty unless the book was built with [`OrderBook::with_bbo_history`].
+    pub fn bbo_history(&self) -> &VecDeque<BboSample> {
+        &self.bbo_history
+    }
+
+    /// Returns the time-weighted average mid price over the last `lookback` time units.
+    ///
+    /// Time is the logical timestamp sequence; the window ends at the current
+    /// sequence number (see [`OrderBook::depth_with_sequence`]). Each sample's
+    /// mid holds from its timestamp until the next sample, and the latest one
+    /// until now. Intervals where either side was empty have no mid and are
+    /// left out of the average, as is any part of the window older than the
+    /// retained history. The result is in quote minor units.
+    ///
+    /// # Returns
+    ///
+    /// `None` if no mid was defined for any time inside the window
+    pub fn twa_mid(&self, lookback: Timestamp) -> Option<Decimal> {
+        let now = self.next_timestamp;
+        let window_start = now.saturating_sub(lookback);
+        let mut weighted = Decimal::ZERO;
+        let mut duration: Timestamp = 0;
+        let ends = self.bbo_history.iter().skip(1).map(|s| s.timestamp).chain([now]);
+        for (sample, end) in self.bbo_history.iter().zip(ends) {
+            let start = sample.timestamp.max(window_start);
+            let (Some(mid), true) = (sample.mid_price(), end > start) else {
+                continue;
+            };
+            let span = end - start;
+            weighted = weighted.checked_add(
+                Decimal::from_u128(mid)?.checked_mul(Decimal::from(span))?,
+            )?;
+            duration += span;
+        }
+        if duration == 0 {
+            return None;
+        }
+        weighted.checked_div(Decimal::from(duration))
+    }
+
+    /// Returns the traded volume at each execution price over the retained history.
+    ///
+    /// Only trades still held in the bounded history are counted, so on a
+    /// long session this covers the most recent trades rather than the whole
+    /// session.
+    pub fn volume_profile(&self) -> BTreeMap<Price, Quantity> {
+        let mut profile = BTreeMap::new();
+        for trade in &self.trade_history {
+            *profile.entry(trade.price).or_insert(0) += trade.quantity;
+        }
+        profile
+    }
+
+    /// Returns the best (highest) buy price and total quantity at that level.
+    ///
+    /// # Returns
+    ///
+    /// `Some(PriceAndQuantity)` if buy orders exist, `None` otherwise
+    pub fn best_buy(&self) -> Option<PriceAndQuantity> {
+        self.best_buy
+    }
+
+    /// Returns the best (lowest) sell price and total quantity at that level.
+    ///
+    /// # Returns
+    ///
+    /// `Some(PriceAndQuantity)` if sell orders exist, `None` otherwise
+    pub fn best_sell(&self) -> Option<PriceAndQuantity> {
+        self.best_sell
+    }
+
+    /// Returns the midpoint between the best buy and best sell prices.
+    ///
+    /// The midpoint is floored to whole minor units.
+    ///
+    /// # Returns
+    ///
+    /// `Some(Price)` if both sides have orders, `None` otherwise
+    pub fn mid_price(&self) -> Option<Price> {
+        let (bid, _) = self.best_buy?;
+        let (ask, _) = self.best_sell?;
+        Some(bid / 2 + ask / 2 + (bid % 2 + ask % 2) / 2)
+    }
+
+    /// Returns the midpoint rounded onto the instrument's tick grid.
+    ///
+    /// Unlike [`OrderBook::mid_price`], the result is always a price an order
+    /// could rest at. The rounding is applied to the exact mid, so a mid
+    /// falling halfway between two ticks goes to the upper one under
+    /// `HalfUp` and `Ceil`, and to the lower one under `Floor` and `Truncate`.
+    ///
+    /// # Returns
+    ///
+    /// `Some(Price)` if both sides have orders, `None` otherwise
+    pub fn mid_price_on_tick(&self, rounding: RoundingMode) -> Option<Price> {
+        let (bid, _) = self.best_buy?;
+        let (ask, _) = self.best_sell?;
+        // Snap the doubled mid onto a doubled grid so half-unit mids stay exact
+        let (sum, grid) = (bid.checked_add(ask)?, self.instrument.tick_size.checked_mul(2)?);
+        Some(snap_to_tick_with(sum, grid, rounding) / 2)
+    }
+
+    /// Returns the spread as a fraction of the midpoint, e.g. `0.02` for 2%.
+    ///
+    /// Both spread and midpoint are taken exactly from the best prices, so
+    /// the result does not depend on the quote asset's decimals. Negative
+    /// while the book is crossed in `PreOpen`.
+    ///
+    /// # Returns
+    ///
+    /// `None` if either side is empty or the midpoint is zero
+    pub fn relative_spread(&self) -> Option<Decimal> {
+        let (bid, _) = self.best_buy?;
+        let (ask, _) = self.best_sell?;
+        let bid = Decimal::from_u128(bid)?;
+        let ask = Decimal::from_u128(ask)?;
+        let mid = (bid + ask) / Decimal::TWO;
+        if mid.is_zero() {
+            return None;
+        }
+        (ask - bid).checked_div(mid)
+    }
+
+    /// Selects the estimator used by [`OrderBook::fair_value`].
+    ///
+    /// Built-in estimators live in [`crate::fair_value`]; a closure taking
+    /// the book works as well. The default is [`crate::fair_value::Mid`].
+    pub fn set_fair_value_estimator(&mut self, estimator: impl FairValueEstimator + 'static) {
+        self.fair_value_estimator = Box::new(estimator);
+    }
+
+    /// Returns the book's fair value from the selected estimator, in quote
+    /// minor units.
+    pub fn fair_value(&self) -> Option<Decimal> {
+        self.fair_value_estimator.estimate(self)
+    }
+
+    /// Returns true if an order of `side` at `price` would trade immediately.
+    ///
+    /// A buy is marketable at or above the best sell, a sell at or below the
+    /// best buy. Only the cached best prices are consulted, so this is O(1).
+    /// Returns false when the opposite side is empty.
+    pub fn is_marketable(&self, side: Side, price: Price) -> bool {
+        match side {
+            Side::Buy => self.best_sell.is_some_and(|(ask, _)| price >= ask),
+            Side::Sell => self.best_buy.is_some_and(|(bid, _)| price <= bid),
+        }
+    }
+
+    /// Returns market depth information for the specified side.
+    ///
+    /// For buy side, returns prices in descending order (best first).
+    /// For sell side, returns prices in ascending order (best first).
+    ///
+    /// # Arguments
+    ///
+    /// * `side` - Which side of the book to query
+    /// * `levels` - Maximum number of price levels to return
+    ///
+    /// # Returns
+    ///
+    /// Vector of (price, total_quantity) tuples
+    #[allow(dead_code)]
+    pub fn depth(&self, side: Side, levels: usize) -> Vec<PriceAndQuantity> {
+        let entry = |level: &PriceLevel| (level.price, level.total_quantity);
+        // Each direction gets its own concrete iterator, avoiding a boxed trait object
+        match side {
+            Side::Buy => self.buy_side.values().rev().take(levels).map(entry).collect(),
+            Side::Sell => self.sell_side.values().take(levels).map(entry).collect(),
+        }
+    }
+
+    /// Returns every price level on `side` as `(price, total_quantity)`, best first.
+    ///
+    /// Unlike [`OrderBook::depth`] this is not capped and does not allocate.
+    pub fn price_levels(&self, side: Side) -> impl Iterator<Item = PriceAndQuantity> + '_ {
+        // Only one of the two is non-empty; chaining them keeps a single concrete type
+        let (bids, asks) = match side {
+            Side::Buy => (Some(self.buy_side.values().rev()), None),
+            Side::Sell => (None, Some(self.sell_side.values())),
+        };
+        bids.into_iter()
+            .flatten()
+            .chain(asks.into_iter().flatten())
+            .map(|level| (level.price, level.total_quantity))
+    }
+
+    /// Returns the first `n` resting orders on `side` in price-time priority.
+    ///
+    /// Unlike [`OrderBook::depth`] orders are not aggregated: levels are
+    /// walked best first and each one's queue in FIFO order. Midpoint pool
+    /// orders are not included.
+    pub fn top_orders(&self, side: Side, n: usize) -> Vec<&Order> {
+        match side {
+            Side::Buy => {
+                self.buy_side.values().rev().flat_map(|level| &level.orders).take(n).collect()
+            }
+            Side::Sell => self.sell_side.values().flat_map(|level| &level.orders).take(n).collect(),
+        }
+    }
+
+    /// Returns the number of distinct price levels on `side`.
+    pub fn level_count(&self, side: Side) -> usize {
+        match side {
+            Side::Buy => self.buy_side.len(),
+            Side::Sell => self.sell_side.len(),
+        }
+    }
+
+    /// Returns the top price levels for a side with running quantity totals.
+    ///
+    /// Levels come best first, as from [`OrderBook::depth`]. Each entry is
+    /// `(price, level_quantity, cumulative_quantity)`, where the cumulative
+    /// quantity sums every level from the best price up to and including
+    /// this one.
+    pub fn cumulative_depth(&self, side: Side, levels: usize) -> Vec<(Price, Quantity, Quantity)> {
+        self.depth(side, levels)
+            .into_iter()
+            .scan(0 as Quantity, |cumulative, (price, quantity)| {
+                *cumulative = cumulative.saturating_add(quantity);
+                Some((price, quantity, *cumulative))
+            })
+            .collect()
+    }
+
+    /// Returns the quantity-weighted average price of the top `levels` levels on `side`.
+    ///
+    /// Computes `sum(price * quantity) / sum(quantity)` over the levels
+    /// returned by [`OrderBook::depth`]. The result is in quote minor units,
+    /// like every other price, but keeps the fractional part of the average.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the side is empty, `levels` is 0, or the sum overflows
+    pub fn weighted_price(&self, side: Side, levels: usize) -> Option<Decimal> {
+        let mut weighted: u128 = 0;
+        let mut total: Quantity = 0;
+        for (price, quantity) in self.depth(side, levels) {
+            weighted = weighted.checked_add(price.checked_mul(quantity)?)?;
+            total = total.checked_add(quantity)?;
+        }
+        if total == 0 {
+            return None;
+        }
+        Decimal::from_u128(weighted)?.checked_div(Decimal::from_u128(total)?)
+    }
+
+    /// Returns the notional imbalance over the top `levels` levels of each side.
+    ///
+    /// Computes `(bid_notional - ask_notional) / (bid_notional + ask_notional)`,
+    /// where each level contributes `price * total_quantity`. The result lies
+    /// in `[-1, 1]`: positive when bids carry more value, negative when asks
+    /// do. Unlike a quantity imbalance, levels far from the touch count in
+    /// proportion to their price.
+    ///
+    /// # Returns
+    ///
+    /// `None` if both sides are empty within the window, or on overflow
+    pub fn notional_imbalance(&self, levels: usize) -> Option<Decimal> {
+        let side_notional = |side: Side| -> Option<u128> {
+            self.depth(side, levels).into_iter().try_fold(0u128, |sum, (price, quantity)| {
+                sum.checked_add(price.checked_mul(quantity)?)
+            })
+        };
+        // The ratio is scale-free, so raw price * quantity needs no rescaling
+        let bid = Decimal::from_u128(side_notional(Side::Buy)?)?;
+        let ask = Decimal::from_u128(side_notional(Side::Sell)?)?;
+        let total = bid.checked_add(ask)?;
+        if total.is_zero() {
+            return None;
+        }
+        bid.checked_sub(ask)?.checked_div(total)
+    }
+
+    /// Returns market depth stamped with the book's current sequence number.
+    ///
+    /// The sequence is the logical timestamp the next accepted order will
+    /// receive, so it strictly increases with every placement. Feed consumers
+    /// can use it to order and deduplicate depth snapshots.
+    pub fn depth_with_sequence(
+        &self,
+        side: Side,
+        levels: usize,
+    ) -> (Timestamp, Vec<PriceAndQuantity>) {
+        (self.next_timestamp, self.depth(side, levels))
+    }
+
+    /// Returns market depth for the specified side formatted with the book's instrument.
+    ///
+    /// Each level is rendered as `"<qty> <base> @ <price> <quote>"`, in the same
+    /// best-first order as [`OrderBook::depth`].
+    pub fn format_depth(&self, side: Side, levels: usize) -> Vec<String> {
+        self.format_depth_with(side, levels, false)
+    }
+
+    /// Returns market depth for a side formatted with the book's instrument,
+    /// optionally with each level's cumulative quantity.
+    ///
+    /// Without `cumulative` this is [`OrderBook::format_depth`]. With it, each
+    /// level is rendered as `"<qty> <base> @ <price> <quote> (cumulative <qty> <base>)"`
+    /// using the totals from [`OrderBook::cumulative_depth`], and every column
+    /// is right-aligned to a width shared by the top `levels` levels of both
+    /// sides, so bids and asks printed together line up.
+    pub fn format_depth_with(&self, side: Side, levels: usize, cumulative: bool) -> Vec<String> {
+        let columns = |(price, quantity, total): (Price, Quantity, Quantity)| {
+            [
+                format_quantity(quantity, &self.instrument.base),
+                format_price(price, &self.instrument.quote),
+                format_quantity(total, &self.instrument.base),
+            ]
+        };
+        if !cumulative {
+            return self
+                .depth(side, levels)
+                .into_iter()
+                .map(|(price, quantity)| {
+                    format!(
+                        "{} @ {}",
+                        format_quantity(quantity, &self.instrument.base),
+                        format_price(price, &self.instrument.quote)
+                    )
+                })
+                .collect();
+        }
+        let mut widths = [0; 3];
+        for level in [Side::Buy, Side::Sell]
+            .into_iter()
+            .flat_map(|side| self.cumulative_depth(side, levels))
+        {
+            for (width, column) in widths.iter_mut().zip(columns(level)) {
+                *width = (*width).max(column.chars().count());
+            }
+        }
+        self.cumulative_depth(side, levels)
+            .into_iter()
+            .map(|level| {
+                let [quantity, price, total] = columns(level);
+                format!(
+                    "{:>w0$} @ {:>w1$} (cumulative {:>w2$})",
+                    quantity,
+                    price,
+                    total,
+                    w0 = widths[0],
+                    w1 = widths[1],
+                    w2 = widths[2]
+                )
+            })
+            .collect()
+    }
+
+    /// Returns the complete book state for debugging.
+    ///
+    /// Unlike [`OrderBook::depth`] this lists every resting order of every
+    /// level in priority order, and includes the cached best prices so they
+    /// can be checked against the levels.
+    pub fn dump(&self) -> BookDump {
+        let dump_level = |level: &PriceLevel| LevelDump {
+            price: level.price,
+            total_quantity: level.total_quantity,
+            orders: level
+                .orders
+                .iter()
+                .map(|order| OrderDump {
+                    id: order.id,
+                    quantity: order.quantity,
+                    timestamp: order.timestamp,
+                })
+                .collect(),
+        };
+        BookDump {
+            instrument: self.instrument.clone(),
+            buy_levels: self.buy_side.values().rev().map(dump_level).collect(),
+            sell_levels: self.sell_side.values().map(dump_level).collect(),
+            best_buy: self.best_buy,
+            best_sell: self.best_sell,
+            next_timestamp: self.next_timestamp,
+        }
+    }
+
+    /// Captures the resting orders of both sides for persistence.
+    ///
+    /// See [`BookSnapshot`] for what is and is not included.
+    pub fn snapshot(&self) -> BookSnapshot {
+        let snapshot_level =
+            |level: &PriceLevel| (level.price, level.orders.iter().cloned().collect());
+        BookSnapshot {
+            instrument: self.instrument.clone(),
+            buy_side: self.buy_side.values().rev().map(snapshot_level).collect(),
+            sell_side: self.sell_side.values().map(snapshot_level).collect(),
+            next_timestamp: self.next_timestamp,
+        }
+    }
+
+    /// Rebuilds a book from a [`BookSnapshot`].
+    ///
+    /// Orders are restored as-is, without validation or matching, into a book
+    /// with default configuration. The id index, good-til-date expiries and
+    /// cached best prices are rebuilt from the restored levels.
+    pub fn restore(snapshot: BookSnapshot) -> Self {
+        let mut book = Self::new(snapshot.instrument);
+        let sides = [(Side::Buy, snapshot.buy_side), (Side::Sell, snapshot.sell_side)];
+        for (side, levels) in sides {
+            for (price, orders) in levels {
+                let mut level = PriceLevel::new(price);
+                for order in orders {
+                    book.id_index.insert(order.id, (side, price));
+                    if let TimeInForce::Gtd(expiry) = order.time_in_force {
+                        book.gtd_expiries.insert((expiry, order.id));
+                    }
+                    level.add_order(order);
+                }
+                match side {
+                    Side::Buy => book.buy_side.insert(price, level),
+                    Side::Sell => book.sell_side.insert(price, level),
+                };
+            }
+        }
+        book.set_best_buy();
+        book.update_cached_best_sell();
+        book.next_timestamp = snapshot.next_timestamp;
+        book.assert_cache_consistent();
+        book
+    }
+
+    /// Rebuilds a book by re-applying a journal's entries in sequence.
+    ///
+    /// The journaled configuration is applied first. Each order is then
+    /// re-submitted through the entry point it originally took, with its
+    /// original timestamp, and cancels, amendments, stop placements, phase
+    /// changes, auctions, expiries and clears are re-applied through the
+    /// same methods. The rebuilt book's trades (IDs, sequences, timestamps
+    /// and fees included) therefore match the journaled ones. Journaled
+    /// trades are not applied directly; they result from the replayed
+    /// entries, and entries a replayed one records itself, such as a
+    /// repriced order's resubmission or a triggered stop, are skipped. The
+    /// rebuilt book journals too, so the two can be compared.
+    pub fn replay(journal: &TradeJournal, instrument: Instrument) -> Self {
+        let mut book = Self::new(instrument).with_journal();
+        let entries = journal.entries();
+        let mut next = 0;
+        while let Some(entry) = entries.get(next) {
+            match entry {
+                JournalEntry::Configured(config) => book = book.with_config(*config),
+                JournalEntry::Accepted { order, kind } => {
+                    let Order { id, side, price, quantity, timestamp, .. } = *order;
+                    match kind {
+                        OrderKind::Limit => {
+                            // Imported orders may carry a timestamp older than the sequence
+                            book.next_timestamp = book.next_timestamp.max(timestamp + 1);
+                            book.stats.orders_placed += 1;
+                            book.execute_order_detailed(order.clone());
+                        }
+                        OrderKind::Market => {
+                            book.next_timestamp = timestamp;
+                            book.execute_market_order(side, price, quantity, id);
+                        }
+                        OrderKind::Midpoint => {
+                            book.next_timestamp = timestamp;
+                            let mid = book.midpoint_execution_price();
+                            book.execute_midpoint_order(side, quantity, id, mid);
+                        }
+                    }
+                }
+                JournalEntry::Cancelled(id) => {
+                    let _ = book.cancel_order(*id);
+                }
+                JournalEntry::StopPlaced(stop) => {
+                    let StopOrder { id, side, stop_price, limit_price, quantity } = *stop;
+                    let _ = book.place_stop_limit(side, stop_price, limit_price, quantity, id);
+                }
+                JournalEntry::QuantityAmended { id, quantity } => {
+                    let _ = book.amend_quantity(*id, *quantity);
+                }
+                JournalEntry::PriceAmended { id, price } => {
+                    let _ = book.amend_price(*id, *price);
+                }
+                JournalEntry::PhaseChanged(phase) => {
+                    book.set_phase(*phase);
+                }
+                JournalEntry::Uncrossed => {
+                    book.uncross();
+                }
+                JournalEntry::Expired(now) => {
+                    book.expire_orders(*now);
+                }
+                JournalEntry::Cleared => book.clear(),
+                JournalEntry::Trade(_) => {}
+            }
+            let replayed = book.journal.as_ref().map_or(0, TradeJournal::len);
+            next = replayed.max(next + 1);
+        }
+        book
+    }
+
+    /// Returns the limit price an order of `side` needs to fill `target_quantity` immediately.
+    ///
+    /// Walks the opposite side (asks for a buy, bids for a sell) from the best
+    /// price outward and returns the price of the level at which cumulative
+    /// quantity first reaches `target_quantity`, i.e. the marginal price of
+    /// the fill.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the opposite side cannot supply `target_quantity`
+    pub fn price_for_quantity(&self, side: Side, target_quantity: Quantity) -> Option<Price> {
+        let reach = |levels: &mut dyn Iterator<Item = &PriceLevel>| {
+            let mut cumulative: Quantity = 0;
+            for level in levels {
+                cumulative = cumulative.saturating_add(level.total_quantity);
+                if cumulative >= target_quantity {
+                    return Some(level.price);
+                }
+            }
+            None
+        };
+        match side {
+            Side::Buy => reach(&mut self.sell_side.values()),
+            Side::Sell => reach(&mut self.buy_side.values().rev()),
+        }
+    }
+
+    /// Returns the average execution price of a hypothetical `side` order
+    /// for `quantity`, without mutating the book.
+    ///
+    /// Walks the opposite side best-first (asks for a buy, bids for a sell)
+    /// and weights each level's price by the quantity taken from it. The
+    /// weighting is done in integer minor units and the average is floored
+    /// to whole quote minor units.
+    ///
+    /// # Returns
+    ///
+    /// The average price and the quantity actually available, which is less
+    /// than `quantity` on a thin book, or `None` if nothing is available
+    pub fn vwap(&self, side: Side, quantity: Quantity) -> Option<(Price, Quantity)> {
+        let (filled, raw_notional) = self.preview_sweep(side, quantity)?;
+        if filled == 0 {
+            return None;
+        }
+        Some((raw_notional / filled, filled))
+    }
+
+    /// Returns the cost of buying `quantity` and immediately selling it back.
+    ///
+    /// Sweeps the asks to buy `quantity` and the bids to sell it, without
+    /// mutating the book, and returns the difference between the two notionals
+    /// in quote minor units (truncated). This is the slippage paid for crossing
+    /// the spread both ways.
+    ///
+    /// # Returns
+    ///
+    /// `None` if either side cannot supply `quantity`
+    pub fn round_trip_cost(&self, quantity: Quantity) -> Option<Price> {
+        let (bought, buy_notional) = self.preview_sweep(Side::Buy, quantity)?;
+        let (sold, sell_notional) = self.preview_sweep(Side::Sell, quantity)?;
+        if bought < quantity || sold < quantity {
+            return None;
+        }
+        scale_notional(buy_notional.saturating_sub(sell_notional), &self.instrument.base)
+    }
+
+    /// Recomputes the cached best buy and best sell from the price levels.
+    ///
+    /// Every public mutation keeps the caches current, so this is only needed
+    /// after tooling has edited the levels directly (bulk imports or repairs
+    /// done inside the crate). It is cheap: each side costs one lookup.
+    pub fn recompute_caches(&mut self) {
+        self.set_best_buy();
+        self.update_cached_best_sell();
+    }
+
+    /// Returns true if the order book has no orders on either side.
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.buy_side.is_empty() && self.sell_side.is_empty()
+    }
+
+    /// Returns the resting order with this ID, including its remaining
+    /// quantity and timestamp.
+    ///
+    /// Orders in the midpoint pools are found as well. Returns `None` for
+    /// orders that were never placed, or have been filled or cancelled.
+    pub fn get_order(&self, id: Id) -> Option<&Order> {
+        if self.midpoint_ids.contains(&id) {
+            return self.midpoint_buys.iter().chain(&self.midpoint_sells).find(|o| o.id == id);
+        }
+        let &(side, price) = self.id_index.get(&id)?;
+        let book_side = match side {
+            Side::Buy => &self.buy_side,
+            Side::Sell => &self.sell_side,
+        };
+        book_side.get(&price)?.orders.iter().find(|o| o.id == id)
+    }
+
+    /// Returns true if an order with this ID is resting in the book.
+    pub fn contains_order(&self, id: Id) -> bool {
+        self.id_index.contains_key(&id) || self.midpoint_ids.contains(&id)
+    }
+
+    /// Returns every price level that exists but holds no orders.
+    ///
+    /// Empty levels are always dropped by the mutation paths, so this is a
+    /// diagnostic: a non-empty result points at a cleanup bug.
+    pub fn orphaned_levels(&self) -> Vec<(Side, Price)> {
+        let buys = self.buy_side.values().map(|level| (Side::Buy, level));
+        let sells = self.sell_side.values().map(|level| (Side::Sell, level));
+        buys.chain(sells)
+            .filter(|(_, level)| level.orders.is_empty())
+            .map(|(side, level)| (side, level.price))
+            .collect()
+    }
+
+    /// Checks the book's internal structures against each other.
+    ///
+    /// Verifies that no level is orphaned, that every level's total matches
+    /// its orders, that each order rests on its own side and price and is
+    /// indexed there, that the index holds nothing else, that every queue is
+    /// in timestamp order, that every resting quantity (iceberg reserves and
+    /// the midpoint pool included) is a whole number of lots, and that the
+    /// cached best prices agree with the levels.
+    /// Walks the whole book, so it is meant for tests and diagnostics rather
+    /// than the hot path.
+    ///
+    /// # Returns
+    ///
+    /// `OrderBookError::InvariantViolated` describing the first problem found
+    pub fn validate_invariants(&self) -> Result<(), OrderBookError> {
+        let violated = |reason: String| Err(OrderBookError::InvariantViolated(reason));
+        if let Some((side, price)) = self.orphaned_levels().first() {
+            return violated(format!("empty {} level at {}", side, price));
+        }
+        let mut indexed = 0;
+        for (side, book_side) in [(Side::Buy, &self.buy_side), (Side::Sell, &self.sell_side)] {
+            for (&price, level) in book_side {
+                let total: Quantity = level.orders.iter().map(|o| o.quantity).sum();
+                if level.price != price || level.total_quantity != total {
+                    return violated(format!("{} level at {} is inconsistent", side, price));
+                }
+                for order in &level.orders {
+                    if order.side != side || order.price != price {
+                        return violated(format!("order {} rests at the wrong level", order.id));
+                    }
+                    if self.id_index.get(&order.id) != Some(&(side, price)) {
+                        return violated(format!("order {} is not indexed", order.id));
+                    }
+                    indexed += 1;
+                }
+            }
+        }
+        let mut queues = self.buy_side.values().chain(self.sell_side.values());
+        let out_of_order = |level: &&PriceLevel| {
+            let queue = &level.orders;
+            queue.iter().zip(queue.iter().skip(1)).any(|(a, b)| a.timestamp > b.timestamp)
+        };
+        if let Some(level) = queues.find(out_of_order) {
+            return violated(format!("queue at {} is out of timestamp order", level.price));
+        }
+        if indexed != self.id_index.len() {
+            return violated("ID index holds orders not in the book".to_string());
+        }
+        let lot = self.instrument.lot_size;
+        let resting = self.buy_side.values().chain(self.sell_side.values());
+        let pools = self.midpoint_buys.iter().chain(&self.midpoint_sells);
+        for order in resting.flat_map(|level| &level.orders).chain(pools) {
+            if !order.quantity.is_multiple_of(lot) || !order.hidden_remaining.is_multiple_of(lot) {
+                return violated(format!("order {} is not a whole number of lots", order.id));
+            }
+        }
+        let best_buy = self.buy_side.values().next_back().map(|l| (l.price, l.total_quantity));
+        let best_sell = self.sell_side.values().next().map(|l| (l.price, l.total_quantity));
+        if self.best_buy != best_buy || self.best_sell != best_sell {
+            return violated("cached best prices out of sync".to_string());
+        }
+        Ok(())
+    }
+
+    /// Rejects cancelling `order` before it has rested for `min_resting_duration`.
+    fn check_min_resting_time(&self, order: &Order) -> Result<(), OrderBookError> {
+        let Some(required) = self.min_resting_duration else {
+            return Ok(());
+        };
+        // The most recent stamp, on an order or a requeued iceberg slice, is
+        // `next_timestamp - 1`
+        let rested = self.next_timestamp - 1 - order.timestamp;
+        if rested < required {
+            return Err(OrderBookError::MinRestingTimeNotMet { id: order.id, rested, required });
+        }
+        Ok(())
+    }
+
+    /// Returns every resting order on both sides in arrival (timestamp) order.
+    ///
+    /// Re-placing the returned orders into an empty book reproduces the same
+    /// levels and FIFO queues, since resting orders never cross each other.
+    #[cfg_attr(not(feature = "scenario"), allow(dead_code))]
+    pub(crate) fn resting_orders(&self) -> Vec<&Order> {
+        let mut orders: Vec<&Order> = self
+            .buy_side
+            .values()
+            .chain(self.sell_side.values())
+            .flat_map(|level| level.orders.iter())
+            .collect();
+        orders.sort_by_key(|order| order.timestamp);
+        orders
+    }
+
+    /// Removes the order at `index` within the level at `price` on `side`.
+    ///
+    /// Drops the level if it becomes empty, deindexes the order, and refreshes
+    /// the best-price cache when the level was top of book. The caller must
+    /// have verified that the level and index exist.
+    fn remove_resting_order(&mut self, side: Side, price: Price, index: usize) -> Order {
+        let book_side = match side {
+            Side::Buy => &mut self.buy_side,
+            Side::Sell => &mut self.sell_side,
+        };
+        let level = book_side.get_mut(&price).expect("level exists");
+        let order = level.remove_order_at(index).expect("order exists");
+        let level_emptied = level.is_empty();
+        if level_emptied {
+            book_side.remove(&price);
+        }
+        self.id_index.remove(&order.id);
+        self.fill_milestones.remove(&order.id);
+
+        match side {
+            Side::Buy if self.best_buy.map(|(p, _)| p) == Some(price) => self.set_best_buy(),
+            Side::Sell if self.best_sell.map(|(p, _)| p) == Some(price) => {
+                self.update_cached_best_sell()
+            }
+            _ => {}
+        }
+        if level_emptied {
+            self.notify_level_removed(side, price);
+        }
+        order
+    }
+
+    /// Fires the level-removed callback, if registered.
+    fn notify_level_removed(&mut self, side: Side, price: Price) {
+        if let Some(callback) = self.on_level_removed.as_mut() {
+            callback(side, price);
+        }
+    }
+
+    /// Assigns trade IDs and sequence numbers, counts the trades and appends
+    /// them to the bounded history and taker index, evicting the oldest as
+    /// needed.
+    fn record_trades(&mut self, trades: &mut [Trade]) {
+        let first_sequence = self.stats.trades;
+        for trade in trades.iter_mut() {
+            // The trade counter is the monotonic sequence; the ID hashes it
+            // with the order IDs, so it is stable across replays but unordered
+            trade.trade_id = Trade::derive_id(trade.maker_id, trade.taker_id, self.stats.trades);
+            trade.sequence = self.stats.trades;
+            self.last_trade_price = Some(trade.price);
+            self.ohlc.record(trade.price, trade.quantity);
+            self.stats.trades += 1;
+            let quantity = i128::try_from(trade.quantity).unwrap_or(i128::MAX);
+            if let Some(buyer) = trade.buyer_account {
+                let position = self.positions.entry(buyer).or_insert(0);
+                *position = position.saturating_add(quantity);
+            }
+            if let Some(seller) = trade.seller_account {
+                let position = self.positions.entry(seller).or_insert(0);
+                *position = position.saturating_sub(quantity);
+            }
+            if let Some(fees) = self.fees {
+                let (price, quantity) = (trade.price, trade.quantity);
+                let base = &self.instrument.base;
+                trade.maker_fee = FeeSchedule::fee(fees.maker_bps, price, quantity, base);
+                trade.taker_fee = FeeSchedule::fee(fees.taker_bps, price, quantity, base);
+            }
+        }
+        if self.audit_capacity > 0 {
+            self.record_audit(first_sequence, trades);
+        }
+        if self.taker_trade_capacity > 0 {
+            self.index_taker_trades(trades);
+        }
+        if self.trade_history_capacity > 0 {
+            for trade in trades.iter() {
+                if self.trade_history.len() == self.trade_history_capacity {
+                    self.trade_history.pop_front();
+                }
+                self.trade_history.push_back(trade.clone());
+            }
+        }
+        self.notify_trades(trades);
+    }
+
+    /// Pairs the pending order snapshots with their trades in the audit trail.
+    fn record_audit(&mut self, first_sequence: u64, trades: &[Trade]) {
+        let pending = std::mem::take(&mut self.pending_audit);
+        debug_assert_eq!(pending.len(), trades.len(), "one audit snapshot per trade");
+        for (sequence, ((incoming, resting, price_policy), trade)) in
+            (first_sequence..).zip(pending.into_iter().zip(trades))
+        {
+            if self.audit_trail.len() == self.audit_capacity {
+                self.audit_trail.pop_front();
+            }
+            self.audit_trail.push_back(AuditRecord {
+                sequence,
+                trade: trade.clone(),
+                incoming,
+                resting,
+                price_policy,
+            });
+        }
+    }
+
+    /// Reports an accepted order to the event sink and journal.
+    fn report_accepted(&mut self, order: &Order, kind: OrderKind) {
+        if let Some(sink) = self.event_sink.as_mut() {
+            sink.on_order_accepted(order);
+        }
+        self.record_journal(JournalEntry::Accepted { order: order.clone(), kind });
+    }
+
+    /// Appends `entry` to the journal, if enabled, preceded by the book's
+    /// configuration when it changed since it was last journaled.
+    fn record_journal(&mut self, entry: JournalEntry) {
+        if self.journal.is_none() {
+            return;
+        }
+        let config = self.config();
+        if let Some(journal) = self.journal.as_mut() {
+            journal.record(config, entry);
+        }
+    }
+
+    /// Reports a cancelled order to the event sink and journal.
+    fn report_cancelled(&mut self, id: Id) {
+        if let Some(sink) = self.event_sink.as_mut() {
+            sink.on_order_cancelled(id);
+        }
+        self.record_journal(JournalEntry::Cancelled(id));
+    }
+
+    /// Reports trades to the trade callback and fires any fill milestones they cross.
+    fn notify_trades(&mut self, trades: &[Trade]) {
+        for trade in trades {
+            if let Some(callback) = self.on_trade.as_mut() {
+                callback(trade);
+            }
+            if let Some(sink) = self.event_sink.as_mut() {
+                sink.on_trade(trade);
+            }
+            self.record_journal(JournalEntry::Trade(trade.clone()));
+            if self.fill_milestones.is_empty() {
+                continue;
+            }
+            for id in [trade.maker_id, trade.taker_id] {
+                let Some(milestone) = self.fill_milestones.get_mut(&id) else {
+                    continue;
+                };
+                milestone.filled += trade.quantity;
+                if milestone.filled < milestone.threshold {
+                    continue;
+                }
+                let filled = milestone.filled;
+                self.fill_milestones.remove(&id);
+                if let Some(callback) = self.on_fill_milestone.as_mut() {
+                    callback(id, filled);
+                }
+            }
+        }
+    }
+
+    /// Adds trades to the bounded per-taker index, evicting the oldest takers as needed.
+    fn index_taker_trades(&mut self, trades: &[Trade]) {
+        for trade in trades {
+            if !self.taker_trades.contains_key(&trade.taker_id) {
+                if self.taker_trade_ids.len() == self.taker_trade_capacity {
+                    if let Some(evicted) = self.taker_trade_ids.pop_front() {
+                        self.taker_trades.remove(&evicted);
+                    }
+                }
+                self.taker_trade_ids.push_back(trade.taker_id);
+            }
+            self.taker_trades
+                .entry(trade.taker_id)
+                .or_default()
+                .push(trade.clone());
+        }
+    }
+
+    /// Returns how much of an order of `side` at `limit_price` could fill
+    /// immediately without running into the per-order fill cap.
+    ///
+    /// Without a cap this is [`OrderBook::crossable_quantity`]. With one,
+    /// each resting order reached costs a fill. Under pro-rata matching any
+    /// order at a level may take a share, so a level only counts if all of
+    /// its orders fit within the fills left; the result may then understate
+    /// what would fill, never overstate it. A designated market maker's
+    /// allocation fills come first and its orders may be filled again by
+    /// FIFO, so each of its orders at a level costs an extra fill, and a level
+    /// where one of them is an iceberg ends the count.
+    fn fillable_quantity(&self, side: Side, limit_price: Price, up_to: Quantity) -> Quantity {
+        let Some(max_fills) = self.max_fills_per_order else {
+            return self.crossable_quantity(side, limit_price, up_to);
+        };
+        let levels: Vec<&PriceLevel> = match side {
+            Side::Buy => self.sell_side.range(..=limit_price).map(|(_, l)| l).collect(),
+            Side::Sell => self.buy_side.range(limit_price..).rev().map(|(_, l)| l).collect(),
+        };
+        let (mut available, mut fills): (Quantity, usize) = (0, 0);
+        for level in levels {
+            if self.matching_policy == MatchingPolicy::ProRata {
+                if fills + level.orders.len() > max_fills {
+                    break;
+                }
+                fills += level.orders.len();
+                available = available.saturating_add(level.total_quantity);
+            } else {
+                let Some(allocation_fills) = self.designated_maker_fills(level) else {
+                    break;
+                };
+                if fills + allocation_fills >= max_fills {
+                    break;
+                }
+                fills += allocation_fills;
+                for order in level.orders.iter().take(max_fills - fills) {
+                    fills += 1;
+                    available = available.saturating_add(order.quantity);
+                    if available >= up_to {
+                        break;
+                    }
+                }
+            }
+            if available >= up_to || fills == max_fills {
+                break;
+            }
+        }
+        available.min(up_to)
+    }
+
+    /// Returns how many allocation fills the designated market maker's orders
+    /// at `level` can take, or `None` if an iceberg among them could take any
+    /// number by replenishing.
+    fn designated_maker_fills(&self, level: &PriceLevel) -> Option<usize> {
+        let Some(maker) = self.designated_maker else {
+            return Some(0);
+        };
+        let mut fills = 0;
+        for order in level.orders.iter().filter(|o| o.account == Some(maker.account)) {
+            if order.hidden_remaining > 0 {
+                return None;
+            }
+            fills += 1;
+        }
+        Some(fills)
+    }
+
+    /// Returns how much of an order of `side` at `limit_price` could fill immediately.
+    ///
+    /// Walks only the crossable levels on the opposite side, best-first, and
+    /// stops early once `up_to` is reached.
+    fn crossable_quantity(&self, side: Side, limit_price: Price, up_to: Quantity) -> Quantity {
+        let fillable = |levels: &mut dyn Iterator<Item = &PriceLevel>| {
+            let mut available: Quantity = 0;
+            for level in levels {
+                available = available.saturating_add(level.total_quantity);
+                if available >= up_to {
+                    break;
+                }
+            }
+            available.min(up_to)
+        };
+        match side {
+            Side::Buy => fillable(&mut self.sell_side.range(..=limit_price).map(|(_, l)| l)),
+            Side::Sell => fillable(&mut self.buy_side.range(limit_price..).rev().map(|(_, l)| l)),
+        }
+    }
+
+    /// Previews sweeping the side opposite to `taker_side` for up to `quantity`.
+    ///
+    /// Walks levels best-first without mutating the book and returns the
+    /// quantity that could be filled together with the raw `price * quantity`
+    /// sum of those fills. Returns `None` on overflow.
+    fn preview_sweep(&self, taker_side: Side, quantity: Quantity) -> Option<(Quantity, u128)> {
+        match taker_side {
+            Side::Buy => Self::sweep_levels(self.sell_side.values(), quantity),
+            Side::Sell => Self::sweep_levels(self.buy_side.values().rev(), quantity),
+        }
+    }
+
+    /// Accumulates fills over `levels` (already in best-first order) for `preview_sweep`.
+    fn sweep_levels<'a>(
+        levels: impl Iterator<Item = &'a PriceLevel>,
+        quantity: Quantity,
+    ) -> Option<(Quantity, u128)> {
+        let mut filled: Quantity = 0;
+        let mut raw_notional: u128 = 0;
+        for level in levels {
+            if filled == quantity {
+                break;
+            }
+            let take = level.total_quantity.min(quantity - filled);
+            raw_notional = raw_notional.checked_add(level.price.checked_mul(take)?)?;
+            filled += take;
+        }
+        Some((filled, raw_notional))
+    }
+
+    /// Updates the cached best buy price and quantity.
+    ///
+    /// Recalculates the best buy from the buy_side BTreeMap and caches the result.
+    /// This should be called whenever the buy side of the book is modified.
+    fn set_best_buy(&mut self) {
+        self.best_buy = self
+            .buy_side
+            .iter()
+            .next_back()
+            .map(|(price, level)| (*price, level.total_quantity));
+    }
+
+    /// Updates the cached best sell price and quantity.
+    ///
+    /// Recalculates the best sell from the sell_side BTreeMap and caches the result.
+    /// This should be called whenever the sell side of the book is modified.
+    fn update_cached_best_sell(&mut self) {
+        self.best_sell = self
+            .sell_side
+            .iter()
+            .next()
+            .map(|(price, level)| (*price, level.total_quantity));
+    }
+
+    /// Matches front orders while the best buy is at or above the best sell.
+    ///
+    /// Only a book that rested orders in `PreOpen` can be crossed. Each step
+    /// trades the front orders of the two best levels, the older one acting
+    /// as the maker, at `clearing` if given and otherwise at the maker's price.
+    fn match_crossed_orders(&mut self, clearing: Option<Price>) -> Trades {
+        let mut trades = Vec::new();
+        while let (Some((bid, _)), Some((ask, _))) = (self.best_buy, self.best_sell) {
+            if bid < ask {
+                break;
+            }
+            let buy = self.buy_side[&bid].orders.front().expect("level has orders");
+            let sell = self.sell_side[&ask].orders.front().expect("level has orders");
+            let match_qty = buy.quantity.min(sell.quantity);
+            let (maker, taker, maker_price) = if buy.timestamp < sell.timestamp {
+                (buy, sell, bid)
+            } else {
+                (sell, buy, ask)
+            };
+            let (price, policy) = match clearing {
+                Some(clearing) => (clearing, PricePolicy::ClearingPrice),
+                None => (maker_price, PricePolicy::MakerPrice),
+            };
+            let mut trade = Trade::new(price, match_qty, maker.id, taker.id)
+                .with_tags(maker.tag, taker.tag)
+                .with_accounts_of(maker, taker);
+            trade.timestamp = taker.timestamp;
+            trades.push(trade);
+            if self.audit_capacity > 0 {
+                let snapshot = (taker.clone(), maker.clone(), policy);
+                self.pending_audit.push(snapshot);
+            }
+            self.fill_front_order(Side::Buy, bid, match_qty);
+            self.fill_front_order(Side::Sell, ask, match_qty);
+        }
+        trades
+    }
+
+    /// Fills `quantity` of the front order at the best level on `side`,
+    /// replenishing an iceberg whose visible slice is used up.
+    fn fill_front_order(&mut self, side: Side, price: Price, quantity: Quantity) {
+        let book_side = match side {
+            Side::Buy => &mut self.buy_side,
+            Side::Sell => &mut self.sell_side,
+        };
+        let level = book_side.get_mut(&price).expect("level exists");
+        let front = level.orders.front().expect("order exists");
+        let (id, remaining) = (front.id, front.quantity - quantity);
+        if remaining == 0 && front.hidden_remaining == 0 {
+            self.remove_resting_order(side, price, 0);
+            return;
+        }
+        if remaining == 0 {
+            // Replenish an iceberg slice and requeue it, as continuous matching does
+            let mut order = level.remove_order().expect("order exists");
+            order.replenish(&mut self.next_timestamp);
+            level.requeue(order);
+        } else {
+            level.update_front_order_quantity(id, remaining);
+        }
+        match side {
+            Side::Buy => self.set_best_buy(),
+            Side::Sell => self.update_cached_best_sell(),
+        }
+    }
+
+    /// Attempts to match an incoming order against existing orders.
+    ///
+    /// For buy orders, matches against sell orders at or below the buy price.
+    /// For sell orders, matches against buy orders at or above the sell price.
+    /// Orders are matched in price-time priority.
+    fn match_incoming_order(&mut self, incoming: &mut Order) -> Trades {
+        let mut trades = Vec::new();
+        let max_fills = self.max_fills_per_order.unwrap_or(usize::MAX);
+        let mut audit = (self.audit_capacity > 0).then(Vec::new);
+        let (policy, lot) = (self.matching_policy, self.instrument.lot_size);
+        let designated_maker = self.designated_maker;
+        #[cfg(feature = "match-metrics")]
+        let mut levels_visited: u64 = 0;
+        let mut orders_visited = VisitCounter::default();
+
+        match incoming.side {
+            Side::Buy => {
+                while incoming.quantity > 0 && trades.len() < max_fills {
+                    // Get the best matching price level
+                    let best_price = match self.sell_side.range(..=incoming.price).next() {
+                        Some((price, _)) => *price,
+                        None => break, // No more matching levels
+                    };
+                    
+                    #[cfg(feature = "match-metrics")]
+                    {
+                        levels_visited += 1;
+                    }
+
+                    // Process this single price level completely
+                    let mut context = MatchContext {
+                        trades: &mut trades,
+                        max_fills,
+                        id_index: &mut self.id_index,
+                        audit: audit.as_mut(),
+                        lot,
+                        orders_visited: &mut orders_visited,
+                        clock: &mut self.next_timestamp,
+                    };
+                    let match_result = Self::match_price_level(
+                        incoming,
+                        best_price,
+                        &mut self.sell_side,
+                        policy,
+                        designated_maker,
+                        &mut context,
+                    );
+
+                    match match_result {
+                        LevelMatchResult::EmptyBestLevel => {
+                            self.sell_side.remove(&best_price);
+                            self.update_cached_best_sell();
+                            self.notify_level_removed(Side::Sell, best_price);
+                        }
+                        LevelMatchResult::EmptyLevel => {
+                            self.sell_side.remove(&best_price);
+                            self.notify_level_removed(Side::Sell, best_price);
+                        }
+                        LevelMatchResult::MatchedBestLevel => {
+                            self.update_cached_best_sell();
+                        }
+                        LevelMatchResult::Matched => {
+                            // No cache update needed
+                        }
+                    }
+                }
+            }
+            Side::Sell => {
+                while incoming.quantity > 0 && trades.len() < max_fills {
+                    // Get the best matching price level
+                    let best_price = match self.buy_side.range(incoming.price..).next_back() {
+                        Some((price, _)) => *price,
+                        None => break, // No more matching levels
+                    };
+                    
+                    #[cfg(feature = "match-metrics")]
+                    {
+                        levels_visited += 1;
+                    }
+
+                    // Process this single price level completely
+                    let mut context = MatchContext {
+                        trades: &mut trades,
+                        max_fills,
+                        id_index: &mut self.id_index,
+                        audit: audit.as_mut(),
+                        lot,
+                        orders_visited: &mut orders_visited,
+                        clock: &mut self.next_timestamp,
+                    };
+                    let match_result = Self::match_price_level(
+                        incoming,
+                        best_price,
+                        &mut self.buy_side,
+                        policy,
+                        designated_maker,
+                        &mut context,
+                    );
+
+                    match match_result {
+                        LevelMatchResult::EmptyBestLevel => {
+                            self.buy_side.remove(&best_price);
+                            self.set_best_buy();
+                            self.notify_level_removed(Side::Buy, best_price);
+                        }
+                        LevelMatchResult::EmptyLevel => {
+                            self.buy_side.remove(&best_price);
+                            self.notify_level_removed(Side::Buy, best_price);
+                        }
+                        LevelMatchResult::MatchedBestLevel => {
+                            self.set_best_buy();
+                        }
+                        // No cache update needed
+                        LevelMatchResult::Matched => {}
+                    }
+                }
+            }
+        }
+
+        #[cfg(feature = "match-metrics")]
+        {
+            let metrics = MatchMetrics {
+                levels_visited,
+                orders_visited: orders_visited.count,
+            };
+            self.stats.last_match = metrics;
+            *self.stats.levels_visited_histogram.entry(levels_visited).or_insert(0) += 1;
+        }
+        if let Some(audit) = audit {
+            let snapshots = audit.into_iter();
+            self.pending_audit.extend(snapshots.map(|(incoming, resting)| {
+                (incoming, resting, PricePolicy::MakerPrice)
+            }));
+        }
+
+        trades
+    }
+
+    /// Helper method to match against a single price level on a specific book side.
+    ///
+    /// This eliminates the duplication between Buy and Sell matching logic by
+    /// parameterizing the side-specific behaviors.
+    ///
+    /// Returns matching result to guide cache updates.
+    fn match_price_level(
+        incoming: &mut Order,
+        price: Price,
+        book_side: &mut BTreeMap<Price, PriceLevel>,
+        policy: MatchingPolicy,
+        designated_maker: Option<DesignatedMaker>,
+        context: &mut MatchContext<'_>,
+    ) -> LevelMatchResult {
+        // Check if this price level is the best before modifying it
+        let level_was_best = match incoming.side {
+            Side::Buy => book_side.iter().next().map(|(p, _)| *p) == Some(price),
+            Side::Sell => book_side.iter().next_back().map(|(p, _)| *p) == Some(price),
+        };
+
+        // compute whether this level becomes empty *inside* a block
+        let level_is_empty = if let Some(level) = book_side.get_mut(&price) {
+            match policy {
+                MatchingPolicy::ProRata => Self::match_pro_rata(incoming, level, context),
+                MatchingPolicy::PriceTime => {
+                    if let Some(maker) = designated_maker {
+                        Self::match_designated_maker(incoming, level, maker, context);
+                    }
+                    Self::match_against_level(incoming, level, context)
+                }
+            }
+            level.is_empty()
+        } else {
+            false
+        };
+
+        match (level_is_empty, level_was_best) {
+            (true, true) => LevelMatchResult::EmptyBestLevel,
+            (true, false) => LevelMatchResult::EmptyLevel,
+            (false, true) => LevelMatchResult::MatchedBestLevel,
+            (false, false) => LevelMatchResult::Matched,
+        }
+    }
+
+    /// Matches an incoming order against a specific price level.
+    ///
+    /// Continues matching until the incoming order is fully filled, the price
+    /// level is exhausted or `trades` holds `max_fills` trades.
+    ///
+    /// A trade is never produced for a zero quantity: a resting order with
+    /// nothing left to fill is dropped from the level without a trade, and
+    /// matching moves on to the next order.
+    ///
+    /// A partial fill is trimmed so the resting order keeps a whole number of
+    /// `lot`s. If that leaves nothing to trade, the incoming order holds less
+    /// than a lot and its remainder is dropped rather than rest as dust.
+    ///
+    /// An iceberg whose visible slice is consumed is replenished from its
+    /// reserve and requeued at the back of the level instead of removed,
+    /// stamped with the next timestamp from `clock` so the queue stays in
+    /// timestamp order.
+    ///
+    /// When `audit` is given, the incoming and resting orders are captured
+    /// just before each fill. Every resting order examined, traded or not,
+    /// is counted in `orders_visited`.
+    // Free/assoc fn; no &mut self here
+    fn match_against_level(
+        incoming: &mut Order,
+        level: &mut PriceLevel,
+        context: &mut MatchContext<'_>,
+    ) {
+        let MatchContext { trades, max_fills, id_index, audit, lot, orders_visited, clock } =
+            context;
+        let (max_fills, lot) = (*max_fills, (*lot).max(1));
+        while incoming.quantity > 0 && trades.len() < max_fills && !level.orders.is_empty() {
+            let resting = level.orders.front().expect("front exists");
+            orders_visited.add(1);
+            let mut match_qty = incoming.quantity.min(resting.quantity);
+            if match_qty == 0 {
+                let removed = level.remove_order().expect("front existed");
+                id_index.remove(&removed.id);
+                continue;
+            }
+            match_qty -= (resting.quantity - match_qty) % lot;
+            if match_qty == 0 {
+                incoming.quantity = 0;
+                break;
+            }
+
+            if let Some(audit) = audit.as_mut() {
+                audit.push((incoming.clone(), resting.clone()));
+            }
+            let mut trade = Trade::new(level.price, match_qty, resting.id, incoming.id)
+                .with_tags(resting.tag, incoming.tag)
+                .with_accounts_of(resting, incoming);
+            trade.timestamp = incoming.timestamp;
+            trades.push(trade);
+            incoming.quantity -= match_qty;
+
+            if match_qty == resting.quantity {
+                // fully consumed: pop & deindex, or replenish an iceberg slice
+                let mut removed = level.remove_order().expect("front existed");
+                if removed.hidden_remaining > 0 {
+                    removed.replenish(clock);
+                    level.requeue(removed);
+                } else {
+                    id_index.remove(&removed.id);
+                }
+            } else {
+                // partial: shrink front
+                level.update_front_order_quantity(resting.id, resting.quantity - match_qty);
+            }
+        }
+    }
+
+    /// Fills the designated maker's orders at a level with its allocation,
+    /// ahead of FIFO matching; see [`OrderBook::with_designated_maker`] for
+    /// the rule.
+    ///
+    /// Fills are trimmed to whole lots and replenished icebergs requeued as
+    /// in `match_against_level`. Every order of the account examined is
+    /// counted in `orders_visited`.
+    fn match_designated_maker(
+        incoming: &mut Order,
+        level: &mut PriceLevel,
+        maker: DesignatedMaker,
+        context: &mut MatchContext<'_>,
+    ) {
+        let MatchContext { trades, max_fills, id_index, audit, lot, orders_visited, clock } =
+            context;
+        let (max_fills, lot) = (*max_fills, (*lot).max(1));
+        let level_quantity = incoming.quantity.min(level.total_quantity);
+        let share = mul_div_floor(level_quantity, maker.allocation_pct.into(), 100)
+            .expect("an allocation never exceeds the level quantity");
+        let mut allocation = share - share % lot;
+        let mut index = 0;
+        while allocation > 0 && trades.len() < max_fills && index < level.orders.len() {
+            let resting = &level.orders[index];
+            if resting.account != Some(maker.account) {
+                index += 1;
+                continue;
+            }
+            orders_visited.add(1);
+            let mut match_qty = allocation.min(resting.quantity);
+            match_qty -= (resting.quantity - match_qty) % lot;
+            if match_qty == 0 {
+                index += 1;
+                continue;
+            }
+
+            if let Some(audit) = audit.as_mut() {
+                audit.push((incoming.clone(), resting.clone()));
+            }
+            let mut trade = Trade::new(level.price, match_qty, resting.id, incoming.id)
+                .with_tags(resting.tag, incoming.tag)
+                .with_accounts_of(resting, incoming);
+            trade.timestamp = incoming.timestamp;
+            trades.push(trade);
+            incoming.quantity -= match_qty;
+            allocation -= match_qty;
+
+            if match_qty == resting.quantity {
+                // The next order shifts into `index`; a replenished slice
+                // goes to the back and may take what is left of the allocation
+                let mut removed = level.remove_order_at(index).expect("order exists");
+                if removed.hidden_remaining > 0 {
+                    removed.replenish(clock);
+                    level.requeue(removed);
+                } else {
+                    id_index.remove(&removed.id);
+                }
+            } else {
+                level.update_order_quantity(index, resting.quantity - match_qty);
+                index += 1;
+            }
+        }
+    }
+
+    /// Matches an incoming order against a level under `MatchingPolicy::ProRata`.
+    ///
+    /// The fill, capped at the level's visible quantity, is split in
+    /// proportion to each resting order's visible quantity, rounded down to
+    /// whole `lot`s. Lots left over by the rounding then go one per order,
+    /// oldest first, cycling until none remain. Trades are emitted in FIFO
+    /// order; untouched and partially filled orders keep their queue
+    /// position, and replenished icebergs are requeued at the back. Every
+    /// order at the level takes part in the split and counts as visited.
+    fn match_pro_rata(
+        incoming: &mut Order,
+        level: &mut PriceLevel,
+        context: &mut MatchContext<'_>,
+    ) {
+        let total: Quantity = level.orders.iter().map(|order| order.quantity).sum();
+        let fill = incoming.quantity.min(total);
+        if fill == 0 {
+            // Only zero-quantity orders rest here; drop them as price-time would
+            return Self::match_against_level(incoming, level, context);
+        }
+        let MatchContext { trades, max_fills, id_index, audit, lot, orders_visited, clock } =
+            context;
+        let (max_fills, lot) = (*max_fills, (*lot).max(1));
+        orders_visited.add(level.orders.len() as u64);
+        let mut allocations: Vec<Quantity> = level
+            .orders
+            .iter()
+            .map(|order| {
+                // fill <= total, so the share never exceeds the order's quantity
+                let share = mul_div_floor(fill, order.quantity, total)
+                    .expect("pro-rata share fits within the resting quantity");
+                share - share % lot
+            })
+            .collect();
+        let mut remainder = fill - allocations.iter().sum::<Quantity>();
+        while remainder > 0 {
+            for (allocation, order) in allocations.iter_mut().zip(&level.orders) {
+                let extra = lot.min(remainder).min(order.quantity - *allocation);
+                *allocation += extra;
+                remainder -= extra;
+                if remainder == 0 {
+                    break;
+                }
+            }
+        }
+
+        let mut replenished = Vec::new();
+        for allocation in allocations {
+            let mut resting = level.remove_order().expect("one allocation per order");
+            if allocation > 0 && trades.len() < max_fills {
+                if let Some(audit) = audit.as_mut() {
+                    audit.push((incoming.clone(), resting.clone()));
+                }
+                let mut trade = Trade::new(level.price, allocation, resting.id, incoming.id)
+                    .with_tags(resting.tag, incoming.tag)
+                    .with_accounts_of(&resting, incoming);
+                trade.timestamp = incoming.timestamp;
+                trades.push(trade);
+                incoming.quantity -= allocation;
+                resting.quantity -= allocation;
+            }
+            if resting.quantity > 0 {
+                level.requeue(resting);
+            } else if resting.hidden_remaining > 0 {
+                resting.replenish(clock);
+                replenished.push(resting);
+            } else {
+                id_index.remove(&resting.id);
+            }
+        }
+        for order in replenished {
+            level.requeue(order);
+        }
+    }
+
+    /// Adds an order to the appropriate side of the book.
+    ///
+    /// Creates a new price level if one doesn't exist at the order's price.
     fn add_order_to_book(&mut self, order: Order) {
         let book_side = match order.side {
             Side::Buy => &mut self.buy_side,
             Side::Sell => &mut self.sell_side,
         };
 
-        book_side
-            .entry(order.price)
-            .or_insert_with(|| PriceLevel::new(order.price))
-            .add_order(order.clone());
+        let level_added = !book_side.contains_key(&order.price);
+        book_side
+            .entry(order.price)
+            .or_insert_with(|| PriceLevel::new(order.price))
+            .add_order(order.clone());
+
+        // Update cache when adding orders that might affect best prices
+        match order.side {
+            Side::Buy => self.set_best_buy(),
+            Side::Sell => self.update_cached_best_sell(),
+        }
+
+        if let (true, Some(callback)) = (level_added, self.on_level_added.as_mut()) {
+            callback(order.side, order.price);
+        }
+        self.assert_cache_consistent();
+        self.record_bbo();
+    }
+
+    /// Publishes the top of book to the L2 callback if it changed since last time.
+    fn publish_l2(&mut self) {
+        if self.on_l2_update.is_none() {
+            return;
+        }
+        let bids = self.depth(Side::Buy, self.l2_levels);
+        let asks = self.depth(Side::Sell, self.l2_levels);
+        let update = L2Update {
+            sequence: self.next_timestamp,
+            bids: (bids != self.l2_bids).then(|| bids.clone()),
+            asks: (asks != self.l2_asks).then(|| asks.clone()),
+        };
+        if update.bids.is_none() && update.asks.is_none() {
+            return;
+        }
+        self.l2_bids = bids;
+        self.l2_asks = asks;
+        if let Some(callback) = self.on_l2_update.as_mut() {
+            callback(&update);
+        }
+    }
+
+    /// Samples the best prices into the bounded history if either has changed.
+    fn record_bbo(&mut self) {
+        if self.bbo_history_capacity == 0 {
+            return;
+        }
+        let bid = self.best_buy.map(|(price, _)| price);
+        let ask = self.best_sell.map(|(price, _)| price);
+        if let Some(last) = self.bbo_history.back() {
+            if last.bid == bid && last.ask == ask {
+                return;
+            }
+        }
+        if self.bbo_history.len() == self.bbo_history_capacity {
+            self.bbo_history.pop_front();
+        }
+        self.bbo_history.push_back(BboSample {
+            timestamp: self.next_timestamp,
+            bid,
+            ask,
+        });
+    }
+
+    /// Checks in debug builds that the cached best prices match the price levels.
+    ///
+    /// Called at the end of every mutation to catch cache drift during
+    /// development; compiles to nothing in release builds.
+    fn assert_cache_consistent(&self) {
+        debug_assert_eq!(
+            self.best_buy,
+            self.buy_side.iter().next_back().map(|(p, level)| (*p, level.total_quantity)),
+            "cached best buy out of sync with the buy levels"
+        );
+        debug_assert_eq!(
+            self.best_sell,
+            self.sell_side.iter().next().map(|(p, level)| (*p, level.total_quantity)),
+            "cached best sell out of sync with the sell levels"
+        );
+    }
+}
+#[cfg(test)]
+mod order_book_tests {
+    use super::*;
+    use crate::test_support::*;
+    use crate::types::OrderBookError;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_id_uniqueness() {
+        let mut order_book = new_book();
+        let result1 = order_book.place_order(Side::Buy, price("100.00"), quantity("0.010"), 1);
+        assert!(result1.is_ok());
+        let result2 = order_book.place_order(Side::Buy, price("100.00"), quantity("0.010"), 1);
+        assert!(matches!(result2, Err(OrderBookError::DuplicateOrderId(1))));
+    }
+
+    #[test]
+    fn test_zero_quantity_error() {
+        let mut order_book = new_book();
+        let result = order_book.place_order(Side::Buy, price("100.00"), 0, 1);
+        assert!(matches!(
+            result,
+            Err(OrderBookError::ZeroQuantity { id: 1, quantity: 0 })
+        ));
+    }
+
+    #[test]
+    fn test_value_caps_reject_before_matching() {
+        let mut order_book = new_book()
+            .with_max_price(price("1000.00"))
+            .with_max_quantity(quantity("1.0"));
+        order_book
+            .place_order(Side::Sell, price("100.00"), quantity("0.010"), 1)
+            .unwrap();
+
+        let result = order_book.place_order(Side::Buy, price("1000.01"), quantity("0.010"), 2);
+        assert_eq!(
+            result,
+            Err(OrderBookError::ValueTooLarge {
+                id: 2,
+                value: price("1000.01"),
+                max: price("1000.00"),
+            })
+        );
+        let result = order_book.place_order(Side::Buy, price("100.00"), quantity("1.001"), 3);
+        assert!(matches!(result, Err(OrderBookError::ValueTooLarge { id: 3, .. })));
+
+        // Resting ask untouched by the rejected orders
+        assert_eq!(order_book.best_sell(), Some((price("100.00"), quantity("0.010"))));
+
+        // Values at the caps are accepted
+        let trades = order_book
+            .place_order(Side::Buy, price("1000.00"), quantity("1.0"), 4)
+            .unwrap();
+        assert_eq!(trades.len(), 1);
+    }
+
+    #[test]
+    fn test_on_reject_fires_for_duplicate_id() {
+        let rejections = Arc::new(Mutex::new(Vec::new()));
+        let mut order_book = new_book();
+        let sink = Arc::clone(&rejections);
+        order_book.on_reject(move |error, side, price, quantity, id| {
+            sink.lock().unwrap().push((error.clone(), side, price, quantity, id));
+        });
+
+        order_book
+            .place_order(Side::Buy, price("100.00"), quantity("0.010"), 1)
+            .unwrap();
+        assert!(rejections.lock().unwrap().is_empty());
+
+        let result = order_book.place_order(Side::Sell, price("101.00"), quantity("0.020"), 1);
+        assert!(result.is_err());
+        assert_eq!(
+            *rejections.lock().unwrap(),
+            vec![(
+                OrderBookError::DuplicateOrderId(1),
+                Side::Sell,
+                price("101.00"),
+                quantity("0.020"),
+                1
+            )]
+        );
+    }
+
+    #[test]
+    fn test_round_trip_cost_on_symmetric_book() {
+        let order_book = book! {
+            buy 99.00 x 0.005 @id 1;
+            buy 98.00 x 0.005 @id 2;
+            sell 101.00 x 0.005 @id 3;
+            sell 102.00 x 0.005 @id 4;
+        };
+
+        // Top level only: buy 0.505, sell 0.495 -> 0.01 USDT
+        assert_eq!(order_book.round_trip_cost(quantity("0.005")), Some(price("0.01")));
+        // Both levels: buy 1.015, sell 0.985 -> 0.03 USDT
+        assert_eq!(order_book.round_trip_cost(quantity("0.010")), Some(price("0.03")));
+        // Not enough liquidity on either side
+        assert_eq!(order_book.round_trip_cost(quantity("0.011")), None);
+
+        // Read-only: book unchanged
+        assert_eq!(order_book.best_buy(), Some((price("99.00"), quantity("0.005"))));
+        assert_eq!(order_book.best_sell(), Some((price("101.00"), quantity("0.005"))));
+    }
+
+    #[test]
+    fn test_round_trip_cost_one_sided_book() {
+        let mut order_book = new_book();
+        order_book.place_order(Side::Sell, price("101.00"), quantity("0.005"), 1).unwrap();
+        assert_eq!(order_book.round_trip_cost(quantity("0.001")), None);
+    }
+
+    #[test]
+    fn test_format_depth() {
+        let order_book = book! {
+            buy 99.50 x 0.010 @id 1;
+            buy 99.75 x 0.002 @id 2;
+            sell 100.10 x 0.015 @id 3;
+        };
+
+        assert_eq!(
+            order_book.format_depth(Side::Buy, 5),
+            vec!["0.002 BTC @ 99.75 USDT", "0.01 BTC @ 99.50 USDT"]
+        );
+        assert_eq!(order_book.format_depth(Side::Sell, 5), vec!["0.015 BTC @ 100.10 USDT"]);
+        assert!(new_book().format_depth(Side::Sell, 5).is_empty());
+
+        // Cumulative rows share column widths across both sides
+        assert_eq!(
+            order_book.format_depth_with(Side::Buy, 5, true),
+            vec![
+                "0.002 BTC @  99.75 USDT (cumulative 0.002 BTC)",
+                " 0.01 BTC @  99.50 USDT (cumulative 0.012 BTC)",
+            ]
+        );
+        assert_eq!(
+            order_book.format_depth_with(Side::Sell, 5, true),
+            vec!["0.015 BTC @ 100.10 USDT (cumulative 0.015 BTC)"]
+        );
+    }
+
+    #[test]
+    fn test_cancel_by_attributes_removes_oldest_match() {
+        let mut order_book = new_book();
+        order_book.place_order(Side::Buy, price("100.00"), quantity("0.005"), 1).unwrap();
+        order_book.place_order(Side::Buy, price("100.00"), quantity("0.003"), 2).unwrap();
+        order_book.place_order(Side::Buy, price("100.00"), quantity("0.005"), 3).unwrap();
+        order_book.place_order(Side::Buy, price("99.00"), quantity("0.005"), 4).unwrap();
+
+        let cancelled = order_book
+            .cancel_by_attributes(Side::Buy, price("100.00"), quantity("0.005"))
+            .unwrap();
+        assert_eq!(cancelled.id, 1);
+        assert_eq!(order_book.best_buy(), Some((price("100.00"), quantity("0.008"))));
+
+        // Id 1 is free again, ids 2 and 3 are still resting
+        let trades = order_book
+            .place_order(Side::Sell, price("100.00"), quantity("0.008"), 10)
+            .unwrap();
+        assert_eq!(trades.iter().map(|t| t.maker_id).collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(order_book.best_buy(), Some((price("99.00"), quantity("0.005"))));
+        order_book.place_order(Side::Buy, price("90.00"), quantity("0.001"), 1).unwrap();
+    }
+
+    #[test]
+    fn test_cancel_by_attributes_last_order_clears_level() {
+        let mut order_book = new_book();
+        order_book.place_order(Side::Sell, price("101.00"), quantity("0.002"), 1).unwrap();
+        order_book.place_order(Side::Sell, price("102.00"), quantity("0.002"), 2).unwrap();
+
+        order_book
+            .cancel_by_attributes(Side::Sell, price("101.00"), quantity("0.002"))
+            .unwrap();
+        assert_eq!(order_book.best_sell(), Some((price("102.00"), quantity("0.002"))));
+        assert_eq!(order_book.depth(Side::Sell, 5).len(), 1);
+    }
+
+    #[test]
+    fn test_cancel_by_attributes_no_match() {
+        let mut order_book = new_book();
+        order_book.place_order(Side::Sell, price("101.00"), quantity("0.002"), 1).unwrap();
+
+        for (side, p, q) in [
+            (Side::Sell, price("101.00"), quantity("0.001")),
+            (Side::Sell, price("100.00"), quantity("0.002")),
+            (Side::Buy, price("101.00"), quantity("0.002")),
+        ] {
+            assert_eq!(
+                order_book.cancel_by_attributes(side, p, q),
+                Err(OrderBookError::NoMatchingOrder { side, price: p, quantity: q })
+            );
+        }
+        assert_eq!(order_book.best_sell(), Some((price("101.00"), quantity("0.002"))));
+    }
+
+    #[test]
+    fn test_stats_track_order_to_trade_ratio() {
+        let mut order_book = new_book();
+        assert_eq!(order_book.stats().order_to_trade_ratio(), None);
+
+        order_book.place_order(Side::Sell, price("100.00"), quantity("0.002"), 1).unwrap();
+        order_book.place_order(Side::Sell, price("101.00"), quantity("0.002"), 2).unwrap();
+        order_book.place_order(Side::Sell, price("102.00"), quantity("0.002"), 3).unwrap();
+        order_book
+            .cancel_by_attributes(Side::Sell, price("102.00"), quantity("0.002"))
+            .unwrap();
+        // Rejected placements are not counted
+        assert!(order_book.place_order(Side::Sell, price("103.00"), 0, 4).is_err());
+        // Sweeps both remaining levels: two trades
+        order_book.place_order(Side::Buy, price("101.00"), quantity("0.004"), 5).unwrap();
+
+        let stats = order_book.stats();
+        assert_eq!(stats.orders_placed, 4);
+        assert_eq!(stats.orders_cancelled, 1);
+        assert_eq!(stats.trades, 2);
+        assert_eq!(stats.order_to_trade_ratio(), Some(2.5));
+    }
+
+    #[test]
+    fn test_place_taker_only_rejects_when_remainder_would_rest() {
+        let mut order_book = new_book();
+        order_book.place_order(Side::Sell, price("100.00"), quantity("0.002"), 1).unwrap();
+        order_book.place_order(Side::Sell, price("101.00"), quantity("0.002"), 2).unwrap();
+
+        // Crosses only the first level; 0.003 would rest
+        let result = order_book.place_taker_only(Side::Buy, price("100.50"), quantity("0.005"), 3);
+        assert_eq!(
+            result,
+            Err(OrderBookError::WouldRest { id: 3, remaining: quantity("0.003") })
+        );
+        // No fills occurred and nothing rested
+        assert_eq!(order_book.best_sell(), Some((price("100.00"), quantity("0.002"))));
+        assert_eq!(order_book.best_buy(), None);
+
+        let trades = order_book
+            .place_taker_only(Side::Buy, price("101.00"), quantity("0.004"), 3)
+            .unwrap();
+        assert_eq!(trades.len(), 2);
+        assert!(order_book.is_empty());
+    }
+
+    #[test]
+    fn test_volume_profile_over_trade_history() {
+        let mut order_book = new_book().with_trade_history(10);
+        order_book.place_order(Side::Sell, price("100.00"), quantity("0.002"), 1).unwrap();
+        order_book.place_order(Side::Sell, price("100.00"), quantity("0.003"), 2).unwrap();
+        order_book.place_order(Side::Sell, price("101.00"), quantity("0.004"), 3).unwrap();
+        order_book.place_order(Side::Buy, price("101.00"), quantity("0.006"), 4).unwrap();
+
+        let profile = order_book.volume_profile();
+        assert_eq!(profile.len(), 2);
+        assert_eq!(profile[&price("100.00")], quantity("0.005"));
+        assert_eq!(profile[&price("101.00")], quantity("0.001"));
+    }
+
+    #[test]
+    fn test_trade_history_is_bounded() {
+        let mut order_book = new_book().with_trade_history(2);
+        for id in 1..=3 {
+            order_book.place_order(Side::Sell, price("100.00"), quantity("0.001"), id).unwrap();
+        }
+        order_book.place_order(Side::Buy, price("100.00"), quantity("0.003"), 4).unwrap();
+
+        let makers: Vec<Id> = order_book.trade_history().iter().map(|t| t.maker_id).collect();
+        assert_eq!(makers, vec![2, 3]);
+        assert_eq!(order_book.volume_profile()[&price("100.00")], quantity("0.002"));
+
+        // History is off by default
+        let mut order_book = new_book();
+        order_book.place_order(Side::Sell, price("100.00"), quantity("0.001"), 1).unwrap();
+        order_book.place_order(Side::Buy, price("100.00"), quantity("0.001"), 2).unwrap();
+        assert!(order_book.volume_profile().is_empty());
+    }
+
+    #[test]
+    fn test_min_resting_duration_blocks_early_cancel() {
+        let mut order_book = new_book().with_min_resting_duration(2);
+        order_book.place_order(Side::Buy, price("100.00"), quantity("0.001"), 1).unwrap();
+
+        let cancel = |book: &mut OrderBook| {
+            book.cancel_by_attributes(Side::Buy, price("100.00"), quantity("0.001"))
+        };
+        assert_eq!(
+            cancel(&mut order_book),
+            Err(OrderBookError::MinRestingTimeNotMet { id: 1, rested: 0, required: 2 })
+        );
+
+        order_book.place_order(Side::Sell, price("105.00"), quantity("0.001"), 2).unwrap();
+        assert!(matches!(
+            cancel(&mut order_book),
+            Err(OrderBookError::MinRestingTimeNotMet { rested: 1, .. })
+        ));
+
+        order_book.place_order(Side::Sell, price("106.00"), quantity("0.001"), 3).unwrap();
+        assert_eq!(cancel(&mut order_book).unwrap().id, 1);
+        assert_eq!(order_book.best_buy(), None);
+    }
+
+    #[test]
+    fn test_price_for_quantity_marginal_level() {
+        let mut order_book = new_book();
+        order_book.place_order(Side::Sell, price("100.00"), quantity("0.002"), 1).unwrap();
+        order_book.place_order(Side::Sell, price("101.00"), quantity("0.003"), 2).unwrap();
+        order_book.place_order(Side::Sell, price("102.00"), quantity("0.005"), 3).unwrap();
+        order_book.place_order(Side::Buy, price("99.00"), quantity("0.004"), 4).unwrap();
+        order_book.place_order(Side::Buy, price("98.00"), quantity("0.004"), 5).unwrap();
+
+        // Target falls mid-way through the second ask level
+        assert_eq!(order_book.price_for_quantity(Side::Buy, quantity("0.004")), Some(price("101.00")));
+        // Exactly exhausting a level stays on that level
+        assert_eq!(order_book.price_for_quantity(Side::Buy, quantity("0.005")), Some(price("101.00")));
+        assert_eq!(order_book.price_for_quantity(Side::Buy, quantity("0.010")), Some(price("102.00")));
+        assert_eq!(order_book.price_for_quantity(Side::Buy, quantity("0.011")), None);
+
+        assert_eq!(order_book.price_for_quantity(Side::Sell, quantity("0.005")), Some(price("98.00")));
+        assert_eq!(order_book.price_for_quantity(Side::Sell, quantity("0.009")), None);
+    }
+
+    #[test]
+    fn test_midpoint_orders_cross_at_mid() {
+        let mut order_book = new_book();
+        order_book.place_order(Side::Buy, price("99.00"), quantity("0.010"), 1).unwrap();
+        order_book.place_order(Side::Sell, price("101.01"), quantity("0.010"), 2).unwrap();
+        // (99.00 + 101.01) / 2 = 100.005, floored to 100.00
+        assert_eq!(order_book.mid_price(), Some(price("100.00")));
+
+        let trades = order_book.place_midpoint_order(Side::Sell, quantity("0.005"), 10).unwrap();
+        assert!(trades.is_empty());
+        assert_eq!(order_book.midpoint_quantity(Side::Sell), quantity("0.005"));
+
+        let trades = order_book.place_midpoint_order(Side::Buy, quantity("0.003"), 11).unwrap();
+        assert_eq!(
+            trades,
+            vec![Trade {
+                trade_id: Trade::derive_id(10, 11, 0),
+                ..Trade::new(price("100.00"), quantity("0.003"), 10, 11).with_metadata(0, 3)
+            }]
+        );
+        assert_eq!(order_book.midpoint_quantity(Side::Sell), quantity("0.002"));
+        assert_eq!(order_book.midpoint_quantity(Side::Buy), 0);
+
+        // Lit book untouched
+        assert_eq!(order_book.best_buy(), Some((price("99.00"), quantity("0.010"))));
+        assert_eq!(order_book.best_sell(), Some((price("101.01"), quantity("0.010"))));
+
+        // Ids are shared with the lit book
+        let result = order_book.place_order(Side::Buy, price("98.00"), quantity("0.001"), 10);
+        assert_eq!(result, Err(OrderBookError::DuplicateOrderId(10)));
+    }
+
+    #[test]
+    fn test_mid_price_on_tick_rounds_per_mode() {
+        let instrument = new_book().instrument.with_tick_size(5);
+        let mut order_book = OrderBook::new(instrument);
+        assert_eq!(order_book.mid_price_on_tick(RoundingMode::HalfUp), None);
+        order_book.place_order(Side::Buy, price("100.00"), quantity("0.010"), 1).unwrap();
+        order_book.place_order(Side::Sell, price("100.05"), quantity("0.010"), 2).unwrap();
+        // The exact mid 100.025 sits halfway between the 100.00 and 100.05 ticks
+        let on_tick = |mode| order_book.mid_price_on_tick(mode);
+        assert_eq!(on_tick(RoundingMode::HalfUp), Some(price("100.05")));
+        assert_eq!(on_tick(RoundingMode::Ceil), Some(price("100.05")));
+        assert_eq!(on_tick(RoundingMode::Floor), Some(price("100.00")));
+        assert_eq!(on_tick(RoundingMode::Truncate), Some(price("100.00")));
+
+        order_book.place_order(Side::Sell, price("100.10"), quantity("0.010"), 3).unwrap();
+        order_book.cancel_order(2).unwrap();
+        // A mid already on the grid is left alone
+        for mode in [RoundingMode::HalfUp, RoundingMode::Ceil, RoundingMode::Floor] {
+            assert_eq!(order_book.mid_price_on_tick(mode), Some(price("100.05")));
+        }
+    }
+
+    #[test]
+    fn test_midpoint_trades_print_on_tick() {
+        let instrument = new_book().instrument.with_tick_size(5);
+        for (rounding, expected) in
+            [(RoundingMode::Truncate, price("100.00")), (RoundingMode::HalfUp, price("100.05"))]
+        {
+            let mut order_book =
+                OrderBook::new(instrument.clone()).with_midpoint_rounding(rounding);
+            order_book.place_order(Side::Buy, price("100.00"), quantity("0.010"), 1).unwrap();
+            order_book.place_order(Side::Sell, price("100.05"), quantity("0.010"), 2).unwrap();
+            order_book.place_midpoint_order(Side::Sell, quantity("0.002"), 10).unwrap();
+            let trades = order_book.place_midpoint_order(Side::Buy, quantity("0.002"), 11).unwrap();
+            assert_eq!(trades.len(), 1);
+            assert_eq!(trades[0].price, expected);
+            assert_eq!(trades[0].price % 5, 0);
+        }
+    }
+
+    #[test]
+    fn test_midpoint_order_rests_without_mid() {
+        let mut order_book = new_book();
+        order_book.place_midpoint_order(Side::Sell, quantity("0.002"), 1).unwrap();
+        let trades = order_book.place_midpoint_order(Side::Buy, quantity("0.002"), 2).unwrap();
+        assert!(trades.is_empty());
+        assert_eq!(order_book.mid_price(), None);
+        assert_eq!(order_book.midpoint_quantity(Side::Buy), quantity("0.002"));
+        assert_eq!(order_book.midpoint_quantity(Side::Sell), quantity("0.002"));
+    }
+
+    #[test]
+    fn test_level_callbacks_fire_on_create_and_remove() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut order_book = new_book();
+        let added = Arc::clone(&events);
+        order_book.on_level_added(move |side, p| added.lock().unwrap().push(("added", side, p)));
+        let removed = Arc::clone(&events);
+        order_book.on_level_removed(move |side, p| removed.lock().unwrap().push(("removed", side, p)));
+
+        order_book.place_order(Side::Sell, price("100.00"), quantity("0.002"), 1).unwrap();
+        assert_eq!(*events.lock().unwrap(), vec![("added", Side::Sell, price("100.00"))]);
+
+        // Joining an existing level fires nothing
+        order_book.place_order(Side::Sell, price("100.00"), quantity("0.002"), 2).unwrap();
+        order_book.place_order(Side::Sell, price("101.00"), quantity("0.002"), 3).unwrap();
+        events.lock().unwrap().clear();
+
+        // Sweep removes 100.00, rests the remainder as a new bid level
+        order_book.place_order(Side::Buy, price("100.00"), quantity("0.005"), 4).unwrap();
+        order_book
+            .cancel_by_attributes(Side::Sell, price("101.00"), quantity("0.002"))
+            .unwrap();
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                ("removed", Side::Sell, price("100.00")),
+                ("added", Side::Buy, price("100.00")),
+                ("removed", Side::Sell, price("101.00")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_depth_with_sequence_advances() {
+        let mut order_book = new_book();
+        let (seq0, depth0) = order_book.depth_with_sequence(Side::Buy, 5);
+        assert!(depth0.is_empty());
+
+        order_book.place_order(Side::Buy, price("100.00"), quantity("0.001"), 1).unwrap();
+        let (seq1, depth1) = order_book.depth_with_sequence(Side::Buy, 5);
+        assert!(seq1 > seq0);
+        assert_eq!(depth1, vec![(price("100.00"), quantity("0.001"))]);
+
+        // Queries alone do not advance the sequence
+        assert_eq!(order_book.depth_with_sequence(Side::Sell, 5).0, seq1);
+    }
+
+    #[test]
+    fn test_expire_after_ops_sweeps_on_schedule() {
+        let mut order_book = new_book().with_expire_after_ops(3);
+        order_book.place_order(Side::Buy, price("99.00"), quantity("0.001"), 1).unwrap();
+        order_book.place_order(Side::Sell, price("105.00"), quantity("0.001"), 2).unwrap();
+        order_book.place_order(Side::Sell, price("106.00"), quantity("0.001"), 3).unwrap();
+        order_book
+            .cancel_by_attributes(Side::Sell, price("106.00"), quantity("0.001"))
+            .unwrap();
+        // Three operations after order 1: it is gone, order 2 is not yet
+        assert_eq!(order_book.best_buy(), None);
+        assert_eq!(order_book.best_sell(), Some((price("105.00"), quantity("0.001"))));
+
+        // Rejected placements are not operations
+        assert!(order_book.place_order(Side::Buy, price("98.00"), 0, 4).is_err());
+        assert_eq!(order_book.best_sell(), Some((price("105.00"), quantity("0.001"))));
+
+        order_book.place_order(Side::Buy, price("98.00"), quantity("0.001"), 5).unwrap();
+        assert_eq!(order_book.best_sell(), None);
+        assert_eq!(order_book.best_buy(), Some((price("98.00"), quantity("0.001"))));
+    }
+
+    #[test]
+    fn test_midpoint_operations_publish_expiry_sweeps() {
+        let updates = Arc::new(Mutex::new(Vec::new()));
+        let mut order_book = new_book().with_expire_after_ops(1);
+        order_book.place_order(Side::Buy, price("99.00"), quantity("0.001"), 1).unwrap();
+        let sink = Arc::clone(&updates);
+        order_book.on_l2_update(1, move |update| sink.lock().unwrap().push(update.clone()));
+
+        // The midpoint placement is the next operation and expires order 1
+        order_book.place_midpoint_order(Side::Sell, quantity("0.001"), 2).unwrap();
+        assert_eq!(order_book.best_buy(), None);
+        let published = updates.lock().unwrap().clone();
+        assert_eq!(published.len(), 1);
+        assert_eq!((published[0].bids.clone(), published[0].asks.clone()), (Some(vec![]), None));
+
+        order_book.place_order(Side::Sell, price("101.00"), quantity("0.001"), 3).unwrap();
+        updates.lock().unwrap().clear();
+        // Cancelling from the pool is the next operation and expires order 3
+        order_book.cancel_order(2).unwrap();
+        assert_eq!(order_book.best_sell(), None);
+        assert_eq!(updates.lock().unwrap()[0].asks, Some(vec![]));
+    }
+
+    #[test]
+    fn test_expire_after_ops_follows_requeued_icebergs() {
+        let mut order_book = new_book().with_expire_after_ops(2);
+        order_book
+            .place_iceberg(Side::Sell, price("100.00"), quantity("0.001"), quantity("0.003"), 1)
+            .unwrap();
+        // The slice is restamped on replenishment but the order still expires
+        order_book.place_order(Side::Buy, price("100.00"), quantity("0.001"), 2).unwrap();
+        assert_eq!(order_book.best_sell(), Some((price("100.00"), quantity("0.001"))));
+        order_book.place_order(Side::Buy, price("99.00"), quantity("0.001"), 3).unwrap();
+        assert_eq!(order_book.best_sell(), None);
+    }
+
+    #[test]
+    fn test_expire_after_ops_skips_filled_orders() {
+        let mut order_book = new_book().with_expire_after_ops(2);
+        order_book.place_order(Side::Buy, price("99.00"), quantity("0.001"), 1).unwrap();
+        order_book.place_order(Side::Sell, price("99.00"), quantity("0.001"), 2).unwrap();
+        // Id 1 reused by a new resting order; the stale entry must not expire it
+        order_book.place_order(Side::Buy, price("99.00"), quantity("0.002"), 1).unwrap();
+        assert_eq!(order_book.best_buy(), Some((price("99.00"), quantity("0.002"))));
+    }
+
+    #[test]
+    fn test_expire_after_ops_runs_from_the_latest_reprice() {
+        let mut order_book = new_book().with_expire_after_ops(3);
+        order_book.place_order(Side::Buy, price("99.00"), quantity("0.001"), 1).unwrap();
+        order_book.place_order(Side::Sell, price("105.00"), quantity("0.001"), 2).unwrap();
+        order_book.amend_price(1, price("98.00")).unwrap();
+        // The entry from the first placement is stale and leaves order 1 alone
+        order_book.place_order(Side::Sell, price("106.00"), quantity("0.001"), 3).unwrap();
+        assert_eq!(order_book.best_buy(), Some((price("98.00"), quantity("0.001"))));
+        order_book.place_order(Side::Sell, price("107.00"), quantity("0.001"), 4).unwrap();
+        assert_eq!(order_book.best_sell(), Some((price("106.00"), quantity("0.001"))));
+        order_book.place_order(Side::Sell, price("108.00"), quantity("0.001"), 5).unwrap();
+        assert_eq!(order_book.best_buy(), None);
+    }
+    // --- core matching tests ---
+
+    #[test]
+    fn test_dump_lists_every_order_and_cached_bests() {
+        let mut book = new_book();
+        book.place_order(Side::Buy, price("99.00"), quantity("0.010"), 1).unwrap();
+        book.place_order(Side::Buy, price("100.00"), quantity("0.020"), 2).unwrap();
+        book.place_order(Side::Buy, price("100.00"), quantity("0.005"), 3).unwrap();
+        book.place_order(Side::Sell, price("101.00"), quantity("0.030"), 4).unwrap();
+
+        let dump = book.dump();
+        assert_eq!(dump.instrument, book.instrument);
+        assert_eq!(dump.best_buy, book.best_buy());
+        assert_eq!(dump.best_sell, book.best_sell());
+        assert_eq!(dump.next_timestamp, 4);
+
+        let buy_prices: Vec<Price> = dump.buy_levels.iter().map(|l| l.price).collect();
+        assert_eq!(buy_prices, vec![price("100.00"), price("99.00")]);
+        let top = &dump.buy_levels[0];
+        assert_eq!(top.total_quantity, quantity("0.025"));
+        assert_eq!(
+            top.orders,
+            vec![
+                OrderDump { id: 2, quantity: quantity("0.020"), timestamp: 1 },
+                OrderDump { id: 3, quantity: quantity("0.005"), timestamp: 2 },
+            ]
+        );
+
+        let ids: Vec<Id> = dump
+            .buy_levels
+            .iter()
+            .chain(&dump.sell_levels)
+            .flat_map(|level| level.orders.iter().map(|o| o.id))
+            .collect();
+        assert_eq!(ids, vec![2, 3, 1, 4]);
+    }
+
+    #[test]
+    fn test_pre_open_rests_crossed_orders_until_continuous() {
+        let mut book = new_book();
+        book.set_phase(MarketPhase::PreOpen);
+        assert!(book.place_order(Side::Sell, price("99.00"), quantity("0.010"), 1).unwrap().is_empty());
+        assert!(book.place_order(Side::Buy, price("101.00"), quantity("0.015"), 2).unwrap().is_empty());
+        assert_eq!(book.best_buy(), Some((price("101.00"), quantity("0.015"))));
+        assert_eq!(book.best_sell(), Some((price("99.00"), quantity("0.010"))));
+        assert!(matches!(
+            book.place_taker_only(Side::Buy, price("101.00"), quantity("0.005"), 3),
+            Err(OrderBookError::WouldRest { id: 3, .. })
+        ));
+
+        let trades = book.set_phase(MarketPhase::Continuous);
+        // The older sell is the maker and sets the price
+        assert_eq!(
+            trades,
+            vec![Trade {
+                trade_id: Trade::derive_id(1, 2, 0),
+                ..Trade::new(price("99.00"), quantity("0.010"), 1, 2).with_metadata(0, 1)
+            }]
+        );
+        assert_eq!(book.best_buy(), Some((price("101.00"), quantity("0.005"))));
+        assert_eq!(book.best_sell(), None);
+        assert_eq!(book.stats().trades, 1);
+    }
+
+    #[test]
+    fn test_closed_phase_rejects_placements() {
+        let mut book = new_book();
+        book.place_order(Side::Buy, price("100.00"), quantity("0.010"), 1).unwrap();
+        assert!(book.set_phase(MarketPhase::Closed).is_empty());
+        assert_eq!(
+            book.place_order(Side::Sell, price("100.00"), quantity("0.010"), 2),
+            Err(OrderBookError::MarketClosed { id: 2 })
+        );
+        assert_eq!(book.best_buy(), Some((price("100.00"), quantity("0.010"))));
+
+        book.set_phase(MarketPhase::Continuous);
+        let trades = book.place_order(Side::Sell, price("100.00"), quantity("0.010"), 2).unwrap();
+        assert_eq!(trades.len(), 1);
+    }
+
+    #[test]
+    fn test_weighted_price_over_top_levels() {
+        assert_eq!(book! {}.weighted_price(Side::Buy, 3), None);
+        let book = book! {
+            buy 100.00 x 0.010 @id 1;
+            buy 99.50 x 0.020 @id 2;
+            buy 99.00 x 0.030 @id 3;
+            buy 90.00 x 1.000 @id 4;
+        };
+
+        // (10000 * 10000 + 9950 * 20000 + 9900 * 30000) / 60000 = 9933.33..
+        let weighted = book.weighted_price(Side::Buy, 3).unwrap();
+        assert_eq!(weighted.round_dp(2), Decimal::new(993333, 2));
+        assert_eq!(book.weighted_price(Side::Buy, 1), Some(Decimal::from(10000)));
+        assert_eq!(book.weighted_price(Side::Sell, 3), None);
+    }
+
+    #[test]
+    fn test_concurrent_readers_see_consistent_caches() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::RwLock;
+        use std::thread;
+
+        let book = Arc::new(RwLock::new(new_book()));
+        let done = Arc::new(AtomicBool::new(false));
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let book = Arc::clone(&book);
+                let done = Arc::clone(&done);
+                thread::spawn(move || {
+                    let mut checks = 0u64;
+                    loop {
+                        let finished = done.load(Ordering::Acquire);
+                        let book = book.read().unwrap();
+                        let best_buy = book
+                            .buy_side
+                            .iter()
+                            .next_back()
+                            .map(|(p, level)| (*p, level.total_quantity));
+                        let best_sell = book
+                            .sell_side
+                            .iter()
+                            .next()
+                            .map(|(p, level)| (*p, level.total_quantity));
+                        assert_eq!(book.best_buy(), best_buy);
+                        assert_eq!(book.best_sell(), best_sell);
+                        assert_eq!(book.depth(Side::Buy, 1).first().copied(), best_buy);
+                        if let (Some((bid, _)), Some((ask, _))) = (best_buy, best_sell) {
+                            assert!(bid < ask);
+                            assert!(book.mid_price().is_some());
+                        }
+                        checks += 1;
+                        if finished {
+                            return checks;
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        // Deterministic mix of resting and crossing orders around 100.00
+        for id in 1..=2000u64 {
+            let side = if id % 2 == 0 { Side::Buy } else { Side::Sell };
+            let offset = (id * 7919 % 200) as u128;
+            let order_price = price("99.00") + offset;
+            let order_quantity = quantity("0.001") * (1 + (id % 5) as u128);
+            book.write()
+                .unwrap()
+                .place_order(side, order_price, order_quantity, id)
+                .unwrap();
+        }
+        done.store(true, Ordering::Release);
+
+        for reader in readers {
+            assert!(reader.join().unwrap() > 0);
+        }
+    }
+
+    #[test]
+    fn test_recompute_caches_repairs_stale_bests() {
+        let mut book = new_book();
+        book.place_order(Side::Buy, price("99.00"), quantity("0.010"), 1).unwrap();
+        book.place_order(Side::Sell, price("101.00"), quantity("0.010"), 2).unwrap();
+
+        // Bypass the public API to leave the caches stale
+        let mut level = PriceLevel::new(price("100.00"));
+        level.add_order(Order::new(3, Side::Buy, price("100.00"), quantity("0.020"), 2));
+        book.buy_side.insert(price("100.00"), level);
+        book.sell_side.clear();
+        assert_eq!(book.best_buy(), Some((price("99.00"), quantity("0.010"))));
+        assert_eq!(book.best_sell(), Some((price("101.00"), quantity("0.010"))));
+
+        book.recompute_caches();
+        assert_eq!(book.best_buy(), Some((price("100.00"), quantity("0.020"))));
+        assert_eq!(book.best_sell(), None);
+    }
+
+    #[test]
+    fn test_tags_carried_into_trades() {
+        let mut book = new_book();
+        book.place_tagged_order(Side::Sell, price("100.00"), quantity("0.010"), 1, 7).unwrap();
+        book.place_order(Side::Sell, price("100.50"), quantity("0.010"), 2).unwrap();
+
+        let trades = book
+            .place_tagged_order(Side::Buy, price("100.50"), quantity("0.015"), 3, 42)
+            .unwrap();
+        assert_eq!(trades.len(), 2);
+        assert_eq!((trades[0].maker_tag, trades[0].taker_tag), (Some(7), Some(42)));
+        assert_eq!((trades[1].maker_tag, trades[1].taker_tag), (None, Some(42)));
+
+        let trades = book.place_order(Side::Buy, price("100.50"), quantity("0.001"), 4).unwrap();
+        assert_eq!((trades[0].maker_tag, trades[0].taker_tag), (None, None));
+    }
+
+    #[test]
+    fn test_zero_quantity_resting_order_never_trades() {
+        let mut book = new_book();
+        book.place_order(Side::Sell, price("100.00"), quantity("0.010"), 1).unwrap();
+        book.place_order(Side::Sell, price("100.00"), quantity("0.010"), 2).unwrap();
+        // Force a zero remainder at the front, bypassing validation
+        book.sell_side.get_mut(&price("100.00")).unwrap().update_front_order_quantity(1, 0);
+
+        let trades = book.place_order(Side::Buy, price("100.00"), quantity("0.004"), 3).unwrap();
+        assert_eq!(
+            trades,
+            vec![Trade {
+                trade_id: Trade::derive_id(2, 3, 0),
+                ..Trade::new(price("100.00"), quantity("0.004"), 2, 3).with_metadata(0, 2)
+            }]
+        );
+        assert!(!book.id_index.contains_key(&1));
+    }
+
+    #[test]
+    fn test_no_zero_quantity_trades_across_matching_modes() {
+        // Small deterministic LCG so the flow is reproducible without extra dependencies
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = |bound: u64| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 33) % bound
+        };
+
+        let mut book = new_book();
+        let mut trades = Vec::new();
+        for id in 1..=3000u64 {
+            let side = if next(2) == 0 { Side::Buy } else { Side::Sell };
+            let order_price = price("99.00") + next(200) as u128;
+            let order_quantity = quantity("0.001") * (1 + next(20) as u128);
+            let result = match next(10) {
+                0 => book.place_taker_only(side, order_price, order_quantity, id),
+                1 => book.place_midpoint_order(side, order_quantity, id),
+                2 => Ok(book.set_phase(MarketPhase::PreOpen)),
+                3 => Ok(book.set_phase(MarketPhase::Continuous)),
+                _ => book.place_order(side, order_price, order_quantity, id),
+            };
+            if let Ok(produced) = result {
+                trades.extend(produced);
+            }
+        }
+        trades.extend(book.set_phase(MarketPhase::Continuous));
+
+        assert!(!trades.is_empty());
+        assert!(trades.iter().all(|trade| trade.quantity > 0));
+    }
+
+    #[test]
+    fn test_displayed_and_hidden_volume_reconcile() {
+        let mut book = new_book();
+        assert_eq!(book.hidden_volume(Side::Buy), 0);
+        book.place_order(Side::Buy, price("99.00"), quantity("0.010"), 1).unwrap();
+        book.place_order(Side::Buy, price("98.00"), quantity("0.020"), 2).unwrap();
+        book.place_order(Side::Sell, price("101.00"), quantity("0.005"), 3).unwrap();
+        book.place_midpoint_order(Side::Buy, quantity("0.007"), 4).unwrap();
+        book.place_midpoint_order(Side::Buy, quantity("0.003"), 5).unwrap();
+
+        assert_eq!(book.displayed_volume(Side::Buy), quantity("0.030"));
+        assert_eq!(book.hidden_volume(Side::Buy), quantity("0.010"));
+        assert_eq!(book.displayed_volume(Side::Sell), quantity("0.005"));
+        assert_eq!(book.hidden_volume(Side::Sell), 0);
+
+        let resting: Quantity = book
+            .resting_orders()
+            .iter()
+            .filter(|order| order.side == Side::Buy)
+            .map(|order| order.quantity)
+            .sum::<Quantity>()
+            + book.midpoint_buys.iter().map(|order| order.quantity).sum::<Quantity>();
+        assert_eq!(
+            book.displayed_volume(Side::Buy) + book.hidden_volume(Side::Buy),
+            resting
+        );
+    }
+
+    #[cfg(feature = "match-metrics")]
+    #[test]
+    fn test_match_metrics_count_levels_and_orders() {
+        let mut book = new_book();
+        book.place_order(Side::Sell, price("100.00"), quantity("0.010"), 1).unwrap();
+        book.place_order(Side::Sell, price("100.00"), quantity("0.010"), 2).unwrap();
+        book.place_order(Side::Sell, price("101.00"), quantity("0.010"), 3).unwrap();
+        book.place_order(Side::Sell, price("102.00"), quantity("0.010"), 4).unwrap();
+
+        book.place_order(Side::Buy, price("102.00"), quantity("0.035"), 5).unwrap();
+        let metrics = book.stats().last_match;
+        assert_eq!(metrics.levels_visited, 3);
+        assert_eq!(metrics.orders_visited, 4);
+        assert_eq!(book.stats().levels_visited_histogram.get(&0), Some(&4));
+        assert_eq!(book.stats().levels_visited_histogram.get(&3), Some(&1));
+    }
+
+    #[cfg(feature = "match-metrics")]
+    #[test]
+    fn test_match_metrics_count_orders_without_fills() {
+        // A one-lot fill split pro rata reads all three orders but trades once
+        let mut book = three_order_level(MatchingPolicy::ProRata);
+        let trades = book.place_order(Side::Buy, price("100.00"), quantity("0.001"), 4).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(book.stats().last_match.orders_visited, 3);
+    }
+
+    #[test]
+    fn test_is_marketable_against_opposite_best() {
+        let book = book! {
+            buy 99.00 x 0.010 @id 1;
+            sell 101.00 x 0.010 @id 2;
+        };
+        assert!(book.is_marketable(Side::Buy, price("101.00")));
+        assert!(book.is_marketable(Side::Buy, price("102.00")));
+        assert!(!book.is_marketable(Side::Buy, price("100.99")));
+        assert!(book.is_marketable(Side::Sell, price("99.00")));
+        assert!(book.is_marketable(Side::Sell, price("50.00")));
+        assert!(!book.is_marketable(Side::Sell, price("99.01")));
+
+        let one_sided = book! { buy 99.00 x 0.010 @id 1 };
+        assert!(!one_sided.is_marketable(Side::Buy, price("1000.00")));
+    }
+
+    #[test]
+    fn test_trades_for_order_by_taker_id() {
+        let mut book = new_book().with_taker_trade_index(2);
+        book.place_order(Side::Sell, price("100.00"), quantity("0.010"), 1).unwrap();
+        book.place_order(Side::Sell, price("101.00"), quantity("0.010"), 2).unwrap();
+        book.place_order(Side::Sell, price("102.00"), quantity("0.010"), 3).unwrap();
+
+        let fills = book.place_order(Side::Buy, price("101.00"), quantity("0.015"), 10).unwrap();
+        assert_eq!(book.trades_for_order(10), fills);
+        assert!(book.trades_for_order(1).is_empty());
+
+        book.place_order(Side::Buy, price("101.00"), quantity("0.001"), 11).unwrap();
+        book.place_order(Side::Buy, price("102.00"), quantity("0.001"), 12).unwrap();
+        // Capacity 2: the oldest taker is evicted
+        assert!(book.trades_for_order(10).is_empty());
+        assert_eq!(book.trades_for_order(11).len(), 1);
+        assert_eq!(book.trades_for_order(12).len(), 1);
+
+        let mut unindexed = new_book();
+        unindexed.place_order(Side::Sell, price("100.00"), quantity("0.010"), 1).unwrap();
+        unindexed.place_order(Side::Buy, price("100.00"), quantity("0.010"), 2).unwrap();
+        assert!(unindexed.trades_for_order(2).is_empty());
+    }
+
+    #[test]
+    fn test_cancel_best_only_at_best_level() {
+        let mut book = book! {
+            buy 100.00 x 0.010 @id 1;
+            buy 100.00 x 0.020 @id 2;
+            buy 99.00 x 0.010 @id 3;
+            sell 101.00 x 0.010 @id 4;
+        };
+
+        let cancelled = book.cancel_best(Side::Buy, 2).unwrap();
+        assert_eq!(cancelled.id, 2);
+        assert_eq!(book.best_buy(), Some((price("100.00"), quantity("0.010"))));
+
+        assert_eq!(
+            book.cancel_best(Side::Buy, 3),
+            Err(OrderBookError::NotAtBest { id: 3, side: Side::Buy })
+        );
+        assert_eq!(
+            book.cancel_best(Side::Buy, 4),
+            Err(OrderBookError::NotAtBest { id: 4, side: Side::Buy })
+        );
+        assert_eq!(book.cancel_best(Side::Sell, 9), Err(OrderBookError::UnknownOrderId(9)));
+
+        book.cancel_best(Side::Buy, 1).unwrap();
+        assert_eq!(book.best_buy(), Some((price("99.00"), quantity("0.010"))));
+        assert_eq!(book.stats().orders_cancelled, 2);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "cached best buy out of sync")]
+    fn test_debug_cache_check_catches_broken_cache_update() {
+        let mut book = book! { buy 100.00 x 0.010 @id 1 };
+        // Simulate a mutation that forgot to refresh the buy cache
+        book.best_buy = Some((price("99.00"), quantity("0.010")));
+        book.place_order(Side::Sell, price("101.00"), quantity("0.010"), 2).unwrap();
+    }
+
+    #[test]
+    fn test_cancel_order_top_of_book() {
+        let mut book = book! {
+            buy 100.00 x 0.010 @id 1;
+            buy 99.00 x 0.020 @id 2;
+            sell 101.00 x 0.010 @id 3;
+        };
+        let cancelled = book.cancel_order(1).unwrap();
+        assert_eq!((cancelled.id, cancelled.quantity), (1, quantity("0.010")));
+        assert_eq!(book.best_buy(), Some((price("99.00"), quantity("0.020"))));
+        assert_eq!(book.depth(Side::Buy, 5).len(), 1);
+        assert!(!book.id_index.contains_key(&1));
+    }
+
+    #[test]
+    fn test_cancel_order_mid_book() {
+        let mut book = book! {
+            buy 100.00 x 0.010 @id 1;
+            buy 99.00 x 0.020 @id 2;
+            buy 99.00 x 0.005 @id 3;
+            buy 98.00 x 0.010 @id 4;
+        };
+        book.cancel_order(2).unwrap();
+        assert_eq!(
+            book.depth(Side::Buy, 5),
+            vec![
+                (price("100.00"), quantity("0.010")),
+                (price("99.00"), quantity("0.005")),
+                (price("98.00"), quantity("0.010")),
+            ]
+        );
+        assert_eq!(book.best_buy(), Some((price("100.00"), quantity("0.010"))));
+        assert_eq!(book.stats().orders_cancelled, 1);
+    }
+
+    #[test]
+    fn test_cancel_order_filled_or_unknown_id() {
+        let mut book = book! {
+            sell 100.00 x 0.010 @id 1;
+            buy 100.00 x 0.010 @id 2;
+        };
+        assert_eq!(book.cancel_order(1), Err(OrderBookError::UnknownOrderId(1)));
+        assert_eq!(book.cancel_order(2), Err(OrderBookError::UnknownOrderId(2)));
+        assert_eq!(book.cancel_order(42), Err(OrderBookError::UnknownOrderId(42)));
+
+        book.place_midpoint_order(Side::Buy, quantity("0.003"), 3).unwrap();
+        assert_eq!(book.cancel_order(3).unwrap().quantity, quantity("0.003"));
+        assert_eq!(book.midpoint_quantity(Side::Buy), 0);
+        assert_eq!(book.cancel_order(3), Err(OrderBookError::UnknownOrderId(3)));
+    }
+
+    #[test]
+    fn test_set_instrument_allows_rename_only() {
+        use crate::types::Asset;
+
+        let mut book = book! { buy 100.00 x 0.010 @id 1 };
+        let renamed = Instrument::new(Asset::new("XBT", 6), Asset::new("USDT", 2));
+        book.set_instrument(renamed.clone()).unwrap();
+        assert_eq!(book.instrument, renamed);
+        assert_eq!(book.format_depth(Side::Buy, 1), vec!["0.01 XBT @ 100 USDT"]);
+
+        let redenominated = Instrument::new(Asset::new("XBT", 8), Asset::new("USDT", 2));
+        assert_eq!(
+            book.set_instrument(redenominated.clone()),
+            Err(OrderBookError::IncompatibleInstrument(Box::new(redenominated)))
+        );
+        let same_asset = Instrument::new(Asset::new("XBT", 6), Asset::new("XBT", 2));
+        assert!(book.set_instrument(same_asset).is_err());
+        assert_eq!(book.instrument, renamed);
+    }
+
+    #[test]
+    fn test_amend_quantity_down_keeps_priority() {
+        let mut book = book! {
+            sell 100.00 x 0.010 @id 1;
+            sell 100.00 x 0.010 @id 2;
+        };
+        book.amend_quantity(1, quantity("0.004")).unwrap();
+        assert_eq!(book.best_sell(), Some((price("100.00"), quantity("0.014"))));
+
+        let trades = book.place_order(Side::Buy, price("100.00"), quantity("0.004"), 3).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].maker_id, 1);
+        assert_eq!(book.stats().orders_amended, 1);
+    }
+
+    #[test]
+    fn test_amend_quantity_up_loses_priority() {
+        let mut book = book! {
+            sell 100.00 x 0.010 @id 1;
+            sell 100.00 x 0.010 @id 2;
+        };
+        book.amend_quantity(1, quantity("0.015")).unwrap();
+        assert_eq!(book.best_sell(), Some((price("100.00"), quantity("0.025"))));
+
+        let trades = book.place_order(Side::Buy, price("100.00"), quantity("0.012"), 3).unwrap();
+        let makers: Vec<Id> = trades.iter().map(|t| t.maker_id).collect();
+        assert_eq!(makers, vec![2, 1]);
+        assert_eq!(book.best_sell(), Some((price("100.00"), quantity("0.013"))));
+    }
+
+    #[test]
+    fn test_amend_quantity_rejects_zero_and_unknown() {
+        let mut book = book! { buy 100.00 x 0.010 @id 1 };
+        assert_eq!(
+            book.amend_quantity(1, 0),
+            Err(OrderBookError::ZeroQuantity { id: 1, quantity: 0 })
+        );
+        assert_eq!(
+            book.amend_quantity(2, quantity("0.001")),
+            Err(OrderBookError::UnknownOrderId(2))
+        );
+        assert_eq!(book.best_buy(), Some((price("100.00"), quantity("0.010"))));
+    }
+
+    #[test]
+    fn test_amend_quantity_validates_like_a_placement() {
+        let instrument = new_book().instrument.with_lot_size(quantity("0.001"));
+        let mut book = OrderBook::new(instrument)
+            .with_notional_bounds(price("0.10"), price("5.00"));
+        book.place_order(Side::Buy, price("100.00"), quantity("0.010"), 1).unwrap();
+        assert_eq!(
+            book.amend_quantity(1, quantity("0.0015")),
+            Err(OrderBookError::Misaligned { value: quantity("0.0015"), step: quantity("0.001") })
+        );
+        assert!(matches!(
+            book.amend_quantity(1, quantity("0.060")),
+            Err(OrderBookError::AboveMaxNotional { id: 1, .. })
+        ));
+        let huge = u128::MAX - u128::MAX % quantity("0.001");
+        assert_eq!(
+            book.amend_quantity(1, huge),
+            Err(OrderBookError::NotionalOverflow { price: price("100.00"), quantity: huge })
+        );
+        assert_eq!(book.best_buy(), Some((price("100.00"), quantity("0.010"))));
+        assert_eq!(book.stats().orders_amended, 0);
+    }
+
+    #[test]
+    fn test_amend_quantity_resizes_iceberg_reserve() {
+        let mut book = new_book();
+        book.place_iceberg(Side::Sell, price("100.00"), quantity("0.002"), quantity("0.010"), 1)
+            .unwrap();
+        book.place_order(Side::Sell, price("100.00"), quantity("0.001"), 2).unwrap();
+
+        // Shrinking the total trims the reserve and keeps priority
+        book.amend_quantity(1, quantity("0.005")).unwrap();
+        let order = book.get_order(1).unwrap();
+        let split = (quantity("0.002"), quantity("0.003"));
+        assert_eq!((order.quantity, order.hidden_remaining), split);
+        let trades = book.place_order(Side::Buy, price("100.00"), quantity("0.001"), 3).unwrap();
+        assert_eq!(trades[0].maker_id, 1);
+
+        // Below the visible slice the reserve empties
+        book.amend_quantity(1, quantity("0.0005")).unwrap();
+        let order = book.get_order(1).unwrap();
+        assert_eq!((order.quantity, order.hidden_remaining), (quantity("0.0005"), 0));
+
+        // Growing the total refills the reserve at the back of the queue
+        book.amend_quantity(1, quantity("0.004")).unwrap();
+        let order = book.get_order(1).unwrap();
+        let split = (quantity("0.0005"), quantity("0.0035"));
+        assert_eq!((order.quantity, order.hidden_remaining), split);
+        let trades = book.place_order(Side::Buy, price("100.00"), quantity("0.001"), 4).unwrap();
+        assert_eq!(trades[0].maker_id, 2);
+        assert!(book.validate_invariants().is_ok());
+    }
+
+    #[test]
+    fn test_amend_price_repositions_without_crossing() {
+        let mut book = book! {
+            buy 99.00 x 0.010 @id 1;
+            buy 98.00 x 0.010 @id 2;
+            sell 101.00 x 0.010 @id 3;
+        };
+        let trades = book.amend_price(1, price("97.00")).unwrap();
+        assert!(trades.is_empty());
+        assert_eq!(
+            book.depth(Side::Buy, 5),
+            vec![(price("98.00"), quantity("0.010")), (price("97.00"), quantity("0.010"))]
+        );
+        assert_eq!(book.best_buy(), Some((price("98.00"), quantity("0.010"))));
+        assert_eq!(book.id_index.get(&1), Some(&(Side::Buy, price("97.00"))));
+        assert_eq!(book.stats().orders_amended, 1);
+        assert_eq!(book.stats().orders_placed, 3);
+    }
+
+    #[test]
+    fn test_amend_price_crossing_trades_and_rests_remainder() {
+        let mut book = book! {
+            buy 99.00 x 0.015 @id 1;
+            sell 101.00 x 0.010 @id 2;
+        };
+        let trades = book.amend_price(1, price("101.00")).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!((trades[0].maker_id, trades[0].taker_id), (2, 1));
+        assert_eq!(trades[0].price, price("101.00"));
+        assert_eq!(book.best_buy(), Some((price("101.00"), quantity("0.005"))));
+        assert_eq!(book.best_sell(), None);
+
+        // A fully filled amend leaves no id behind
+        book.place_order(Side::Sell, price("102.00"), quantity("0.005"), 3).unwrap();
+        book.amend_price(1, price("102.00")).unwrap();
+        assert!(book.is_empty());
+        assert_eq!(book.amend_price(1, price("100.00")), Err(OrderBookError::UnknownOrderId(1)));
+    }
+
+    #[test]
+    fn test_amend_price_validates_like_a_placement() {
+        let instrument = new_book().instrument.with_tick_size(5);
+        let mut book = OrderBook::new(instrument)
+            .with_notional_bounds(price("0.10"), price("1.50"))
+            .with_min_resting_duration(1);
+        book.place_order(Side::Buy, price("100.00"), quantity("0.010"), 1).unwrap();
+        assert_eq!(
+            book.amend_price(1, price("99.00")),
+            Err(OrderBookError::MinRestingTimeNotMet { id: 1, rested: 0, required: 1 })
+        );
+        book.place_order(Side::Sell, price("105.00"), quantity("0.001"), 2).unwrap();
+        assert_eq!(
+            book.amend_price(1, price("100.01")),
+            Err(OrderBookError::Misaligned { value: price("100.01"), step: 5 })
+        );
+        assert!(matches!(
+            book.amend_price(1, price("160.00")),
+            Err(OrderBookError::AboveMaxNotional { id: 1, .. })
+        ));
+        // u128::MAX happens to be a multiple of the tick
+        assert_eq!(
+            book.amend_price(1, u128::MAX),
+            Err(OrderBookError::NotionalOverflow { price: u128::MAX, quantity: quantity("0.010") })
+        );
+        assert_eq!(book.best_buy(), Some((price("100.00"), quantity("0.010"))));
+        assert_eq!(book.stats().orders_amended, 0);
+
+        book.amend_price(1, price("100.05")).unwrap();
+        assert_eq!(book.best_buy(), Some((price("100.05"), quantity("0.010"))));
+    }
+
+    #[test]
+    fn test_clear_emits_single_cleared_event() {
+        let mut book = book! {
+            buy 99.00 x 0.010 @id 1;
+            buy 98.00 x 0.010 @id 2;
+            sell 101.00 x 0.010 @id 3;
+        };
+        book.place_midpoint_order(Side::Buy, quantity("0.001"), 4).unwrap();
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&events);
+        book.on_level_removed(move |side, p| log.lock().unwrap().push(format!("removed {side} {p}")));
+        let log = Arc::clone(&events);
+        book.on_cleared(move || log.lock().unwrap().push("cleared".to_string()));
+
+        book.clear();
+        assert_eq!(*events.lock().unwrap(), vec!["cleared"]);
+        assert!(book.is_empty());
+        assert_eq!((book.best_buy(), book.best_sell()), (None, None));
+        assert_eq!(book.midpoint_quantity(Side::Buy), 0);
+        assert_eq!(book.cancel_order(1), Err(OrderBookError::UnknownOrderId(1)));
+        // Ids are free again after a clear
+        book.place_order(Side::Buy, price("99.00"), quantity("0.010"), 1).unwrap();
+    }
+
+    #[test]
+    fn test_place_maker_improving_acceptance_rule() {
+        let mut book = new_book();
+        // Empty own side: any non-crossing price is accepted
+        book.place_maker_improving(Side::Buy, price("99.00"), quantity("0.010"), 1).unwrap();
+        book.place_maker_improving(Side::Sell, price("101.00"), quantity("0.010"), 2).unwrap();
+
+        // Joining and improving the own-side best
+        book.place_maker_improving(Side::Buy, price("99.00"), quantity("0.010"), 3).unwrap();
+        book.place_maker_improving(Side::Buy, price("99.50"), quantity("0.010"), 4).unwrap();
+        book.place_maker_improving(Side::Sell, price("100.50"), quantity("0.010"), 5).unwrap();
+        assert_eq!(book.best_buy(), Some((price("99.50"), quantity("0.010"))));
+        assert_eq!(book.best_sell(), Some((price("100.50"), quantity("0.010"))));
+
+        assert_eq!(
+            book.place_maker_improving(Side::Buy, price("100.50"), quantity("0.010"), 6),
+            Err(OrderBookError::WouldTake { id: 6 })
+        );
+        assert_eq!(
+            book.place_maker_improving(Side::Buy, price("99.00"), quantity("0.010"), 7),
+            Err(OrderBookError::BehindBest { id: 7, price: price("99.00"), best: price("99.50") })
+        );
+        assert_eq!(
+            book.place_maker_improving(Side::Sell, price("101.00"), quantity("0.010"), 8),
+            Err(OrderBookError::BehindBest { id: 8, price: price("101.00"), best: price("100.50") })
+        );
+        assert_eq!(book.stats().orders_placed, 5);
+        assert_eq!(book.stats().trades, 0);
+    }
+
+    #[test]
+    fn test_market_buy_sweeps_three_levels() {
+        let mut book = book! {
+            sell 100.00 x 0.010 @id 1;
+            sell 101.00 x 0.010 @id 2;
+            sell 102.00 x 0.010 @id 3;
+            sell 103.00 x 0.010 @id 4;
+        };
+        let trades = book.place_market_order(Side::Buy, quantity("0.025"), 5).unwrap();
+        let fills: Vec<(Price, Quantity)> = trades.iter().map(|t| (t.price, t.quantity)).collect();
+        assert_eq!(
+            fills,
+            vec![
+                (price("100.00"), quantity("0.010")),
+                (price("101.00"), quantity("0.010")),
+                (price("102.00"), quantity("0.005")),
+            ]
+        );
+        assert_eq!(book.best_sell(), Some((price("102.00"), quantity("0.005"))));
+        assert_eq!(book.best_buy(), None);
+    }
+
+    #[test]
+    fn test_market_sell_empties_book_and_discards_remainder() {
+        let mut book = book! {
+            buy 100.00 x 0.010 @id 1;
+            buy 99.00 x 0.005 @id 2;
+        };
+        let trades = book.place_market_order(Side::Sell, quantity("0.050"), 3).unwrap();
+        let filled: Quantity = trades.iter().map(|t| t.quantity).sum();
+        assert_eq!(filled, quantity("0.015"));
+        assert!(book.is_empty());
+        assert_eq!(book.cancel_order(3), Err(OrderBookError::UnknownOrderId(3)));
+
+        assert_eq!(
+            book.place_market_order(Side::Sell, quantity("0.001"), 4),
+            Err(OrderBookError::InsufficientLiquidity { requested: quantity("0.001"), filled: 0 })
+        );
+    }
+
+    #[test]
+    fn test_twa_mid_over_bbo_history() {
+        let mut book = new_book().with_bbo_history(16);
+        assert_eq!(book.twa_mid(10), None);
+        book.place_order(Side::Buy, price("99.00"), quantity("0.010"), 1).unwrap(); // one-sided from 1
+        book.place_order(Side::Sell, price("101.00"), quantity("0.010"), 2).unwrap(); // mid 100.00 from 2
+        book.place_order(Side::Buy, price("100.00"), quantity("0.010"), 3).unwrap(); // mid 100.50 from 3
+        book.place_order(Side::Buy, price("98.00"), quantity("0.010"), 4).unwrap(); // no change
+        book.place_order(Side::Buy, price("97.00"), quantity("0.010"), 5).unwrap(); // now = 5
+        assert_eq!(book.bbo_history().len(), 3);
+
+        // [2, 3) at 10000 and [3, 5) at 10050; the one-sided interval is a gap
+        let expected = Decimal::from(10000 + 2 * 10050) / Decimal::from(3);
+        assert_eq!(book.twa_mid(3), Some(expected));
+        assert_eq!(book.twa_mid(100), Some(expected));
+        assert_eq!(book.twa_mid(1), Some(Decimal::from(10050)));
+        assert_eq!(book.twa_mid(0), None);
+
+        book.cancel_order(2).unwrap();
+        assert_eq!(book.twa_mid(0), None);
+        assert_eq!(book.bbo_history().back().unwrap().ask, None);
+    }
+
+    fn fok_book() -> OrderBook {
+        book! {
+            sell 100.00 x 0.010 @id 1;
+            sell 101.00 x 0.010 @id 2;
+            buy 98.00 x 0.010 @id 3;
+        }
+    }
+
+    #[test]
+    fn test_fok_fills_exactly() {
+        let mut book = fok_book();
+        let trades = book
+            .place_order_tif(Side::Buy, price("101.00"), quantity("0.020"), 10, TimeInForce::Fok)
+            .unwrap();
+        assert_eq!(trades.len(), 2);
+        assert_eq!(book.best_sell(), None);
+        assert_eq!(book.best_buy(), Some((price("98.00"), quantity("0.010"))));
+    }
+
+    #[test]
+    fn test_fok_short_by_one_lot_is_killed() {
+        let mut book = fok_book();
+        let before = book.dump();
+        let trades = book
+            .place_order_tif(Side::Buy, price("101.00"), quantity("0.021"), 10, TimeInForce::Fok)
+            .unwrap();
+        assert!(trades.is_empty());
+        assert_eq!(book.dump(), before);
+        assert_eq!(book.stats().orders_placed, 3);
+    }
+
+    #[test]
+    fn test_fok_and_taker_only_respect_fill_cap() {
+        let capped = || {
+            book! {
+                sell 100.00 x 0.010 @id 1;
+                sell 100.00 x 0.010 @id 2;
+                sell 100.00 x 0.010 @id 3;
+            }
+            .with_max_fills_per_order(2)
+        };
+        let mut book = capped();
+        let before = book.dump();
+        // Three makers are needed but only two fills are allowed
+        let trades = book
+            .place_order_tif(Side::Buy, price("100.00"), quantity("0.030"), 10, TimeInForce::Fok)
+            .unwrap();
+        assert!(trades.is_empty());
+        assert_eq!(book.dump(), before);
+        assert_eq!(
+            book.place_taker_only(Side::Buy, price("100.00"), quantity("0.030"), 11),
+            Err(OrderBookError::WouldRest { id: 11, remaining: quantity("0.010") })
+        );
+        assert_eq!(book.dump(), before);
+
+        let trades = book
+            .place_order_tif(Side::Buy, price("100.00"), quantity("0.020"), 12, TimeInForce::Fok)
+            .unwrap();
+        assert_eq!(trades.iter().map(|trade| trade.quantity).sum::<Quantity>(), quantity("0.020"));
+
+        let mut book = capped().with_matching_policy(MatchingPolicy::ProRata);
+        // A pro-rata split would touch all three makers, over the cap
+        assert!(book
+            .place_order_tif(Side::Buy, price("100.00"), quantity("0.003"), 10, TimeInForce::Fok)
+            .unwrap()
+            .is_empty());
+        assert_eq!(book.best_sell(), Some((price("100.00"), quantity("0.030"))));
+    }
+
+    #[test]
+    fn test_fok_and_taker_only_count_designated_maker_fills_against_cap() {
+        let capped = || {
+            let mut book = new_book().with_designated_maker(7, 50).with_max_fills_per_order(2);
+            book.place_account_order(Side::Sell, price("100.00"), quantity("0.020"), 1, 7).unwrap();
+            book.place_order(Side::Sell, price("100.00"), quantity("0.010"), 2).unwrap();
+            book
+        };
+        let mut book = capped();
+        let before = book.dump();
+        // The allocation and the FIFO pass both fill order 1, using up the cap
+        let trades = book
+            .place_order_tif(Side::Buy, price("100.00"), quantity("0.030"), 10, TimeInForce::Fok)
+            .unwrap();
+        assert!(trades.is_empty());
+        assert_eq!(book.dump(), before);
+        assert!(matches!(
+            book.place_taker_only(Side::Buy, price("100.00"), quantity("0.030"), 11),
+            Err(OrderBookError::WouldRest { id: 11, .. })
+        ));
+        assert_eq!(book.dump(), before);
+
+        let mut book = capped();
+        let trades = book
+            .place_order_tif(Side::Buy, price("100.00"), quantity("0.020"), 12, TimeInForce::Fok)
+            .unwrap();
+        assert_eq!(fills(&trades), vec![(1, quantity("0.010")), (1, quantity("0.010"))]);
+    }
+
+    #[test]
+    fn test_fok_without_crossing_price_is_killed() {
+        let mut book = fok_book();
+        let before = book.dump();
+        let trades = book
+            .place_order_tif(Side::Buy, price("99.99"), quantity("0.001"), 10, TimeInForce::Fok)
+            .unwrap();
+        assert!(trades.is_empty());
+        assert_eq!(book.dump(), before);
+
+        // Gtc through the same entry point rests as usual
+        book.place_order_tif(Side::Buy, price("99.99"), quantity("0.001"), 10, TimeInForce::Gtc)
+            .unwrap();
+        assert_eq!(book.best_buy(), Some((price("99.99"), quantity("0.001"))));
+    }
+
+    #[test]
+    fn test_place_order_at_older_timestamp_gains_priority() {
+        let mut book = book! {
+            sell 100.00 x 0.010 @id 1;
+            sell 100.00 x 0.010 @id 2;
+        };
+        // Older than order 2 (timestamp 1), tied with order 1 (timestamp 0)
+        book.place_order_at(Side::Sell, price("100.00"), quantity("0.010"), 3, 0).unwrap();
+        let queue: Vec<Id> = book.sell_side[&price("100.00")].orders.iter().map(|o| o.id).collect();
+        assert_eq!(queue, vec![1, 3, 2]);
+        assert_eq!(book.best_sell(), Some((price("100.00"), quantity("0.030"))));
+
+        // The sequence never moves backwards, and jumps past newer imports
+        assert_eq!(book.depth_with_sequence(Side::Sell, 0).0, 2);
+        book.place_order_at(Side::Sell, price("101.00"), quantity("0.010"), 4, 10).unwrap();
+        assert_eq!(book.depth_with_sequence(Side::Sell, 0).0, 11);
+
+        let trades = book.place_order(Side::Buy, price("100.00"), quantity("0.020"), 5).unwrap();
+        let makers: Vec<Id> = trades.iter().map(|t| t.maker_id).collect();
+        assert_eq!(makers, vec![1, 3]);
+    }
+
+    #[test]
+    fn test_notional_imbalance_opposes_quantity_imbalance() {
+        assert_eq!(new_book().notional_imbalance(5), None);
+
+        // More quantity bid, but far more value offered
+        let book = book! {
+            buy 10.00 x 0.900 @id 1;
+            buy 9.00 x 0.100 @id 2;
+            sell 100.00 x 0.300 @id 3;
+            sell 110.00 x 0.200 @id 4;
+        };
+        let bid_quantity: Quantity = book.depth(Side::Buy, 2).iter().map(|(_, q)| q).sum();
+        let ask_quantity: Quantity = book.depth(Side::Sell, 2).iter().map(|(_, q)| q).sum();
+        assert!(bid_quantity > ask_quantity);
+
+        // bids 9.0 + 0.9 = 9.9, asks 30 + 22 = 52 -> (9.9 - 52) / 61.9
+        let expected = Decimal::new(-421, 1) / Decimal::new(619, 1);
+        assert_eq!(book.notional_imbalance(2), Some(expected));
+        assert!(expected.is_sign_negative());
+
+        // Top level only: 9.0 vs 30
+        assert_eq!(book.notional_imbalance(1), Some(Decimal::new(-21, 0) / Decimal::new(39, 0)));
+        let one_sided = book! { buy 10.00 x 0.100 @id 1 };
+        assert_eq!(one_sided.notional_imbalance(3), Some(Decimal::ONE));
+    }
+
+    #[test]
+    fn test_post_only_rejects_at_best_ask_and_rests_below() {
+        let mut book = book! {
+            buy 99.00 x 0.010 @id 1;
+            sell 100.00 x 0.010 @id 2;
+        };
+        let before = book.dump();
+        assert_eq!(
+            book.place_post_only(Side::Buy, price("100.00"), quantity("0.005"), 3),
+            Err(OrderBookError::WouldCross {
+                price: price("100.00"),
+                best_opposite: price("100.00"),
+            })
+        );
+        assert_eq!(book.dump(), before);
+
+        book.place_post_only(Side::Buy, price("99.99"), quantity("0.005"), 3).unwrap();
+        assert_eq!(book.best_buy(), Some((price("99.99"), quantity("0.005"))));
+        assert_eq!(book.best_sell(), Some((price("100.00"), quantity("0.010"))));
+        assert_eq!(book.cancel_order(3).unwrap().quantity, quantity("0.005"));
+    }
+
+    #[test]
+    fn test_fill_cap_truncates_sweep() {
+        let mut book = book! {
+            sell 101.00 x 0.010 @id 1;
+            sell 102.00 x 0.010 @id 2;
+            sell 103.00 x 0.010 @id 3;
+        }
+        .with_max_fills_per_order(2);
+
+        let result = book
+            .place_order_detailed(Side::Buy, price("103.00"), quantity("0.030"), 4)
+            .unwrap();
+        assert!(result.truncated);
+        assert_eq!(result.trades.len(), 2);
+        assert_eq!(result.trades[1].price, price("102.00"));
+        assert_eq!(book.best_sell(), Some((price("103.00"), quantity("0.010"))));
+        assert_eq!(book.best_buy(), None);
+        assert!(book.cancel_order(4).is_err());
+
+        // A remainder that no longer crosses rests as usual
+        let result = book
+            .place_order_detailed(Side::Buy, price("102.50"), quantity("0.010"), 5)
+            .unwrap();
+        assert!(!result.truncated);
+        assert_eq!(book.best_buy(), Some((price("102.50"), quantity("0.010"))));
+    }
+
+    #[test]
+    fn test_get_order_reflects_partial_fill() {
+        let mut book = book! {
+            sell 100.00 x 0.010 @id 1;
+            sell 101.00 x 0.010 @id 2;
+        };
+        book.place_order(Side::Buy, price("100.00"), quantity("0.004"), 3).unwrap();
+
+        let order = book.get_order(1).unwrap();
+        assert_eq!(order.quantity, quantity("0.006"));
+        assert_eq!(order.timestamp, 0);
+        assert_eq!(book.get_order(2).unwrap().quantity, quantity("0.010"));
+        assert!(book.contains_order(1));
+
+        book.place_order(Side::Buy, price("100.00"), quantity("0.006"), 4).unwrap();
+        assert_eq!(book.get_order(1), None);
+        assert!(!book.contains_order(1));
+        assert!(!book.contains_order(3));
+        assert!(!book.contains_order(99));
+    }
+
+    #[test]
+    fn test_relative_spread_of_mid() {
+        let mut book = book! { buy 99.00 x 0.010 @id 1 };
+        assert_eq!(book.relative_spread(), None);
+
+        book.place_order(Side::Sell, price("101.00"), quantity("0.010"), 2).unwrap();
+        assert_eq!(book.relative_spread(), Some(Decimal::new(2, 2)));
+    }
+
+    #[test]
+    fn test_side_totals_after_fills_and_cancels() {
+        let mut book = book! {
+            buy 99.00 x 0.010 @id 1;
+            buy 99.00 x 0.020 @id 2;
+            buy 98.00 x 0.030 @id 3;
+            sell 101.00 x 0.010 @id 4;
+        };
+        assert_eq!(book.total_quantity(Side::Buy), quantity("0.060"));
+        assert_eq!(book.order_count(Side::Buy), 3);
+
+        book.place_order(Side::Sell, price("99.00"), quantity("0.015"), 5).unwrap();
+        assert_eq!(book.total_quantity(Side::Buy), quantity("0.045"));
+        assert_eq!(book.order_count(Side::Buy), 2);
+
+        book.cancel_order(3).unwrap();
+        assert_eq!(book.total_quantity(Side::Buy), quantity("0.015"));
+        assert_eq!(book.order_count(Side::Buy), 1);
+        assert_eq!(book.total_quantity(Side::Sell), quantity("0.010"));
+        assert_eq!(book.order_count(Side::Sell), 1);
+    }
+
+    #[test]
+    fn test_l2_updates_only_for_top_levels() {
+        let updates = Arc::new(Mutex::new(Vec::new()));
+        let mut book = book! {
+            buy 99.00 x 0.010 @id 1;
+            buy 98.00 x 0.010 @id 2;
+            sell 101.00 x 0.010 @id 3;
+        };
+        let sink = Arc::clone(&updates);
+        book.on_l2_update(2, move |update| sink.lock().unwrap().push(update.clone()));
+
+        book.place_order(Side::Buy, price("97.00"), quantity("0.010"), 4).unwrap();
+        assert!(updates.lock().unwrap().is_empty());
+
+        book.place_order(Side::Buy, price("98.00"), quantity("0.005"), 5).unwrap();
+        assert_eq!(
+            *updates.lock().unwrap(),
+            vec![L2Update {
+                sequence: 5,
+                bids: Some(vec![
+                    (price("99.00"), quantity("0.010")),
+                    (price("98.00"), quantity("0.015")),
+                ]),
+                asks: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_ioc_fills_available_and_discards_remainder() {
+        let mut book = book! { sell 100.00 x 0.010 @id 1 };
+        let trades = book
+            .place_order_tif(Side::Buy, price("100.00"), quantity("0.015"), 2, TimeInForce::Ioc)
+            .unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].quantity, quantity("0.010"));
+        assert!(book.is_empty());
+
+        book.place_order_tif(Side::Buy, price("99.00"), quantity("0.010"), 3, TimeInForce::Gtc)
+            .unwrap();
+        assert_eq!(book.best_buy(), Some((price("99.00"), quantity("0.010"))));
+    }
+
+    #[test]
+    fn test_gtd_order_expires_after_its_timestamp() {
+        let mut book = new_book();
+        book.place_order_tif(Side::Sell, price("100.00"), quantity("0.010"), 1, TimeInForce::Gtd(1))
+            .unwrap();
+
+        // Stamped 1, still within the expiry
+        let trades = book.place_order(Side::Buy, price("100.00"), quantity("0.004"), 2).unwrap();
+        assert_eq!(trades.len(), 1);
+
+        // Stamped 2, the remainder has expired before matching
+        let trades = book.place_order(Side::Buy, price("100.00"), quantity("0.004"), 3).unwrap();
+        assert!(trades.is_empty());
+        assert!(!book.contains_order(1));
+        assert_eq!(book.best_sell(), None);
+    }
+
+    #[test]
+    fn test_day_orders_removed_on_close() {
+        let mut book = new_book();
+        book.place_order_tif(Side::Buy, price("99.00"), quantity("0.010"), 1, TimeInForce::Day)
+            .unwrap();
+        book.place_order(Side::Buy, price("98.00"), quantity("0.010"), 2).unwrap();
+
+        book.set_phase(MarketPhase::Closed);
+        assert!(!book.contains_order(1));
+        assert!(book.contains_order(2));
+        assert_eq!(book.best_buy(), Some((price("98.00"), quantity("0.010"))));
+    }
+
+    #[test]
+    fn test_vwap_across_levels_and_thin_book() {
+        let book = book! {
+            buy 99.00 x 0.010 @id 1;
+            sell 100.00 x 0.010 @id 2;
+            sell 101.00 x 0.030 @id 3;
+        };
+        // 0.010 @ 100.00 + 0.010 @ 101.00
+        assert_eq!(
+            book.vwap(Side::Buy, quantity("0.020")),
+            Some((price("100.50"), quantity("0.020")))
+        );
+        // 0.010 @ 100.00 + 0.020 @ 101.00 = 3.02 / 0.030, floored
+        assert_eq!(
+            book.vwap(Side::Buy, quantity("0.030")),
+            Some((price("100.66"), quantity("0.030")))
+        );
+        // Thin book: only 0.010 of bids to sell into
+        assert_eq!(
+            book.vwap(Side::Sell, quantity("0.050")),
+            Some((price("99.00"), quantity("0.010")))
+        );
+        assert_eq!(new_book().vwap(Side::Buy, quantity("0.010")), None);
+    }
+
+    #[test]
+    fn test_orphaned_level_detected() {
+        let mut book = book! {
+            buy 99.00 x 0.010 @id 1;
+            sell 101.00 x 0.010 @id 2;
+        };
+        book.place_order(Side::Sell, price("99.00"), quantity("0.010"), 3).unwrap();
+        assert!(book.orphaned_levels().is_empty());
+        assert_eq!(book.validate_invariants(), Ok(()));
+
+        book.buy_side.insert(price("90.00"), PriceLevel::new(price("90.00")));
+        assert_eq!(book.orphaned_levels(), vec![(Side::Buy, price("90.00"))]);
+        assert!(matches!(
+            book.validate_invariants(),
+            Err(OrderBookError::InvariantViolated(_))
+        ));
+    }
+
+    #[test]
+    fn test_iceberg_replenishes_behind_level() {
+        let mut book = new_book();
+        book.place_iceberg(Side::Sell, price("100.00"), quantity("0.010"), quantity("0.030"), 1)
+            .unwrap();
+        book.place_order(Side::Sell, price("100.00"), quantity("0.010"), 2).unwrap();
+        assert_eq!(book.best_sell(), Some((price("100.00"), quantity("0.020"))));
+        assert_eq!(book.hidden_volume(Side::Sell), quantity("0.020"));
+
+        let trades = book.place_order(Side::Buy, price("100.00"), quantity("0.025"), 3).unwrap();
+        let fills: Vec<_> = trades.iter().map(|t| (t.maker_id, t.quantity)).collect();
+        assert_eq!(
+            fills,
+            [(1, quantity("0.010")), (2, quantity("0.010")), (1, quantity("0.005"))]
+        );
+
+        let order = book.get_order(1).unwrap();
+        assert_eq!(order.quantity, quantity("0.005"));
+        assert_eq!(order.hidden_remaining, quantity("0.010"));
+        assert_eq!(book.best_sell(), Some((price("100.00"), quantity("0.005"))));
+        assert_eq!(book.total_quantity(Side::Sell), quantity("0.015"));
+    }
+
+    #[test]
+    fn test_incoming_iceberg_trades_full_size_then_rests_slice() {
+        let mut book = book! { sell 100.00 x 0.010 @id 1 };
+        let trades = book
+            .place_iceberg(Side::Buy, price("100.00"), quantity("0.005"), quantity("0.030"), 2)
+            .unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(book.best_buy(), Some((price("100.00"), quantity("0.005"))));
+        assert_eq!(book.hidden_volume(Side::Buy), quantity("0.015"));
+        assert_eq!(
+            book.place_iceberg(Side::Buy, price("99.00"), 0, quantity("0.010"), 3),
+            Err(OrderBookError::ZeroQuantity { id: 3, quantity: 0 })
+        );
+    }
+
+    #[test]
+    fn test_fill_milestone_fires_once_when_crossed() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut book = new_book();
+        let trades = Arc::clone(&events);
+        book.on_trade(move |trade| trades.lock().unwrap().push(("trade", trade.maker_id)));
+        let milestones = Arc::clone(&events);
+        book.on_fill_milestone(move |id, _| milestones.lock().unwrap().push(("milestone", id)));
+
+        let half = Decimal::new(5, 1);
+        book.place_order_notify(Side::Sell, price("100.00"), quantity("0.010"), 1, half)
+            .unwrap();
+        for id in 2..5 {
+            book.place_order(Side::Buy, price("100.00"), quantity("0.003"), id).unwrap();
+        }
+        assert_eq!(
+            *events.lock().unwrap(),
+            [("trade", 1), ("trade", 1), ("milestone", 1), ("trade", 1)]
+        );
+        let zero = Decimal::ZERO;
+        assert_eq!(
+            book.place_order_notify(Side::Sell, price("100.00"), quantity("0.010"), 5, zero),
+            Err(OrderBookError::InvalidFillFraction { id: 5, fraction: zero })
+        );
+    }
+
+    #[test]
+    fn test_fees_charged_on_trades() {
+        let fees = FeeSchedule { maker_bps: 10, taker_bps: 25 };
+        let mut book = new_book().with_fees(fees);
+        book.place_order(Side::Sell, price("20000.00"), quantity("1.500000"), 1).unwrap();
+        let trades =
+            book.place_order(Side::Buy, price("20000.00"), quantity("1.500000"), 2).unwrap();
+        // Notional 30000.00 USDT
+        assert_eq!(trades[0].maker_fee, price("30.00") as i128);
+        assert_eq!(trades[0].taker_fee, price("75.00") as i128);
+
+        // 0.333333 @ 100.00 = 33.3333 USDT, 25 bps = 0.0833 rounds down to 0.08
+        book.place_order(Side::Sell, price("100.00"), quantity("0.333333"), 3).unwrap();
+        let trades =
+            book.place_order(Side::Buy, price("100.00"), quantity("0.333333"), 4).unwrap();
+        assert_eq!(trades[0].maker_fee, price("0.03") as i128);
+        assert_eq!(trades[0].taker_fee, price("0.08") as i128);
+    }
+
+    #[test]
+    fn test_maker_rebate_nets_below_notional() {
+        let fees = FeeSchedule { maker_bps: -5, taker_bps: 25 };
+        let mut book = new_book().with_fees(fees);
+        book.place_order(Side::Sell, price("20000.00"), quantity("1.500000"), 1).unwrap();
+        let trades =
+            book.place_order(Side::Buy, price("20000.00"), quantity("1.500000"), 2).unwrap();
+        let base = &book.instrument.base;
+        // Notional 30000.00 USDT, less a 15.00 rebate for the maker
+        assert_eq!(trades[0].maker_fee, -(price("15.00") as i128));
+        assert_eq!(trades[0].net_cost(true, base), price("29985.00") as i128);
+        assert_eq!(trades[0].net_cost(false, base), price("30075.00") as i128);
+
+        // A rebate larger than the notional takes the net cost below zero
+        let trade = Trade { maker_fee: -(price("30001.00") as i128), ..trades[0].clone() };
+        assert_eq!(trade.net_cost(true, base), -(price("1.00") as i128));
+        assert_eq!(FeeSchedule::fee(-10, u128::MAX, u128::MAX, base), i128::MIN + 1);
+    }
+
+    #[test]
+    fn test_no_fees_by_default() {
+        let mut book = book! { sell 20000.00 x 1.500000 @id 1 };
+        let trades =
+            book.place_order(Side::Buy, price("20000.00"), quantity("1.500000"), 2).unwrap();
+        assert_eq!((trades[0].maker_fee, trades[0].taker_fee), (0, 0));
+    }
+
+    fn stepped_book(policy: AlignmentPolicy) -> OrderBook {
+        let instrument = new_book().instrument.with_tick_size(5).with_lot_size(1_000);
+        OrderBook::new(instrument).with_alignment_policy(policy)
+    }
+
+    #[test]
+    fn test_reject_policy_rejects_misaligned_orders() {
+        let mut book = stepped_book(AlignmentPolicy::Reject);
+        assert_eq!(
+            book.place_order(Side::Buy, price("100.01"), quantity("0.010"), 1),
+            Err(OrderBookError::Misaligned { value: price("100.01"), step: 5 })
+        );
+        assert_eq!(
+            book.place_order(Side::Buy, price("100.05"), quantity("0.0105"), 1),
+            Err(OrderBookError::Misaligned { value: quantity("0.0105"), step: 1_000 })
+        );
+        book.place_order(Side::Buy, price("100.05"), quantity("0.010"), 1).unwrap();
+        assert_eq!(book.best_buy(), Some((price("100.05"), quantity("0.010"))));
+    }
+
+    #[test]
+    fn test_round_policy_snaps_orders() {
+        let mut book = stepped_book(AlignmentPolicy::Round);
+        book.place_order(Side::Buy, price("100.03"), quantity("0.0105"), 1).unwrap();
+        assert_eq!(book.best_buy(), Some((price("100.05"), quantity("0.010"))));
+        assert_eq!(
+            book.place_order(Side::Buy, price("100.00"), quantity("0.0005"), 2),
+            Err(OrderBookError::ZeroQuantity { id: 2, quantity: 0 })
+        );
+    }
+
+    #[test]
+    fn test_preview_batch_matches_applying_it() {
+        let mut book = book! {
+            buy 99.00 x 0.010 @id 1;
+            sell 101.00 x 0.010 @id 2;
+            sell 102.00 x 0.010 @id 3;
+        };
+        let batch = [
+            BookCommand::Place {
+                side: Side::Buy,
+                price: price("101.00"),
+                quantity: quantity("0.015"),
+                id: 4,
+            },
+            BookCommand::Cancel { id: 1 },
+            BookCommand::Amend { id: 3, quantity: quantity("0.004") },
+            BookCommand::Cancel { id: 99 },
+        ];
+        let before = book.dump();
+        let preview = book.preview_batch(&batch);
+        assert_eq!(book.dump(), before);
+
+        assert_eq!(preview.trades.len(), 1);
+        assert_eq!(preview.buy_quantity_delta, -5_000);
+        assert_eq!(preview.sell_quantity_delta, -16_000);
+        assert_eq!(preview.errors, [(3, OrderBookError::UnknownOrderId(99))]);
 
-        // Update cache when adding orders that might affect best prices
-        match order.side {
-            Side::Buy => self.set_best_buy(),
-            Side::Sell => self.update_cached_best_sell(),
+        let mut trades = Vec::new();
+        for command in &batch {
+            trades.extend(book.apply(command).unwrap_or_default());
         }
+        assert_eq!(preview.trades, trades);
+        assert_eq!(book.total_quantity(Side::Buy), quantity("0.005"));
+        assert_eq!(book.total_quantity(Side::Sell), quantity("0.004"));
     }
-}
-#[cfg(test)]
-mod order_book_tests {
-    use super::*;
-    use crate::test_support::*;
-    use crate::types::OrderBookError;
 
     #[test]
-    fn test_id_uniqueness() {
-        let mut order_book = new_book();
-        let result1 = order_book.place_order(Side::Buy, price("100.00"), quantity("0.010"), 1);
-        assert!(result1.is_ok());
-        let result2 = order_book.place_order(Side::Buy, price("100.00"), quantity("0.010"), 1);
-        assert!(matches!(result2, Err(OrderBookError::DuplicateOrderId(1))));
+    fn test_notional_bounds_inclusive() {
+        let mut book = new_book().with_notional_bounds(price("10.00"), price("1000.00"));
+        // 0.1 BTC @ 100.00 = 10.00 USDT, 10 BTC @ 100.00 = 1000.00 USDT
+        book.place_order(Side::Buy, price("100.00"), quantity("0.1"), 1).unwrap();
+        book.place_order(Side::Buy, price("100.00"), quantity("10"), 2).unwrap();
+        assert_eq!(
+            book.place_order(Side::Buy, price("100.00"), quantity("0.099999"), 3),
+            Err(OrderBookError::BelowMinNotional {
+                id: 3,
+                notional: price("9.99"),
+                min: price("10.00"),
+            })
+        );
+        assert_eq!(
+            book.place_order(Side::Sell, price("100.01"), quantity("10"), 4),
+            Err(OrderBookError::AboveMaxNotional {
+                id: 4,
+                notional: price("1000.10"),
+                max: price("1000.00"),
+            })
+        );
+        assert_eq!(book.best_sell(), None);
     }
 
     #[test]
-    fn test_zero_quantity_error() {
-        let mut order_book = new_book();
-        let result = order_book.place_order(Side::Buy, price("100.00"), 0, 1);
-        assert!(matches!(
+    fn test_cumulative_depth_running_totals() {
+        let book = book! {
+            buy 99.00 x 0.010 @id 1;
+            buy 98.00 x 0.020 @id 2;
+            buy 97.00 x 0.030 @id 3;
+            buy 96.00 x 0.040 @id 4;
+        };
+        assert_eq!(
+            book.cumulative_depth(Side::Buy, 4),
+            [
+                (price("99.00"), quantity("0.010"), quantity("0.010")),
+                (price("98.00"), quantity("0.020"), quantity("0.030")),
+                (price("97.00"), quantity("0.030"), quantity("0.060")),
+                (price("96.00"), quantity("0.040"), quantity("0.100")),
+            ]
+        );
+        assert_eq!(book.cumulative_depth(Side::Buy, 2).len(), 2);
+        assert!(book.cumulative_depth(Side::Sell, 4).is_empty());
+    }
+
+    #[test]
+    fn test_audit_record_captures_both_orders() {
+        let mut book = new_book().with_audit_trail(10);
+        book.place_tagged_order(Side::Sell, price("100.00"), quantity("0.010"), 1, 7).unwrap();
+        let trades = book.place_order(Side::Buy, price("101.00"), quantity("0.004"), 2).unwrap();
+
+        assert_eq!(book.audit_trail().len(), 1, "expected one audit record");
+        let record = &book.audit_trail()[0];
+        assert_eq!(record.sequence, 0);
+        assert_eq!(record.trade, trades[0]);
+        assert_eq!(record.price_policy, PricePolicy::MakerPrice);
+        assert_eq!(
+            (record.incoming.id, record.incoming.price, record.incoming.quantity),
+            (2, price("101.00"), quantity("0.004"))
+        );
+        assert_eq!(
+            (record.resting.id, record.resting.quantity, record.resting.tag),
+            (1, quantity("0.010"), Some(7))
+        );
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Accepted(Id, Quantity),
+        Trade(Id, Id, Quantity),
+        Cancelled(Id),
+    }
+
+    struct VecSink(Arc<Mutex<Vec<Event>>>);
+
+    impl EventSink for VecSink {
+        fn on_order_accepted(&mut self, order: &Order) {
+            self.0.lock().unwrap().push(Event::Accepted(order.id, order.quantity));
+        }
+
+        fn on_trade(&mut self, trade: &Trade) {
+            let event = Event::Trade(trade.maker_id, trade.taker_id, trade.quantity);
+            self.0.lock().unwrap().push(event);
+        }
+
+        fn on_order_cancelled(&mut self, id: Id) {
+            self.0.lock().unwrap().push(Event::Cancelled(id));
+        }
+    }
+
+    #[test]
+    fn test_event_sink_sees_ordered_stream() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut book = new_book().with_event_sink(VecSink(Arc::clone(&events)));
+        book.place_order(Side::Sell, price("100.00"), quantity("0.004"), 1).unwrap();
+        book.place_order(Side::Sell, price("101.00"), quantity("0.004"), 2).unwrap();
+        book.place_order(Side::Buy, price("101.00"), quantity("0.010"), 3).unwrap();
+        book.cancel_order(3).unwrap();
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                Event::Accepted(1, quantity("0.004")),
+                Event::Accepted(2, quantity("0.004")),
+                Event::Accepted(3, quantity("0.010")),
+                Event::Trade(1, 3, quantity("0.004")),
+                Event::Trade(2, 3, quantity("0.004")),
+                Event::Cancelled(3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cancel_order_withdraws_pending_stop() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut book = new_book().with_journal().with_event_sink(VecSink(Arc::clone(&events)));
+        book.place_stop_limit(Side::Buy, price("101.00"), price("102.00"), quantity("0.001"), 10)
+            .unwrap();
+
+        let cancelled = book.cancel_order(10).unwrap();
+        assert_eq!(
+            (cancelled.id, cancelled.side, cancelled.price, cancelled.quantity),
+            (10, Side::Buy, price("102.00"), quantity("0.001"))
+        );
+        assert!(book.pending_stops().is_empty());
+        assert_eq!(*events.lock().unwrap(), [Event::Cancelled(10)]);
+        assert_eq!(book.cancel_order(10), Err(OrderBookError::UnknownOrderId(10)));
+
+        // The ID is free again, and a replay withdraws the stop too
+        book.place_order(Side::Sell, price("101.00"), quantity("0.001"), 10).unwrap();
+        let replayed = OrderBook::replay(book.journal().unwrap(), std_instrument());
+        assert!(replayed.pending_stops().is_empty());
+        assert_eq!(replayed.dump(), book.dump());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_snapshot_json_round_trip_restores_book() {
+        let mut book = book! {
+            buy 99.00 x 0.010 @id 1;
+            buy 99.50 x 0.002 @id 2;
+            buy 99.50 x 0.003 @id 3;
+            sell 100.50 x 0.004 @id 4;
+            sell 101.00 x 0.006 @id 5;
+        };
+        let gtd = TimeInForce::Gtd(50);
+        book.place_order_tif(Side::Sell, price("102.00"), quantity("0.001"), 6, gtd)
+            .unwrap();
+
+        let json = serde_json::to_string(&book.snapshot()).unwrap();
+        let snapshot: BookSnapshot = serde_json::from_str(&json).unwrap();
+        let mut restored = OrderBook::restore(snapshot);
+
+        assert_eq!(restored.best_buy(), book.best_buy());
+        assert_eq!(restored.best_sell(), book.best_sell());
+        for side in [Side::Buy, Side::Sell] {
+            assert_eq!(restored.depth(side, 10), book.depth(side, 10));
+        }
+        assert_eq!(restored.dump(), book.dump());
+        assert!(restored.validate_invariants().is_ok());
+
+        // The restored index supports cancels, and matching keeps time priority
+        restored.cancel_order(3).unwrap();
+        let trades = restored
+            .place_order(Side::Sell, price("99.00"), quantity("0.003"), 7)
+            .unwrap();
+        assert_eq!(trades[0].maker_id, 2);
+        assert_eq!(trades[1].maker_id, 1);
+    }
+
+    #[test]
+    fn test_trades_carry_increasing_sequence_and_taker_timestamp() {
+        let mut book = book! {
+            sell 100.00 x 0.002 @id 1;
+            sell 100.50 x 0.002 @id 2;
+            sell 101.00 x 0.002 @id 3;
+        };
+        let first = book.place_order(Side::Buy, price("100.00"), quantity("0.001"), 4).unwrap();
+        let sweep = book.place_order(Side::Buy, price("101.00"), quantity("0.005"), 5).unwrap();
+
+        assert_eq!(first[0].sequence, 0);
+        assert_eq!(first[0].timestamp, 3);
+        let sequences: Vec<_> = sweep.iter().map(|trade| trade.sequence).collect();
+        assert_eq!(sequences, [1, 2, 3]);
+        assert!(sweep.iter().all(|trade| trade.timestamp == 4));
+    }
+
+    #[test]
+    fn test_expire_orders_removes_top_and_mid_book_orders() {
+        let mut book = book! {
+            buy 99.00 x 0.010 @id 1;
+            sell 101.00 x 0.010 @id 2;
+        };
+        book.place_order_gtd(Side::Sell, price("100.00"), quantity("0.003"), 3, 10).unwrap();
+        book.place_order_gtd(Side::Sell, price("101.00"), quantity("0.004"), 4, 10).unwrap();
+        book.place_order_gtd(Side::Sell, price("102.00"), quantity("0.005"), 5, 11).unwrap();
+        assert_eq!(book.get_order(3).unwrap().expires_at(), Some(10));
+
+        assert!(book.expire_orders(9).is_empty());
+        let expired: Vec<_> = book.expire_orders(10).iter().map(|o| o.id).collect();
+        assert_eq!(expired, [3, 4]);
+
+        assert!(!book.contains_order(3) && !book.contains_order(4));
+        assert_eq!(book.best_sell(), Some((price("101.00"), quantity("0.010"))));
+        assert_eq!(
+            book.depth(Side::Sell, 5),
+            [(price("101.00"), quantity("0.010")), (price("102.00"), quantity("0.005"))]
+        );
+        assert!(book.validate_invariants().is_ok());
+    }
+
+    #[test]
+    fn test_sweep_triggers_stop_that_executes_against_book() {
+        let mut book = book! {
+            buy 98.00 x 0.010 @id 1;
+            sell 100.00 x 0.002 @id 2;
+            sell 101.00 x 0.002 @id 3;
+            sell 102.00 x 0.005 @id 4;
+        };
+        book.place_stop_limit(Side::Buy, price("101.00"), price("102.00"), quantity("0.003"), 10)
+            .unwrap();
+        book.place_stop_limit(Side::Sell, price("99.00"), price("98.00"), quantity("0.001"), 11)
+            .unwrap();
+        let duplicate =
+            book.place_stop_limit(Side::Buy, price("101.00"), price("101.00"), quantity("0.001"), 10);
+        assert_eq!(duplicate, Err(OrderBookError::DuplicateOrderId(10)));
+
+        // Trading at 100.00 is below the buy stop
+        let trades = book.place_order(Side::Buy, price("100.00"), quantity("0.001"), 5).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(book.pending_stops().len(), 2);
+
+        // The sweep's last trade at 101.00 trips the buy stop, which lifts 102.00
+        let trades = book.place_order(Side::Buy, price("101.00"), quantity("0.003"), 6).unwrap();
+        let fills: Vec<_> = trades.iter().map(|t| (t.price, t.maker_id, t.taker_id)).collect();
+        assert_eq!(
+            fills,
+            [(price("100.00"), 2, 6), (price("101.00"), 3, 6), (price("102.00"), 4, 10)]
+        );
+        assert_eq!(book.best_sell(), Some((price("102.00"), quantity("0.002"))));
+        let pending: Vec<_> = book.pending_stops().iter().map(|stop| stop.id).collect();
+        assert_eq!(pending, [11]);
+    }
+
+    #[test]
+    fn test_uncross_replenishes_iceberg_reserves() {
+        let mut book = new_book();
+        book.set_phase(MarketPhase::PreOpen);
+        book.place_iceberg(Side::Sell, price("100.00"), quantity("0.001"), quantity("0.003"), 1)
+            .unwrap();
+        book.place_order(Side::Sell, price("102.00"), quantity("0.003"), 2).unwrap();
+        book.place_order(Side::Buy, price("102.00"), quantity("0.003"), 3).unwrap();
+
+        // Counting the reserve, 100.00 clears the whole bid with no surplus
+        let (clearing, trades) = book.uncross();
+        assert_eq!(clearing, Some(price("100.00")));
+        // Each replenished slice is newer than the bid, so it trades as taker
+        let fills: Vec<(Id, Id)> =
+            trades.iter().map(|trade| (trade.maker_id, trade.taker_id)).collect();
+        assert_eq!(fills, vec![(1, 3), (3, 1), (3, 1)]);
+        assert!(trades.iter().all(|trade| trade.quantity == quantity("0.001")));
+        assert_eq!(book.hidden_volume(Side::Sell), 0);
+        assert_eq!(book.best_buy(), None);
+        assert_eq!(book.best_sell(), Some((price("102.00"), quantity("0.003"))));
+        assert!(book.validate_invariants().is_ok());
+    }
+
+    #[test]
+    fn test_uncross_clears_ladder_at_single_price() {
+        let mut book = new_book();
+        book.set_phase(MarketPhase::PreOpen);
+        // Demand at or above: 103 -> 0.010, 102 -> 0.030, 101 -> 0.060, 100 -> 0.100
+        // Supply at or below: 100 -> 0.020, 101 -> 0.050, 102 -> 0.080, 103 -> 0.100
+        let ladder = [
+            (Side::Buy, "103.00", "0.010"),
+            (Side::Buy, "102.00", "0.020"),
+            (Side::Buy, "101.00", "0.030"),
+            (Side::Buy, "100.00", "0.040"),
+            (Side::Sell, "100.00", "0.020"),
+            (Side::Sell, "101.00", "0.030"),
+            (Side::Sell, "102.00", "0.030"),
+            (Side::Sell, "103.00", "0.020"),
+        ];
+        for (id, (side, p, q)) in (1..).zip(ladder) {
+            book.place_order(side, price(p), quantity(q), id).unwrap();
+        }
+
+        // Executable volume: 100 -> 0.020, 101 -> 0.050, 102 -> 0.030, 103 -> 0.010
+        let (clearing, trades) = book.uncross();
+        assert_eq!(clearing, Some(price("101.00")));
+        assert!(trades.iter().all(|trade| trade.price == price("101.00")));
+        let volume: Quantity = trades.iter().map(|trade| trade.quantity).sum();
+        assert_eq!(volume, quantity("0.050"));
+
+        // Asks up to 101.00 are used up, leaving 0.010 of the 101.00 bid
+        assert_eq!(book.best_buy(), Some((price("101.00"), quantity("0.010"))));
+        assert_eq!(book.best_sell(), Some((price("102.00"), quantity("0.030"))));
+        assert_eq!(book.uncross(), (None, Vec::new()));
+        assert!(book.set_phase(MarketPhase::Continuous).is_empty());
+    }
+
+    #[test]
+    fn test_ohlc_tracks_trade_prices() {
+        let mut book = book! {
+            sell 100.00 x 0.010 @id 1;
+            sell 102.00 x 0.010 @id 2;
+            buy 98.00 x 0.010 @id 3;
+        };
+        assert_eq!(book.last_trade_price(), None);
+        assert_eq!(book.ohlc().open(), None);
+
+        book.place_order(Side::Buy, price("100.00"), quantity("0.002"), 4).unwrap();
+        book.place_order(Side::Buy, price("102.00"), quantity("0.010"), 5).unwrap();
+        book.place_order(Side::Sell, price("98.00"), quantity("0.003"), 6).unwrap();
+
+        let ohlc = book.ohlc();
+        assert_eq!(ohlc.open(), Some(price("100.00")));
+        assert_eq!(ohlc.high(), Some(price("102.00")));
+        assert_eq!(ohlc.low(), Some(price("98.00")));
+        assert_eq!(ohlc.close(), Some(price("98.00")));
+        assert_eq!(ohlc.volume(), quantity("0.015"));
+        assert_eq!(book.last_trade_price(), Some(price("98.00")));
+
+        book.reset_ohlc();
+        assert_eq!(book.ohlc(), &OhlcAccumulator::default());
+        book.place_order(Side::Sell, price("98.00"), quantity("0.001"), 7).unwrap();
+        assert_eq!(book.ohlc().open(), Some(price("98.00")));
+        assert_eq!(book.ohlc().high(), Some(price("98.00")));
+        assert_eq!(book.ohlc().volume(), quantity("0.001"));
+    }
+
+    #[test]
+    fn test_place_orders_returns_trades_per_order() {
+        let mut book = new_book();
+        let trades = book
+            .place_orders(&[
+                (Side::Sell, price("100.00"), quantity("0.010"), 1),
+                (Side::Buy, price("99.00"), quantity("0.010"), 2),
+                (Side::Buy, price("100.00"), quantity("0.004"), 3),
+            ])
+            .unwrap();
+        assert_eq!(trades.len(), 3);
+        assert!(trades[0].is_empty() && trades[1].is_empty());
+        assert_eq!(trades[2][0].quantity, quantity("0.004"));
+        assert_eq!(book.best_sell(), Some((price("100.00"), quantity("0.006"))));
+    }
+
+    #[test]
+    fn test_place_orders_atomic_rolls_back_on_duplicate_id() {
+        let mut book = book! {
+            sell 100.00 x 0.010 @id 1;
+        };
+        let before = book.dump();
+        let batch = [
+            (Side::Buy, price("100.00"), quantity("0.004"), 2),
+            (Side::Buy, price("99.00"), quantity("0.010"), 1),
+        ];
+
+        assert_eq!(book.place_orders_atomic(&batch), Err(OrderBookError::DuplicateOrderId(1)));
+        assert_eq!(book.dump(), before);
+        assert_eq!(book.stats().trades, 0);
+
+        // Without atomicity the first order still trades
+        assert_eq!(book.place_orders(&batch), Err(OrderBookError::DuplicateOrderId(1)));
+        assert_eq!(book.best_sell(), Some((price("100.00"), quantity("0.006"))));
+    }
+
+    #[test]
+    fn test_quantity_available_counts_crossable_levels() {
+        let book = book! {
+            buy 98.00 x 0.001 @id 1;
+            buy 99.00 x 0.002 @id 2;
+            buy 100.00 x 0.004 @id 3;
+            sell 101.00 x 0.010 @id 4;
+            sell 102.00 x 0.020 @id 5;
+            sell 103.00 x 0.040 @id 6;
+        };
+        assert_eq!(book.quantity_available(Side::Buy, price("102.50")), quantity("0.030"));
+        assert_eq!(book.quantity_available(Side::Sell, price("99.00")), quantity("0.006"));
+        assert_eq!(book.quantity_available(Side::Buy, price("100.00")), 0);
+        assert_eq!(book.quantity_available(Side::Sell, price("90.00")), quantity("0.007"));
+    }
+
+    #[test]
+    fn test_level_count_and_price_levels_after_cancel() {
+        let mut book = book! {
+            buy 98.00 x 0.001 @id 1;
+            buy 99.00 x 0.002 @id 2;
+            buy 99.00 x 0.003 @id 3;
+            buy 100.00 x 0.004 @id 4;
+            sell 101.00 x 0.010 @id 5;
+        };
+        assert_eq!(book.level_count(Side::Buy), 3);
+        book.cancel_order(1).unwrap();
+
+        assert_eq!(book.level_count(Side::Buy), 2);
+        assert_eq!(book.level_count(Side::Sell), 1);
+        let bids: Vec<_> = book.price_levels(Side::Buy).collect();
+        assert_eq!(
+            bids,
+            [(price("100.00"), quantity("0.004")), (price("99.00"), quantity("0.005"))]
+        );
+        let asks: Vec<_> = book.price_levels(Side::Sell).collect();
+        assert_eq!(asks, book.depth(Side::Sell, usize::MAX));
+    }
+
+    #[test]
+    fn test_place_order_detailed_reports_resting_remainder() {
+        let mut book = book! {
+            sell 100.00 x 0.004 @id 1;
+        };
+        let result = book
+            .place_order_detailed(Side::Buy, price("100.50"), quantity("0.010"), 2)
+            .unwrap();
+        assert_eq!(result.trades.len(), 1);
+        let resting = result.resting.unwrap();
+        assert_eq!(resting.id, 2);
+        assert_eq!(resting.quantity, quantity("0.006"));
+        assert_eq!(resting.timestamp, 1);
+        assert_eq!(book.get_order(2), Some(&resting));
+
+        let result = book
+            .place_order_detailed(Side::Sell, price("100.50"), quantity("0.006"), 3)
+            .unwrap();
+        assert_eq!(result.resting, None);
+    }
+
+    #[test]
+    fn test_journal_replay_reproduces_book() {
+        let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut next = |bound: u64| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 33) % bound
+        };
+
+        let mut book = new_book().with_journal();
+        for id in 1..=500u64 {
+            let side = if next(2) == 0 { Side::Buy } else { Side::Sell };
+            let order_price = price("99.00") + next(200) as u128;
+            let order_quantity = quantity("0.001") * (1 + next(20) as u128);
+            let _ = match next(10) {
+                0 => book.place_market_order(side, order_quantity, id),
+                1 => book.place_midpoint_order(side, order_quantity, id),
+                2 => book.cancel_order(next(id)).map(|_| Vec::new()),
+                _ => book.place_order(side, order_price, order_quantity, id),
+            };
+        }
+        let journal = book.journal().unwrap();
+        assert!(journal.trades().count() > 0);
+
+        let replayed = OrderBook::replay(journal, std_instrument());
+        assert_eq!(replayed.journal(), Some(journal));
+        assert_eq!(replayed.best_buy(), book.best_buy());
+        assert_eq!(replayed.best_sell(), book.best_sell());
+        for side in [Side::Buy, Side::Sell] {
+            assert_eq!(replayed.order_count(side), book.order_count(side));
+        }
+    }
+
+    #[test]
+    fn test_journal_replay_reproduces_config_and_every_mutation() {
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = |bound: u64| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 33) % bound
+        };
+
+        let mut book = new_book()
+            .with_journal()
+            .with_fees(FeeSchedule { maker_bps: -10, taker_bps: 25 })
+            .with_matching_policy(MatchingPolicy::ProRata)
+            .with_alignment_policy(AlignmentPolicy::Round)
+            .with_max_fills_per_order(4)
+            .with_expire_after_ops(60)
+            .with_midpoint_rounding(RoundingMode::Ceil);
+        for id in 1..=800u64 {
+            let side = if next(2) == 0 { Side::Buy } else { Side::Sell };
+            let order_price = price("99.00") + next(200) as u128;
+            let order_quantity = quantity("0.100") * (1 + next(20) as u128);
+            let _ = match next(20) {
+                0 => book.place_market_order(side, order_quantity, id),
+                1 => book.place_midpoint_order(side, order_quantity, id),
+                2 | 3 => book.cancel_order(next(id)).map(|_| Vec::new()),
+                4 => book.amend_quantity(next(id), order_quantity).map(|_| Vec::new()),
+                5 => book.amend_price(next(id), order_price),
+                6 => book.place_order_gtd(side, order_price, order_quantity, id, id + next(30)),
+                7 => book.place_order_tif(side, order_price, order_quantity, id, TimeInForce::Day),
+                8 => book
+                    .place_stop_limit(side, order_price, order_price, order_quantity, id)
+                    .map(|_| Vec::new()),
+                9 => book.place_iceberg(side, order_price, quantity("0.200"), order_quantity, id),
+                10 => {
+                    book.expire_orders(id.saturating_sub(next(20)));
+                    Ok(Vec::new())
+                }
+                11 if next(8) == 0 => {
+                    book.clear();
+                    Ok(Vec::new())
+                }
+                12 if next(4) == 0 => {
+                    book.set_phase(MarketPhase::PreOpen);
+                    Ok(Vec::new())
+                }
+                13 => {
+                    book.uncross();
+                    Ok(book.set_phase(MarketPhase::Continuous))
+                }
+                14 if next(6) == 0 => {
+                    book.set_phase(MarketPhase::Closed);
+                    Ok(book.set_phase(MarketPhase::Continuous))
+                }
+                15 => book.place_order_at(side, order_price, order_quantity, id, next(id)),
+                _ => book.place_order(side, order_price, order_quantity, id),
+            };
+        }
+        let journal = book.journal().unwrap();
+        assert!(journal.trades().any(|trade| trade.maker_fee < 0 && trade.taker_fee > 0));
+        // Every kind of entry was journaled
+        let kinds: HashSet<_> = journal.entries().iter().map(std::mem::discriminant).collect();
+        assert_eq!(kinds.len(), 11);
+
+        let replayed = OrderBook::replay(journal, std_instrument());
+        assert_eq!(replayed.journal(), Some(journal));
+        assert_eq!(replayed.config(), book.config());
+        assert_eq!(replayed.dump(), book.dump());
+        assert_eq!(replayed.pending_stops(), book.pending_stops());
+    }
+
+    #[test]
+    fn test_journal_replay_honours_clear_and_amendments() {
+        let mut book = new_book().with_journal();
+        book.place_order(Side::Sell, price("100.00"), quantity("0.010"), 1).unwrap();
+        book.clear();
+        let trades = book.place_order(Side::Buy, price("100.00"), quantity("0.010"), 2).unwrap();
+        assert!(trades.is_empty());
+        book.amend_quantity(2, quantity("0.005")).unwrap();
+
+        let replayed = OrderBook::replay(book.journal().unwrap(), std_instrument());
+        assert_eq!(replayed.journal(), book.journal());
+        assert_eq!(replayed.best_buy(), Some((price("100.00"), quantity("0.005"))));
+        assert_eq!(replayed.best_sell(), None);
+    }
+
+    #[test]
+    fn test_partial_fills_keep_fifo_and_level_total() {
+        let mut book = new_book();
+        book.place_order(Side::Sell, price("100.00"), quantity("0.010"), 1).unwrap();
+        book.place_order(Side::Sell, price("100.00"), quantity("0.010"), 2).unwrap();
+        let level_orders = |book: &OrderBook| {
+            book.sell_side[&price("100.00")]
+                .orders
+                .iter()
+                .map(|order| (order.id, order.quantity))
+                .collect::<Vec<_>>()
+        };
+
+        // Partially fill the first order; the second keeps its size and place
+        let trades = book.place_order(Side::Buy, price("100.00"), quantity("0.004"), 3).unwrap();
+        assert_eq!(trades[0].maker_id, 1);
+        assert_eq!(
+            level_orders(&book),
+            vec![(1, quantity("0.006")), (2, quantity("0.010"))]
+        );
+        assert_eq!(book.best_sell(), Some((price("100.00"), quantity("0.016"))));
+
+        // Finish the first order exactly
+        let trades = book.place_order(Side::Buy, price("100.00"), quantity("0.006"), 4).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].maker_id, 1);
+        assert_eq!(level_orders(&book), vec![(2, quantity("0.010"))]);
+        assert_eq!(book.best_sell(), Some((price("100.00"), quantity("0.010"))));
+
+        // The second order is next in line, untouched until now
+        let trades = book.place_order(Side::Buy, price("100.00"), quantity("0.003"), 5).unwrap();
+        assert_eq!(trades[0].maker_id, 2);
+        assert_eq!(book.best_sell(), Some((price("100.00"), quantity("0.007"))));
+        assert!(book.validate_invariants().is_ok());
+    }
+
+    #[test]
+    fn test_top_orders_in_price_time_priority() {
+        let mut book = new_book();
+        book.place_order(Side::Buy, price("99.00"), quantity("0.010"), 1).unwrap();
+        book.place_order(Side::Buy, price("100.00"), quantity("0.010"), 2).unwrap();
+        book.place_order(Side::Buy, price("100.00"), quantity("0.005"), 3).unwrap();
+        book.place_order(Side::Sell, price("101.00"), quantity("0.010"), 4).unwrap();
+
+        let ids = |orders: Vec<&Order>| orders.iter().map(|order| order.id).collect::<Vec<_>>();
+        assert_eq!(ids(book.top_orders(Side::Buy, 10)), vec![2, 3, 1]);
+        assert_eq!(ids(book.top_orders(Side::Buy, 2)), vec![2, 3]);
+        assert_eq!(ids(book.top_orders(Side::Sell, 10)), vec![4]);
+        assert!(book.top_orders(Side::Buy, 0).is_empty());
+    }
+
+    fn three_order_level(policy: MatchingPolicy) -> OrderBook {
+        let instrument = std_instrument().with_lot_size(quantity("0.001"));
+        let mut book = OrderBook::new(instrument).with_matching_policy(policy);
+        book.place_order(Side::Sell, price("100.00"), quantity("0.010"), 1).unwrap();
+        book.place_order(Side::Sell, price("100.00"), quantity("0.020"), 2).unwrap();
+        book.place_order(Side::Sell, price("100.00"), quantity("0.030"), 3).unwrap();
+        book
+    }
+
+    fn fills(trades: &[Trade]) -> Vec<(Id, Quantity)> {
+        trades.iter().map(|trade| (trade.maker_id, trade.quantity)).collect()
+    }
+
+    #[test]
+    fn test_price_time_fills_oldest_order_first() {
+        let mut book = three_order_level(MatchingPolicy::PriceTime);
+        let trades = book.place_order(Side::Buy, price("100.00"), quantity("0.012"), 4).unwrap();
+        assert_eq!(fills(&trades), vec![(1, quantity("0.010")), (2, quantity("0.002"))]);
+        assert_eq!(book.best_sell(), Some((price("100.00"), quantity("0.048"))));
+    }
+
+    #[test]
+    fn test_designated_maker_allocation_fills_before_fifo() {
+        let instrument = std_instrument().with_lot_size(quantity("0.001"));
+        let mut book = OrderBook::new(instrument).with_designated_maker(7, 40);
+        book.place_order(Side::Sell, price("100.00"), quantity("0.010"), 1).unwrap();
+        book.place_account_order(Side::Sell, price("100.00"), quantity("0.010"), 2, 3).unwrap();
+        book.place_account_order(Side::Sell, price("100.00"), quantity("0.010"), 3, 7).unwrap();
+
+        // 40% of the 0.010 traded goes to account 7 first, the rest FIFO
+        let trades = book.place_order(Side::Buy, price("100.00"), quantity("0.010"), 4).unwrap();
+        assert_eq!(fills(&trades), vec![(3, quantity("0.004")), (1, quantity("0.006"))]);
+        let ids: Vec<Id> = book.top_orders(Side::Sell, 3).iter().map(|o| o.id).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+
+        // The allocation is capped by the level: 40% of 0.020 is 0.008, but
+        // account 7 only has 0.006 left, so FIFO takes the rest
+        let trades = book.place_order(Side::Buy, price("100.00"), quantity("0.030"), 5).unwrap();
+        assert_eq!(
+            fills(&trades),
+            vec![(3, quantity("0.006")), (1, quantity("0.004")), (2, quantity("0.010"))]
+        );
+        assert_eq!(book.best_buy(), Some((price("100.00"), quantity("0.010"))));
+        assert!(book.validate_invariants().is_ok());
+    }
+
+    #[test]
+    fn test_risk_check_limits_account_positions_from_fills() {
+        let mut book = new_book();
+        let signed = |q: &str| i128::try_from(quantity(q)).unwrap();
+        // Account 1 may hold at most 0.015 long
+        let limit = signed("0.015");
+        book.set_risk_check(move |order, position| {
+            let signed = i128::try_from(order.quantity).unwrap();
+            let projected = match order.side {
+                Side::Buy => position + signed,
+                Side::Sell => position - signed,
+            };
+            if order.account == Some(1) && projected > limit {
+                return Err(OrderBookError::RiskLimitExceeded { id: order.id, position });
+            }
+            Ok(())
+        });
+        book.place_account_order(Side::Sell, price("100.00"), quantity("0.020"), 1, 2).unwrap();
+
+        let trades = book.place_account_order(Side::Buy, price("100.00"), quantity("0.010"), 2, 1);
+        let trade = &trades.unwrap()[0];
+        assert_eq!((trade.buyer_account, trade.seller_account), (Some(1), Some(2)));
+        assert_eq!(book.position(1), signed("0.010"));
+        assert_eq!(book.position(2), -signed("0.010"));
+        assert_eq!(book.position(3), 0);
+
+        // A further 0.010 would take account 1 to 0.020 long
+        let result = book.place_account_order(Side::Buy, price("100.00"), quantity("0.010"), 3, 1);
+        let position = signed("0.010");
+        assert_eq!(result, Err(OrderBookError::RiskLimitExceeded { id: 3, position }));
+        // Selling reduces the position, and orders without an account pass
+        book.place_account_order(Side::Sell, price("101.00"), quantity("0.010"), 4, 1).unwrap();
+        book.place_order(Side::Buy, price("101.00"), quantity("0.020"), 5).unwrap();
+        assert_eq!(book.position(1), 0);
+        assert_eq!(book.position(2), -signed("0.020"));
+        assert!(book.validate_invariants().is_ok());
+    }
+
+    #[test]
+    fn test_pro_rata_allocates_by_resting_quantity() {
+        let mut book = three_order_level(MatchingPolicy::ProRata);
+        let trades = book.place_order(Side::Buy, price("100.00"), quantity("0.012"), 4).unwrap();
+        assert_eq!(
+            fills(&trades),
+            vec![(1, quantity("0.002")), (2, quantity("0.004")), (3, quantity("0.006"))]
+        );
+        // Every order keeps its place in the queue
+        let ids: Vec<Id> = book.top_orders(Side::Sell, 3).iter().map(|o| o.id).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+        assert_eq!(book.best_sell(), Some((price("100.00"), quantity("0.048"))));
+        assert!(book.validate_invariants().is_ok());
+    }
+
+    #[test]
+    fn test_pro_rata_rounds_down_and_gives_leftover_lots_oldest_first() {
+        let mut book = three_order_level(MatchingPolicy::ProRata);
+        // 0.008 split 1:2:3 is 0.00133, 0.00267 and 0.004, floored to whole lots
+        // as 0.001, 0.002 and 0.004; the leftover lot goes to the oldest order
+        let trades = book.place_order(Side::Buy, price("100.00"), quantity("0.008"), 4).unwrap();
+        assert_eq!(
+            fills(&trades),
+            vec![(1, quantity("0.002")), (2, quantity("0.002")), (3, quantity("0.004"))]
+        );
+
+        // A fill larger than the level takes it all and moves on
+        let trades = book.place_order(Side::Buy, price("100.00"), quantity("0.060"), 5).unwrap();
+        assert_eq!(trades.iter().map(|t| t.quantity).sum::<Quantity>(), quantity("0.052"));
+        assert_eq!(book.best_sell(), None);
+        assert_eq!(book.best_buy(), Some((price("100.00"), quantity("0.008"))));
+    }
+
+    #[test]
+    fn test_pro_rata_splits_quantities_whose_product_overflows() {
+        let mut book = new_book().with_matching_policy(MatchingPolicy::ProRata);
+        let (small, large) = (1u128 << 100, 1u128 << 101);
+        book.place_order(Side::Sell, 1, small, 1).unwrap();
+        book.place_order(Side::Sell, 1, small, 2).unwrap();
+        book.place_order(Side::Sell, 1, large, 3).unwrap();
+
+        // fill * quantity is around 2^201, far beyond u128
+        let trades = book.place_order(Side::Buy, 1, large, 4).unwrap();
+        assert_eq!(fills(&trades), vec![(1, small / 2), (2, small / 2), (3, large / 2)]);
+        assert!(book.validate_invariants().is_ok());
+    }
+
+    #[test]
+    fn test_notional_overflow_rejected_before_mutation() {
+        let mut book = new_book();
+        book.place_order(Side::Buy, price("99.00"), quantity("0.010"), 1).unwrap();
+        let stats = book.stats().clone();
+
+        let result = book.place_order(Side::Sell, u128::MAX, 2, 2);
+        assert_eq!(
             result,
-            Err(OrderBookError::ZeroQuantity { id: 1, quantity: 0 })
+            Err(OrderBookError::NotionalOverflow { price: u128::MAX, quantity: 2 })
+        );
+        assert_eq!(book.best_buy(), Some((price("99.00"), quantity("0.010"))));
+        assert_eq!(book.best_sell(), None);
+        assert_eq!(book.get_order(2), None);
+        assert_eq!(book.stats(), &stats);
+        // The largest product that still fits is accepted
+        book.place_order(Side::Sell, u128::MAX, 1, 3).unwrap();
+    }
+
+    #[test]
+    fn test_resting_quantities_stay_lot_aligned() {
+        let mut book = three_order_level(MatchingPolicy::ProRata);
+        // 0.007 split 1:2:3 is fractional in lots; every share is floored to a lot
+        book.place_order(Side::Buy, price("100.00"), quantity("0.007"), 4).unwrap();
+        assert!(book.validate_invariants().is_ok());
+
+        // A display size off the lot grid is rejected rather than resting 1.5 lots
+        assert_eq!(
+            book.place_iceberg(Side::Buy, price("99.00"), quantity("0.0015"), quantity("0.010"), 5),
+            Err(OrderBookError::Misaligned { value: quantity("0.0015"), step: quantity("0.001") })
+        );
+        book.place_iceberg(Side::Buy, price("99.00"), quantity("0.002"), quantity("0.010"), 5)
+            .unwrap();
+        assert!(book.validate_invariants().is_ok());
+
+        // Bypassing validation leaves a misaligned order the check reports
+        book.buy_side.get_mut(&price("99.00")).unwrap().update_front_order_quantity(5, 1_500);
+        book.recompute_caches();
+        assert!(matches!(
+            book.validate_invariants(),
+            Err(OrderBookError::InvariantViolated(reason)) if reason.contains("lots")
         ));
     }
-    // --- core matching tests ---
+
+    #[test]
+    fn test_partial_fills_leave_whole_lots_resting() {
+        let instrument = new_book().instrument.with_lot_size(quantity("0.001"));
+        let mut book =
+            OrderBook::new(instrument.clone()).with_alignment_policy(AlignmentPolicy::Round);
+        book.place_order(Side::Sell, price("100.00"), quantity("0.003"), 1).unwrap();
+        // A taker for 1.5 lots is rounded down before it can split a lot
+        let trades =
+            book.place_order(Side::Buy, price("100.00"), quantity("0.0015"), 2).unwrap();
+        assert_eq!(trades[0].quantity, quantity("0.001"));
+        assert_eq!(book.best_sell(), Some((price("100.00"), quantity("0.002"))));
+
+        // A resting order restored off the lot grid is trimmed back onto it
+        let mut loose = new_book();
+        loose.place_order(Side::Sell, price("100.00"), quantity("0.0015"), 1).unwrap();
+        let mut snapshot = loose.snapshot();
+        snapshot.instrument = instrument;
+        let mut book = OrderBook::restore(snapshot);
+        let trades = book.place_order(Side::Buy, price("100.00"), quantity("0.001"), 2).unwrap();
+        assert_eq!(trades.iter().map(|trade| trade.quantity).sum::<Quantity>(), quantity("0.0005"));
+        // The taker's sub-lot remainder is dropped instead of crossing the book
+        assert_eq!(book.best_buy(), None);
+        assert_eq!(book.best_sell(), Some((price("100.00"), quantity("0.001"))));
+        assert!(book.validate_invariants().is_ok());
+    }
+
+    #[test]
+    fn test_replenished_slices_keep_queues_in_timestamp_order() {
+        let mut book = new_book();
+        book.place_iceberg(Side::Sell, price("100.00"), quantity("0.001"), quantity("0.003"), 0)
+            .unwrap();
+        book.place_order(Side::Sell, price("100.00"), quantity("0.001"), 1).unwrap();
+        // Consuming the first slice requeues the iceberg behind order 1
+        book.place_order(Side::Buy, price("100.00"), quantity("0.001"), 9).unwrap();
+        let queue = |book: &OrderBook| -> Vec<Id> {
+            book.sell_side[&price("100.00")].orders.iter().map(|order| order.id).collect()
+        };
+        assert_eq!(queue(&book), vec![1, 0]);
+        assert!(book.validate_invariants().is_ok());
+
+        // A snapshot round trip keeps the queue as it was
+        let restored = OrderBook::restore(book.snapshot());
+        assert_eq!(queue(&restored), vec![1, 0]);
+
+        // An import stamped before the new slice goes ahead of it
+        book.place_order_at(Side::Sell, price("100.00"), quantity("0.001"), 2, 1).unwrap();
+        assert_eq!(queue(&book), vec![1, 2, 0]);
+        assert!(book.validate_invariants().is_ok());
+
+        let level = book.sell_side.get_mut(&price("100.00")).unwrap();
+        level.orders.swap(0, 2);
+        assert!(matches!(
+            book.validate_invariants(),
+            Err(OrderBookError::InvariantViolated(reason)) if reason.contains("timestamp order")
+        ));
+    }
+
+    #[test]
+    fn test_randomized_iceberg_peaks_are_seeded_and_in_range() {
+        let peaks = |seed: u64| {
+            let mut book = new_book();
+            let peak = RandomPeak::new(quantity("0.002"), quantity("0.006"), seed);
+            book.place_iceberg_randomized(Side::Sell, price("100.00"), peak, quantity("0.050"), 1)
+                .unwrap();
+            let (mut shown, mut filled) = (Vec::new(), 0);
+            for id in 2.. {
+                let Some(slice) = book.get_order(1).map(|order| order.quantity) else {
+                    break;
+                };
+                shown.push(slice);
+                let trades = book.place_order(Side::Buy, price("100.00"), slice, id).unwrap();
+                filled += trades.iter().map(|trade| trade.quantity).sum::<Quantity>();
+            }
+            assert_eq!(filled, quantity("0.050"));
+            shown
+        };
+
+        let shown = peaks(7);
+        assert_eq!(shown, peaks(7));
+        assert_ne!(shown, peaks(8));
+        // Every slice but the last, which only shows what is left, is a full peak
+        let (last, full) = shown.split_last().unwrap();
+        assert!(full.iter().all(|peak| (quantity("0.002")..=quantity("0.006")).contains(peak)));
+        assert!(*last <= quantity("0.006"));
+        assert!(full.windows(2).any(|pair| pair[0] != pair[1]));
+
+        let mut book = new_book();
+        let inverted = RandomPeak::new(quantity("0.006"), quantity("0.002"), 7);
+        let total = quantity("0.050");
+        assert_eq!(
+            book.place_iceberg_randomized(Side::Sell, price("100.00"), inverted, total, 1),
+            Err(OrderBookError::InvalidPeakRange {
+                min: quantity("0.006"),
+                max: quantity("0.002")
+            })
+        );
+    }
+
+    #[test]
+    fn test_max_price_gap_rejects_orders_far_from_own_best() {
+        let mut book = new_book().with_max_price_gap(100);
+        // Empty own side: nothing to measure against
+        book.place_order(Side::Buy, price("90.00"), quantity("0.010"), 1).unwrap();
+        book.place_order(Side::Sell, price("110.00"), quantity("0.010"), 2).unwrap();
+
+        // Within 100 ticks (1.00) of the own-side best
+        book.place_order(Side::Buy, price("89.00"), quantity("0.010"), 3).unwrap();
+        book.place_order(Side::Sell, price("111.00"), quantity("0.010"), 4).unwrap();
+        assert_eq!(
+            book.place_order(Side::Buy, price("88.99"), quantity("0.010"), 5),
+            Err(OrderBookError::PriceGapTooLarge {
+                id: 5,
+                price: price("88.99"),
+                best: price("90.00"),
+                max_ticks: 100,
+            })
+        );
+        assert_eq!(
+            book.place_order(Side::Sell, price("111.01"), quantity("0.010"), 6),
+            Err(OrderBookError::PriceGapTooLarge {
+                id: 6,
+                price: price("111.01"),
+                best: price("110.00"),
+                max_ticks: 100,
+            })
+        );
+
+        // Marketable orders are not measured against their own side
+        let trades = book.place_order(Side::Buy, price("120.00"), quantity("0.010"), 7).unwrap();
+        assert_eq!(trades.len(), 1);
+        book.validate_invariants().unwrap();
+    }
+
+    #[test]
+    fn test_max_price_gap_applies_to_price_amendments() {
+        let mut book = new_book().with_max_price_gap(100);
+        book.place_order(Side::Buy, price("90.00"), quantity("0.010"), 1).unwrap();
+        book.place_order(Side::Buy, price("89.00"), quantity("0.010"), 2).unwrap();
+        book.place_order(Side::Sell, price("110.00"), quantity("0.010"), 3).unwrap();
+        assert_eq!(
+            book.amend_price(2, price("88.99")),
+            Err(OrderBookError::PriceGapTooLarge {
+                id: 2,
+                price: price("88.99"),
+                best: price("90.00"),
+                max_ticks: 100,
+            })
+        );
+        assert_eq!(book.get_order(2).map(|o| o.price), Some(price("89.00")));
+
+        // The moved order's own level does not count: order 2 is the best left
+        assert!(book.amend_price(1, price("87.99")).is_err());
+        book.amend_price(1, price("88.00")).unwrap();
+        assert_eq!(book.best_buy(), Some((price("89.00"), quantity("0.010"))));
+
+        // A marketable amendment is not measured against its own side
+        let trades = book.amend_price(1, price("110.00")).unwrap();
+        assert_eq!(trades.len(), 1);
+        book.validate_invariants().unwrap();
+    }
+
+    #[test]
+    fn test_audit_trail_evicts_oldest_records_at_capacity() {
+        let mut book = new_book().with_audit_trail(2);
+        for id in 1..=3 {
+            book.place_order(Side::Sell, price("100.00"), quantity("0.001"), id).unwrap();
+            book.place_order(Side::Buy, price("100.00"), quantity("0.001"), id + 10).unwrap();
+        }
+
+        let sequences: Vec<_> = book.audit_trail().iter().map(|r| r.sequence).collect();
+        assert_eq!(sequences, vec![1, 2]);
+        let resting: Vec<_> = book.audit_trail().iter().map(|r| r.resting.id).collect();
+        assert_eq!(resting, vec![2, 3]);
+    }
 
     #[test]
     fn basic_full_fill_resting_ask_hit_by_buy() {
@@ -566,7 +6954,7 @@ mod order_book_tests {
         assert_eq!(lvl.total_quantity, quantity("0.005"));
 
         // Partial consume front
-        lvl.update_front_order_quantity(quantity("0.001"));
+        lvl.update_front_order_quantity(1, quantity("0.001"));
         assert_eq!(lvl.orders.front().unwrap().quantity, quantity("0.001"));
         assert_eq!(lvl.total_quantity, quantity("0.003")); // 0.001 + 0.002
 
@@ -0,0 +1,120 @@
+use crate::order_book::OrderBook;
+use crate::types::{BookCommand, Instrument, PriceAndQuantity, Side, Timestamp};
+
+/// Number of levels per side captured in each [`DepthSnapshot`].
+pub const STEP_DEPTH_LEVELS: usize = 10;
+
+/// Top-of-book depth captured after one replayed command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DepthSnapshot {
+    /// Book sequence number after the command, see `OrderBook::depth_with_sequence`
+    pub sequence: Timestamp,
+    /// Best buy levels, best first
+    pub bids: Vec<PriceAndQuantity>,
+    /// Best sell levels, best first
+    pub asks: Vec<PriceAndQuantity>,
+}
+
+impl DepthSnapshot {
+    fn capture(book: &OrderBook) -> Self {
+        let (sequence, bids) = book.depth_with_sequence(Side::Buy, STEP_DEPTH_LEVELS);
+        DepthSnapshot {
+            sequence,
+            bids,
+            asks: book.depth(Side::Sell, STEP_DEPTH_LEVELS),
+        }
+    }
+}
+
+/// Replays a command log into a fresh book and returns the final book.
+///
+/// Rejected commands are skipped, just as they left the original book
+/// unchanged.
+pub fn replay(instrument: Instrument, log: &[BookCommand]) -> OrderBook {
+    let mut book = OrderBook::new(instrument);
+    for command in log {
+        let _ = book.apply(command);
+    }
+    book
+}
+
+/// Replays a command log step by step, yielding the depth after each command.
+///
+/// Exactly one snapshot is produced per command, including rejected ones, so
+/// a UI can animate the book alongside the log. Snapshots hold only the top
+/// [`STEP_DEPTH_LEVELS`] levels per side to stay cheap.
+pub fn replay_steps(
+    instrument: Instrument,
+    log: &[BookCommand],
+) -> impl Iterator<Item = DepthSnapshot> + '_ {
+    let mut book = OrderBook::new(instrument);
+    log.iter().map(move |command| {
+        let _ = book.apply(command);
+        DepthSnapshot::capture(&book)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{new_book, price, quantity};
+
+    fn place(side: Side, price_str: &str, quantity_str: &str, id: u64) -> BookCommand {
+        BookCommand::Place {
+            side,
+            price: price(price_str),
+            quantity: quantity(quantity_str),
+            id,
+        }
+    }
+
+    #[test]
+    fn replay_steps_yields_one_snapshot_per_command() {
+        let log = vec![
+            place(Side::Buy, "99.00", "0.010", 1),
+            place(Side::Sell, "101.00", "0.010", 2),
+            place(Side::Buy, "99.00", "0.010", 1), // duplicate id, rejected
+            place(Side::Sell, "99.00", "0.004", 3),
+        ];
+        let instrument = new_book().instrument;
+        let steps: Vec<DepthSnapshot> = replay_steps(instrument.clone(), &log).collect();
+        assert_eq!(steps.len(), log.len());
+
+        assert_eq!(steps[0].bids, vec![(price("99.00"), quantity("0.010"))]);
+        assert!(steps[0].asks.is_empty());
+        assert_eq!(steps[2], steps[1]);
+        assert_eq!(steps[3].bids, vec![(price("99.00"), quantity("0.006"))]);
+
+        let book = replay(instrument, &log);
+        let last = steps.last().unwrap();
+        assert_eq!(last.bids, book.depth(Side::Buy, STEP_DEPTH_LEVELS));
+        assert_eq!(last.asks, book.depth(Side::Sell, STEP_DEPTH_LEVELS));
+        assert_eq!(last.sequence, book.depth_with_sequence(Side::Buy, 0).0);
+    }
+
+    #[test]
+    fn replay_reproduces_trade_ids() {
+        let log = [
+            place(Side::Sell, "100.00", "0.010", 1),
+            place(Side::Sell, "100.00", "0.010", 2),
+            place(Side::Buy, "100.00", "0.005", 3),
+            place(Side::Buy, "100.00", "0.005", 3), // duplicate id, rejected
+            place(Side::Buy, "100.00", "0.015", 4),
+        ];
+        let run = || {
+            let mut book = new_book();
+            log.iter()
+                .filter_map(|command| book.apply(command).ok())
+                .flatten()
+                .map(|trade| trade.trade_id)
+                .collect::<Vec<_>>()
+        };
+
+        let ids = run();
+        assert_eq!(ids.len(), 3);
+        assert_eq!(ids, run());
+        assert_ne!(ids[0], ids[1]);
+        assert_ne!(ids[1], ids[2]);
+        assert_ne!(ids[0], ids[2]);
+    }
+}
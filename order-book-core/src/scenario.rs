@@ -0,0 +1,225 @@
+//! Human-readable TOML scenarios for building books by hand.
+//!
+//! A scenario describes an instrument and a list of orders with decimal
+//! prices and quantities:
+//!
+//! ```toml
+//! [instrument.base]
+//! symbol = "BTC"
+//! decimals = 6
+//!
+//! [instrument.quote]
+//! symbol = "USDT"
+//! decimals = 2
+//!
+//! [[orders]]
+//! side = "buy"
+//! price = "100.50"
+//! quantity = "0.001"
+//! id = 1
+//! ```
+//!
+//! Orders are placed in file order, so crossing orders trade as they would
+//! through `OrderBook::place_order`.
+
+use crate::types::{Id, Instrument, OrderBookError, Side};
+use crate::units::{display_minor_units, price_to_minor_units, quantity_to_minor_units};
+use crate::OrderBook;
+use derive_more::Display;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::str::FromStr;
+
+/// An instrument plus the orders to place on it, in placement order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Scenario {
+    /// Instrument the book trades
+    pub instrument: Instrument,
+    /// Orders to place, in order
+    #[serde(default)]
+    pub orders: Vec<ScenarioOrder>,
+}
+
+/// A single order in a scenario, with decimal price and quantity strings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScenarioOrder {
+    /// Order side
+    pub side: Side,
+    /// Price in decimal format (e.g. "100.50")
+    pub price: String,
+    /// Quantity in decimal format (e.g. "0.001")
+    pub quantity: String,
+    /// Unique order ID
+    pub id: Id,
+}
+
+/// Error type for loading and saving scenarios
+#[derive(Display, Debug)]
+pub enum ScenarioError {
+    /// Reading or writing the scenario file failed
+    #[display("Scenario I/O error: {}", _0)]
+    Io(std::io::Error),
+    /// The file is not a valid scenario
+    #[display("Invalid scenario: {}", _0)]
+    Parse(toml::de::Error),
+    /// The scenario could not be serialized
+    #[display("Could not serialize scenario: {}", _0)]
+    Serialize(toml::ser::Error),
+    /// A price or quantity is not a valid decimal for the instrument
+    #[display("Order {} has invalid {} '{}'", id, field, value)]
+    InvalidValue {
+        id: Id,
+        field: &'static str,
+        value: String,
+    },
+    /// The book rejected an order
+    #[display("{}", _0)]
+    Order(OrderBookError),
+}
+
+impl std::error::Error for ScenarioError {}
+
+impl Scenario {
+    /// Captures the resting orders of `book` as a scenario.
+    ///
+    /// Orders are listed in arrival order so loading the scenario rebuilds
+    /// the same levels with the same time priority.
+    pub fn from_book(book: &OrderBook) -> Self {
+        let instrument = book.instrument.clone();
+        let orders = book
+            .resting_orders()
+            .into_iter()
+            .map(|order| ScenarioOrder {
+                side: order.side,
+                price: display_minor_units(order.price, instrument.quote.decimals),
+                quantity: display_minor_units(order.quantity, instrument.base.decimals),
+                id: order.id,
+            })
+            .collect();
+        Scenario { instrument, orders }
+    }
+
+    /// Builds a fresh book by placing every order in the scenario.
+    pub fn build(&self) -> Result<OrderBook, ScenarioError> {
+        let mut book = OrderBook::new(self.instrument.clone());
+        for order in &self.orders {
+            let invalid = |field: &'static str, value: &str| ScenarioError::InvalidValue {
+                id: order.id,
+                field,
+                value: value.to_string(),
+            };
+            let price = Decimal::from_str(&order.price)
+                .ok()
+                .and_then(|price| price_to_minor_units(price, &self.instrument.quote))
+                .ok_or_else(|| invalid("price", &order.price))?;
+            let quantity = Decimal::from_str(&order.quantity)
+                .ok()
+                .and_then(|quantity| quantity_to_minor_units(quantity, &self.instrument.base))
+                .ok_or_else(|| invalid("quantity", &order.quantity))?;
+            book.place_order(order.side, price, quantity, order.id)
+                .map_err(ScenarioError::Order)?;
+        }
+        Ok(book)
+    }
+}
+
+/// Loads a TOML scenario from `path` and builds the book it describes.
+pub fn load_scenario(path: impl AsRef<Path>) -> Result<OrderBook, ScenarioError> {
+    let text = std::fs::read_to_string(path).map_err(ScenarioError::Io)?;
+    let scenario: Scenario = toml::from_str(&text).map_err(ScenarioError::Parse)?;
+    scenario.build()
+}
+
+/// Writes the resting orders of `book` to `path` as a TOML scenario.
+pub fn save_scenario(book: &OrderBook, path: impl AsRef<Path>) -> Result<(), ScenarioError> {
+    let text = toml::to_string(&Scenario::from_book(book)).map_err(ScenarioError::Serialize)?;
+    std::fs::write(path, text).map_err(ScenarioError::Io)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{new_book, price, quantity};
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("order-book-{}-{}.toml", name, std::process::id()))
+    }
+
+    #[test]
+    fn scenario_round_trip_reproduces_book() {
+        let mut book = new_book();
+        book.place_order(Side::Buy, price("99.50"), quantity("0.010"), 1)
+            .unwrap();
+        book.place_order(Side::Buy, price("99.50"), quantity("0.002"), 2)
+            .unwrap();
+        book.place_order(Side::Buy, price("99.00"), quantity("0.005"), 3)
+            .unwrap();
+        book.place_order(Side::Sell, price("100.25"), quantity("0.004"), 4)
+            .unwrap();
+        // Partially fills order 1 so the saved quantity is the remainder
+        book.place_order(Side::Sell, price("99.50"), quantity("0.003"), 5)
+            .unwrap();
+
+        let path = temp_path("round-trip");
+        save_scenario(&book, &path).unwrap();
+        let restored = load_scenario(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(restored.instrument, book.instrument);
+        for side in [Side::Buy, Side::Sell] {
+            assert_eq!(restored.depth(side, 10), book.depth(side, 10));
+        }
+        let ids = |b: &OrderBook| b.resting_orders().iter().map(|o| o.id).collect::<Vec<_>>();
+        assert_eq!(ids(&restored), ids(&book));
+    }
+
+    #[test]
+    fn scenario_parses_hand_written_file() {
+        let text = r#"
+            [instrument.base]
+            symbol = "ETH"
+            decimals = 4
+
+            [instrument.quote]
+            symbol = "USD"
+            decimals = 2
+
+            [[orders]]
+            side = "sell"
+            price = "1500.00"
+            quantity = "0.5"
+            id = 1
+
+            [[orders]]
+            side = "buy"
+            price = "1500.00"
+            quantity = "0.2"
+            id = 2
+        "#;
+        let scenario: Scenario = toml::from_str(text).unwrap();
+        let book = scenario.build().unwrap();
+        assert_eq!(book.instrument.to_string(), "ETH/USD");
+        assert_eq!(book.best_sell(), Some((150_000, 3_000)));
+        assert_eq!(book.best_buy(), None);
+    }
+
+    #[test]
+    fn scenario_reports_invalid_values() {
+        let mut scenario = Scenario::from_book(&new_book());
+        scenario.orders.push(ScenarioOrder {
+            side: Side::Buy,
+            price: "abc".to_string(),
+            quantity: "0.001".to_string(),
+            id: 7,
+        });
+        assert!(matches!(
+            scenario.build(),
+            Err(ScenarioError::InvalidValue {
+                id: 7,
+                field: "price",
+                ..
+            })
+        ));
+    }
+}
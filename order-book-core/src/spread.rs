@@ -0,0 +1,65 @@
+//! Quotes for synthetic instruments combining the books of two legs.
+
+use crate::order_book::OrderBook;
+
+/// Returns the best bid and ask of the synthetic spread `leg_a - leg_b`.
+///
+/// Selling the spread sells A at its bid and buys B at its ask, so the
+/// synthetic bid is `bid(A) - ask(B)`; buying it gives the ask
+/// `ask(A) - bid(B)`. Both are in quote minor units and signed, as a calendar
+/// spread can trade below zero. Returns `None` if either leg has an empty
+/// side, if the legs are quoted in different assets, or if a price does not
+/// fit in an `i128`.
+pub fn synthetic_bbo(leg_a: &OrderBook, leg_b: &OrderBook) -> Option<(i128, i128)> {
+    if leg_a.instrument.quote != leg_b.instrument.quote {
+        return None;
+    }
+    let difference =
+        |a: u128, b: u128| i128::try_from(a).ok()?.checked_sub(i128::try_from(b).ok()?);
+    let bid = difference(leg_a.best_buy()?.0, leg_b.best_sell()?.0)?;
+    let ask = difference(leg_a.best_sell()?.0, leg_b.best_buy()?.0)?;
+    Some((bid, ask))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{new_book, price, quantity};
+    use crate::types::{Asset, Instrument};
+    use crate::Side;
+
+    #[test]
+    fn test_synthetic_bbo_of_calendar_spread() {
+        let back_instrument = Instrument::new(Asset::new("BTC-DEC", 6), Asset::new("USDT", 2));
+        let mut front = new_book();
+        let mut back = OrderBook::new(back_instrument);
+        let size = quantity("0.010");
+        front
+            .place_order(Side::Buy, price("101.00"), size, 1)
+            .unwrap();
+        front
+            .place_order(Side::Sell, price("102.00"), size, 2)
+            .unwrap();
+        back.place_order(Side::Buy, price("98.00"), size, 1)
+            .unwrap();
+        // One-sided back leg: no synthetic quote yet
+        assert_eq!(synthetic_bbo(&front, &back), None);
+
+        back.place_order(Side::Sell, price("99.00"), size, 2)
+            .unwrap();
+        let (bid, ask) = (price("2.00") as i128, price("4.00") as i128);
+        assert_eq!(synthetic_bbo(&front, &back), Some((bid, ask)));
+        // The reverse spread is quoted below zero
+        assert_eq!(synthetic_bbo(&back, &front), Some((-ask, -bid)));
+
+        let other_quote = Instrument::new(Asset::new("BTC", 6), Asset::new("USDC", 2));
+        let mut other = OrderBook::new(other_quote);
+        other
+            .place_order(Side::Buy, price("98.00"), size, 1)
+            .unwrap();
+        other
+            .place_order(Side::Sell, price("99.00"), size, 2)
+            .unwrap();
+        assert_eq!(synthetic_bbo(&front, &other), None);
+    }
+}
@@ -28,3 +28,34 @@ pub(crate) fn quantity(q: &str) -> Quantity {
     let b_decimals = std_instrument().base.decimals;
     crate::units::to_minor_units(d, b_decimals).unwrap()
 }
+
+/// Builds a `new_book()` from a list of placements, in order.
+///
+/// ```ignore
+/// let book = book! {
+///     buy 100.00 x 0.01 @id 1;
+///     sell 101.00 x 0.02 @id 2;
+/// };
+/// ```
+///
+/// Prices and quantities are decimal literals parsed with `price`/`quantity`.
+/// Every placement must be accepted.
+macro_rules! book {
+    ($($side:ident $price:literal x $quantity:literal @id $id:literal);* $(;)?) => {{
+        #[allow(unused_mut)]
+        let mut book = $crate::test_support::new_book();
+        $(
+            book.place_order(
+                $crate::test_support::book!(@side $side),
+                $crate::test_support::price(stringify!($price)),
+                $crate::test_support::quantity(stringify!($quantity)),
+                $id,
+            )
+            .unwrap();
+        )*
+        book
+    }};
+    (@side buy) => { $crate::types::Side::Buy };
+    (@side sell) => { $crate::types::Side::Sell };
+}
+pub(crate) use book;
@@ -1,20 +1,23 @@
+use crate::units::{notional, scale_notional, RoundingMode};
 use derive_more::Display;
+use rust_decimal::Decimal;
 use std::borrow::Cow;
-use std::collections::VecDeque;
-use validator::Validate;
+use std::collections::{HashMap, VecDeque};
+use validator::{Validate, ValidationError, ValidationErrors};
 
 pub type Price = u128;
 pub type Quantity = u128;
 
 pub type PriceAndQuantity = (Price, Quantity);
 pub type Id = u64;
+pub type AccountId = u32;
 pub type Timestamp = u64;
 
 /// Represents a price level in the order book.
 ///
 /// A price level contains all orders at the same price, maintaining
 /// first-in-first-out (FIFO) ordering for time priority.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct PriceLevel {
     /// The price for this level
     pub(crate) price: Price,
@@ -34,8 +37,31 @@ impl PriceLevel {
         }
     }
 
-    /// Adds an order to the back of the queue at this price level.
+    /// Adds an order to the queue at this price level in timestamp order.
+    ///
+    /// Orders normally arrive with increasing timestamps and are pushed to
+    /// the back. An older (imported) order is inserted ahead of every order
+    /// with a later timestamp; ties keep arrival order.
     pub(crate) fn add_order(&mut self, order: Order) {
+        self.total_quantity += order.quantity;
+        match self.orders.back() {
+            Some(last) if last.timestamp > order.timestamp => {
+                let index = self
+                    .orders
+                    .partition_point(|o| o.timestamp <= order.timestamp);
+                self.orders.insert(index, order);
+            }
+            _ => self.orders.push_back(order),
+        }
+    }
+
+    /// Appends an order to the back of the queue, regardless of its timestamp.
+    ///
+    /// Used to requeue an iceberg order behind the rest of the level once a
+    /// fresh slice has been replenished from its reserve, or to put back
+    /// orders taken off the front in their original order. The caller keeps
+    /// the queue in timestamp order.
+    pub(crate) fn requeue(&mut self, order: Order) {
         self.total_quantity += order.quantity;
         self.orders.push_back(order);
     }
@@ -51,16 +77,46 @@ impl PriceLevel {
         }
     }
 
+    /// Removes and returns the order at `index` in the queue.
+    /// Returns None if the index is out of bounds.
+    pub(crate) fn remove_order_at(&mut self, index: usize) -> Option<Order> {
+        let order = self.orders.remove(index)?;
+        self.total_quantity -= order.quantity;
+        Some(order)
+    }
+
     /// Updates the quantity of the order at the front of the queue.
     /// Used when an order is partially filled.
-    pub(crate) fn update_front_order_quantity(&mut self, new_quantity: Quantity) {
+    ///
+    /// `id` names the order the caller matched; it must still be at the
+    /// front, or the fill would be booked against the wrong order.
+    pub(crate) fn update_front_order_quantity(&mut self, id: Id, new_quantity: Quantity) {
         if let Some(order) = self.orders.front_mut() {
+            debug_assert_eq!(
+                order.id, id,
+                "partial fill applied to a different front order"
+            );
             let old_quantity = order.quantity;
             order.quantity = new_quantity;
             self.total_quantity = self.total_quantity - old_quantity + new_quantity;
         }
     }
 
+    /// Updates the quantity of the order at `index` in the queue, keeping its position.
+    pub(crate) fn update_order_quantity(&mut self, index: usize, new_quantity: Quantity) {
+        if let Some(order) = self.orders.get_mut(index) {
+            let old_quantity = order.quantity;
+            order.quantity = new_quantity;
+            self.total_quantity = self.total_quantity - old_quantity + new_quantity;
+        }
+    }
+
+    /// Returns the level's displayed total plus every iceberg reserve.
+    pub(crate) fn full_quantity(&self) -> Quantity {
+        let reserve: Quantity = self.orders.iter().map(|order| order.hidden_remaining).sum();
+        self.total_quantity + reserve
+    }
+
     /// Returns true if this price level has no orders.
     pub(crate) fn is_empty(&self) -> bool {
         self.orders.is_empty()
@@ -68,6 +124,7 @@ impl PriceLevel {
 }
 
 #[derive(Display, Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[display("{}", symbol)]
 pub struct Asset {
     /// Symbol string
@@ -86,25 +143,136 @@ impl Asset {
 }
 
 #[derive(Display, Validate, Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[validate(schema(function = "validate_instrument"))]
 #[display("{}/{}", base, quote)]
 pub struct Instrument {
     /// Base asset (e.g., BTC)
     pub base: Asset,
     /// Quote asset (e.g., USDT)
     pub quote: Asset,
+    /// Price increment in quote minor units; prices must be multiples of it
+    #[cfg_attr(
+        feature = "serde",
+        serde(default = "unit_step", skip_serializing_if = "is_unit_step")
+    )]
+    pub tick_size: Price,
+    /// Quantity increment in base minor units; quantities must be multiples of it
+    #[cfg_attr(
+        feature = "serde",
+        serde(default = "unit_step", skip_serializing_if = "is_unit_step")
+    )]
+    pub lot_size: Quantity,
 }
 impl Instrument {
+    /// Creates an instrument without validating it; see [`Instrument::try_new`].
+    ///
+    /// Tick and lot size default to one minor unit, which any value is aligned to.
     pub fn new(base: Asset, quote: Asset) -> Self {
-        Self { base, quote }
+        Self {
+            base,
+            quote,
+            tick_size: 1,
+            lot_size: 1,
+        }
+    }
+
+    /// Sets the price increment, in quote minor units.
+    pub fn with_tick_size(mut self, tick_size: Price) -> Self {
+        self.tick_size = tick_size;
+        self
+    }
+
+    /// Sets the quantity increment, in base minor units.
+    pub fn with_lot_size(mut self, lot_size: Quantity) -> Self {
+        self.lot_size = lot_size;
+        self
+    }
+
+    /// Creates an instrument, rejecting one that trades an asset against itself.
+    ///
+    /// Base and quote are the same asset when their symbols match, whatever
+    /// their decimals, so `BTC(8)/BTC(6)` is rejected as well as `BTC/BTC`.
+    pub fn try_new(base: Asset, quote: Asset) -> Result<Self, ValidationErrors> {
+        let instrument = Self::new(base, quote);
+        instrument.validate()?;
+        Ok(instrument)
     }
 }
 
+/// Schema rule for [`Instrument`]: base and quote must be different assets,
+/// and tick and lot size must be non-zero.
+fn validate_instrument(instrument: &Instrument) -> Result<(), ValidationError> {
+    if instrument.base.symbol == instrument.quote.symbol {
+        let mut error = ValidationError::new("same_asset");
+        error.message = Some(Cow::Borrowed("base and quote must be different assets"));
+        return Err(error);
+    }
+    if instrument.tick_size == 0 || instrument.lot_size == 0 {
+        let mut error = ValidationError::new("zero_step");
+        error.message = Some(Cow::Borrowed("tick and lot size must be non-zero"));
+        return Err(error);
+    }
+    Ok(())
+}
+
+/// Serde default for [`Instrument`] tick and lot size.
+#[cfg(feature = "serde")]
+fn unit_step() -> u128 {
+    1
+}
+
+/// Leaves default steps out of serialized instruments, so formats without
+/// `u128` support, such as TOML scenarios, can still carry them.
+#[cfg(feature = "serde")]
+fn is_unit_step(step: &u128) -> bool {
+    *step == 1
+}
+
+/// How a book treats prices and quantities off the instrument's tick and lot grid.
+#[derive(Display, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum AlignmentPolicy {
+    /// Misaligned orders are rejected (the default)
+    #[default]
+    Reject,
+    /// Prices snap to the nearest tick and quantities round down to a whole lot
+    Round,
+}
+
+/// How a fill at one price level is shared among its resting orders.
+#[derive(Display, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum MatchingPolicy {
+    /// Strict FIFO: the oldest order fills completely before the next (the default)
+    #[default]
+    PriceTime,
+    /// Each order fills in proportion to its visible quantity
+    ProRata,
+}
+
+/// A designated market maker and the share of each fill it is allocated
+/// ahead of time priority, see [`crate::OrderBook::with_designated_maker`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DesignatedMaker {
+    /// Account whose resting orders receive the allocation
+    pub account: AccountId,
+    /// Percentage, from 0 to 100, of the quantity traded at a level that is
+    /// allocated to the account first
+    pub allocation_pct: u8,
+}
+
 /// Represents the side of an order in the order book.
 ///
 /// Orders can be either buy orders (bids) or sell orders (asks).
 #[derive(Display, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
 #[cfg_attr(feature = "cli", value(rename_all = "lower"))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum Side {
     /// Buy order (bid) - willing to buy at specified price or lower
     Buy,
@@ -112,11 +280,47 @@ pub enum Side {
     Sell,
 }
 
+/// Trading session phase of a book.
+///
+/// Controls whether placements match, rest without matching, or are rejected.
+#[derive(Display, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum MarketPhase {
+    /// Orders are accepted and rest without matching, so the book may cross
+    PreOpen,
+    /// Orders match on arrival (the default)
+    #[default]
+    Continuous,
+    /// All placements are rejected
+    Closed,
+}
+
+/// How long an order stays eligible to trade, following FIX conventions.
+#[derive(Display, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum TimeInForce {
+    /// Good till cancelled: any remainder rests (plain `place_order`)
+    #[default]
+    Gtc,
+    /// Immediate or cancel: fills what it can at once, the remainder is discarded
+    Ioc,
+    /// Fill or kill: fills in full immediately or does nothing at all
+    Fok,
+    /// Good till date: rests until the book's sequence passes the given timestamp
+    #[display("Gtd({_0})")]
+    Gtd(Timestamp),
+    /// Day: rests until the session is closed
+    Day,
+}
+
 /// Represents an order in the order book.
 ///
 /// An order contains all the information needed to match and execute trades,
 /// including the order ID, side (buy/sell), price, quantity, and timestamp.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Order {
     /// Unique identifier for the order
     pub id: Id,
@@ -128,6 +332,20 @@ pub struct Order {
     pub quantity: Quantity,
     /// Unix timestamp when the order was created
     pub timestamp: Timestamp,
+    /// Optional client tag (e.g. a strategy ID) carried into trades
+    pub tag: Option<u32>,
+    /// How long the order stays eligible to trade
+    pub time_in_force: TimeInForce,
+    /// Size of each displayed slice of an iceberg order, 0 for fully displayed orders
+    pub display_quantity: Quantity,
+    /// Iceberg reserve not yet displayed; `quantity` holds only the visible slice
+    pub hidden_remaining: Quantity,
+    /// Randomizes the iceberg's slice sizes; `display_quantity` holds the current peak
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub random_peak: Option<RandomPeak>,
+    /// Account the order was placed for, if any
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub account: Option<AccountId>,
 }
 
 impl Order {
@@ -147,8 +365,135 @@ impl Order {
             price,
             quantity,
             timestamp,
+            tag: None,
+            time_in_force: TimeInForce::Gtc,
+            display_quantity: 0,
+            hidden_remaining: 0,
+            random_peak: None,
+            account: None,
+        }
+    }
+
+    /// Attaches a client tag to the order.
+    pub fn with_tag(mut self, tag: Option<u32>) -> Self {
+        self.tag = tag;
+        self
+    }
+
+    /// Assigns the order to an account.
+    pub fn with_account(mut self, account: Option<AccountId>) -> Self {
+        self.account = account;
+        self
+    }
+
+    /// Returns the good-till-date expiry, if the order has one.
+    pub fn expires_at(&self) -> Option<Timestamp> {
+        match self.time_in_force {
+            TimeInForce::Gtd(expiry) => Some(expiry),
+            _ => None,
         }
     }
+
+    /// Makes the order an iceberg that rests showing at most `display_quantity`.
+    pub fn with_display_quantity(mut self, display_quantity: Quantity) -> Self {
+        self.display_quantity = display_quantity;
+        self
+    }
+
+    /// Randomizes the iceberg's slice sizes, see [`RandomPeak`].
+    pub fn with_random_peak(mut self, random_peak: Option<RandomPeak>) -> Self {
+        self.random_peak = random_peak;
+        self
+    }
+
+    /// Shows the next iceberg slice from the reserve, drawing a fresh peak
+    /// first if the peak is randomized.
+    ///
+    /// The slice joins the back of its level like a new order, so it takes
+    /// the next timestamp from `clock`.
+    pub(crate) fn replenish(&mut self, clock: &mut Timestamp) {
+        self.timestamp = *clock;
+        *clock += 1;
+        if let Some(peak) = self.random_peak.as_mut() {
+            self.display_quantity = peak.next_peak();
+        }
+        let slice = self.display_quantity.min(self.hidden_remaining);
+        self.hidden_remaining -= slice;
+        self.quantity = slice;
+    }
+
+    /// Sets how long the order stays eligible to trade.
+    pub fn with_time_in_force(mut self, time_in_force: TimeInForce) -> Self {
+        self.time_in_force = time_in_force;
+        self
+    }
+}
+
+/// The SplitMix64 finalizer, a fast bijective mix of 64 bits.
+pub(crate) fn splitmix64(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// A randomized display size for an iceberg, drawn afresh for every slice.
+///
+/// Each peak is drawn uniformly, in whole lots, from `[min_peak, max_peak]`
+/// by a SplitMix64 generator started from `seed`. The same seed always shows
+/// the same sequence of slices, so backtests stay reproducible.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RandomPeak {
+    /// Smallest slice shown
+    pub min_peak: Quantity,
+    /// Largest slice shown
+    pub max_peak: Quantity,
+    /// Generator state, advanced by every draw
+    pub seed: u64,
+    /// Lot size peaks are drawn in, set by the book on placement
+    pub(crate) lot: Quantity,
+}
+
+impl RandomPeak {
+    /// Creates a peak range drawing from `seed`.
+    pub fn new(min_peak: Quantity, max_peak: Quantity, seed: u64) -> Self {
+        RandomPeak {
+            min_peak,
+            max_peak,
+            seed,
+            lot: 1,
+        }
+    }
+
+    /// Draws the next peak and advances the generator.
+    pub(crate) fn next_peak(&mut self) -> Quantity {
+        self.seed = self.seed.wrapping_add(1);
+        let (low, high) = (self.min_peak / self.lot, self.max_peak / self.lot);
+        let lots = low + u128::from(splitmix64(self.seed)) % (high - low + 1);
+        lots * self.lot
+    }
+}
+
+/// A single mutation of a book, as recorded in a command log.
+///
+/// Applying the same commands in the same order to an empty book always
+/// reproduces the same state and trades.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum BookCommand {
+    /// Place a limit order, as with `OrderBook::place_order`
+    Place {
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+        id: Id,
+    },
+    /// Cancel a resting order, as with `OrderBook::cancel_order`
+    Cancel { id: Id },
+    /// Change a resting order's quantity, as with `OrderBook::amend_quantity`
+    Amend { id: Id, quantity: Quantity },
 }
 
 /// Represents a completed trade between two orders.
@@ -156,6 +501,12 @@ impl Order {
 /// A trade occurs when a buy and sell order match at an agreed price.
 /// The maker is the order that was resting in the book, while the taker
 /// is the order that matched against it.
+///
+/// A trade carries two identifiers. `sequence` is the book's monotonic trade
+/// counter, the number of trades it executed before this one (see
+/// [`BookStats::trades`]); use it to order a trade stream or spot gaps.
+/// `trade_id` is a hash of the maker, taker and `sequence`, so it is unique
+/// but not ordered; use it to match the same trade across replicas.
 #[derive(Display, Debug, Clone, PartialEq, Eq)]
 #[display(
     "Trade: {} @ {} (maker: {}, taker: {})",
@@ -164,6 +515,7 @@ impl Order {
     maker_id,
     taker_id
 )]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Trade {
     /// Execution price of the trade
     pub price: Price,
@@ -173,6 +525,27 @@ pub struct Trade {
     pub maker_id: Id,
     /// ID of the taker order (incoming)
     pub taker_id: Id,
+    /// Client tag of the maker order, if any
+    pub maker_tag: Option<u32>,
+    /// Client tag of the taker order, if any
+    pub taker_tag: Option<u32>,
+    /// Deterministic but unordered trade identifier, see [`Trade::derive_id`]
+    pub trade_id: Id,
+    /// Position of the trade among all trades the book has executed,
+    /// starting at 0 and strictly increasing
+    pub sequence: u64,
+    /// Timestamp of the taker order
+    pub timestamp: Timestamp,
+    /// Fee charged to the maker, in quote minor units; negative for a rebate
+    pub maker_fee: i128,
+    /// Fee charged to the taker, in quote minor units; negative for a rebate
+    pub taker_fee: i128,
+    /// Account of the buying order, if any
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub buyer_account: Option<AccountId>,
+    /// Account of the selling order, if any
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub seller_account: Option<AccountId>,
 }
 
 impl Trade {
@@ -190,12 +563,450 @@ impl Trade {
             quantity,
             maker_id,
             taker_id,
+            maker_tag: None,
+            taker_tag: None,
+            trade_id: 0,
+            sequence: 0,
+            timestamp: 0,
+            maker_fee: 0,
+            taker_fee: 0,
+            buyer_account: None,
+            seller_account: None,
         }
     }
+
+    /// Derives the trade ID a book assigns to its `sequence`-th trade.
+    ///
+    /// `sequence` counts every trade the book has executed before this one,
+    /// starting at 0, so it is unique per trade within a book. The ID is
+    /// `mix(maker_id ^ mix(taker_id ^ mix(sequence)))`, where `mix` is the
+    /// SplitMix64 finalizer. It is a pure function of its inputs, so
+    /// replaying the same command log into a fresh book reproduces the same
+    /// IDs on any platform or replica.
+    pub fn derive_id(maker_id: Id, taker_id: Id, sequence: u64) -> Id {
+        let mix = splitmix64;
+        mix(maker_id ^ mix(taker_id ^ mix(sequence)))
+    }
+
+    /// Sets the trade's sequence number and taker timestamp.
+    ///
+    /// A book fills these in itself; this is for building the trades it is
+    /// expected to produce.
+    pub fn with_metadata(mut self, sequence: u64, timestamp: Timestamp) -> Self {
+        self.sequence = sequence;
+        self.timestamp = timestamp;
+        self
+    }
+
+    /// Attaches the maker's and taker's client tags to the trade.
+    pub fn with_tags(mut self, maker_tag: Option<u32>, taker_tag: Option<u32>) -> Self {
+        self.maker_tag = maker_tag;
+        self.taker_tag = taker_tag;
+        self
+    }
+
+    /// Attaches the accounts of the buying and the selling order to the trade.
+    pub fn with_accounts(mut self, buyer: Option<AccountId>, seller: Option<AccountId>) -> Self {
+        self.buyer_account = buyer;
+        self.seller_account = seller;
+        self
+    }
+
+    /// Attaches the accounts of a resting `maker` and the incoming `taker`.
+    pub(crate) fn with_accounts_of(self, maker: &Order, taker: &Order) -> Self {
+        match taker.side {
+            Side::Buy => self.with_accounts(taker.account, maker.account),
+            Side::Sell => self.with_accounts(maker.account, taker.account),
+        }
+    }
+
+    /// Returns the trade's notional plus the maker's or taker's fee, in
+    /// quote minor units.
+    ///
+    /// A rebate is a negative fee, so it brings the net cost below the gross
+    /// notional, and below zero if it exceeds it. `base_asset` is needed to
+    /// scale the notional, see [`crate::notional`]. The arithmetic saturates
+    /// at the `i128` bounds rather than overflow.
+    pub fn net_cost(&self, as_maker: bool, base_asset: &Asset) -> i128 {
+        let fee = if as_maker {
+            self.maker_fee
+        } else {
+            self.taker_fee
+        };
+        notional(self.price, self.quantity, base_asset)
+            .and_then(|gross| i128::try_from(gross).ok())
+            .unwrap_or(i128::MAX)
+            .saturating_add(fee)
+    }
 }
 /// A collection of trades, typically returned from order matching operations.
 pub type Trades = Vec<Trade>;
 
+/// A stop-limit order waiting for its trigger, see [`crate::OrderBook::place_stop_limit`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StopOrder {
+    /// Unique identifier, shared with the limit order it becomes
+    pub id: Id,
+    /// Side of the limit order submitted on trigger
+    pub side: Side,
+    /// Trade price at which the order triggers
+    pub stop_price: Price,
+    /// Limit price of the order submitted on trigger
+    pub limit_price: Price,
+    /// Quantity of the order submitted on trigger
+    pub quantity: Quantity,
+}
+
+impl StopOrder {
+    /// Returns whether a trade at `last_price` triggers the order: a buy stop
+    /// once the price rises to `stop_price`, a sell stop once it falls to it.
+    pub fn is_triggered(&self, last_price: Price) -> bool {
+        match self.side {
+            Side::Buy => last_price >= self.stop_price,
+            Side::Sell => last_price <= self.stop_price,
+        }
+    }
+}
+
+/// Maker and taker fee rates applied to every trade, in basis points.
+///
+/// A negative rate is a rebate paid to that side.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FeeSchedule {
+    /// Fee rate charged to the resting order
+    pub maker_bps: i32,
+    /// Fee rate charged to the incoming order
+    pub taker_bps: i32,
+}
+
+impl FeeSchedule {
+    /// Returns the fee at `bps` on `quantity` filled at `price`, in quote
+    /// minor units, rounded toward zero. A negative `bps` gives a rebate.
+    ///
+    /// Computed as `price * quantity * |bps| / 10_000` scaled by the base
+    /// asset's decimals, in u128 throughout, then signed. If that product
+    /// overflows, the notional is scaled first, and the fee saturates at the
+    /// `i128` bounds if even that does.
+    pub fn fee(bps: i32, price: Price, quantity: Quantity, base_asset: &Asset) -> i128 {
+        let rate = u128::from(bps.unsigned_abs());
+        let magnitude = price
+            .checked_mul(quantity)
+            .and_then(|raw| raw.checked_mul(rate))
+            .and_then(|raw| scale_notional(raw / 10_000, base_asset))
+            .or_else(|| Some(notional(price, quantity, base_asset)?.checked_mul(rate)? / 10_000))
+            .and_then(|fee| i128::try_from(fee).ok())
+            .unwrap_or(i128::MAX);
+        if bps < 0 {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+}
+
+/// The settings that decide how a book validates and matches orders.
+///
+/// Read it with [`crate::OrderBook::config`] and apply it with
+/// [`crate::OrderBook::with_config`]. Journals record it so a replay runs
+/// under the same rules; settings that only retain history or feed
+/// observers are not included. The default is the configuration of a
+/// freshly created book.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BookConfig {
+    /// Cap on order prices, see [`crate::OrderBook::with_max_price`]
+    pub max_price: Option<Price>,
+    /// Cap on order quantities, see [`crate::OrderBook::with_max_quantity`]
+    pub max_quantity: Option<Quantity>,
+    /// Limit on the distance from the own-side best, in ticks
+    pub max_price_gap_ticks: Option<u128>,
+    /// Lower bound on order notional, in quote minor units
+    pub min_notional: Option<Price>,
+    /// Upper bound on order notional, in quote minor units
+    pub max_notional: Option<Price>,
+    /// Cap on the number of trades a single placement may produce
+    pub max_fills_per_order: Option<usize>,
+    /// Treatment of prices and quantities off the tick and lot grid
+    pub alignment_policy: AlignmentPolicy,
+    /// How fills are shared among the orders at a price level
+    pub matching_policy: MatchingPolicy,
+    /// Account given priority allocations at each level, if any
+    pub designated_maker: Option<DesignatedMaker>,
+    /// How the mid is rounded onto the tick grid for midpoint pool trades
+    pub midpoint_rounding: RoundingMode,
+    /// Fee rates applied to trades, if any
+    pub fees: Option<FeeSchedule>,
+    /// Minimum logical time an order must rest before it can be cancelled
+    pub min_resting_duration: Option<Timestamp>,
+    /// Number of operations after which a resting order expires
+    pub expire_after_ops: Option<u64>,
+}
+
+/// How the price of a trade was determined.
+#[derive(Display, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum PricePolicy {
+    /// The resting (or, when uncrossing, the older) order's limit price
+    MakerPrice,
+    /// The lit book's midpoint, for orders in the midpoint pool
+    Midpoint,
+    /// The single price an auction uncrossed at, see [`crate::OrderBook::uncross`]
+    ClearingPrice,
+}
+
+/// Inputs behind one trade, captured for compliance reconstruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditRecord {
+    /// Position of the trade among all trades the book has executed
+    pub sequence: u64,
+    /// The trade itself
+    pub trade: Trade,
+    /// The incoming (taker) order as it stood just before the fill
+    pub incoming: Order,
+    /// The resting (maker) order as it stood just before the fill
+    pub resting: Order,
+    /// How the trade price was determined
+    pub price_policy: PricePolicy,
+}
+
+/// Projected outcome of a command batch, see [`crate::OrderBook::preview_batch`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BatchPreview {
+    /// Trades the batch would execute, in order
+    pub trades: Trades,
+    /// Net change in resting buy quantity, displayed and hidden
+    pub buy_quantity_delta: i128,
+    /// Net change in resting sell quantity, displayed and hidden
+    pub sell_quantity_delta: i128,
+    /// Commands that would be rejected, by position in the batch
+    pub errors: Vec<(usize, OrderBookError)>,
+}
+
+/// Outcome of a placement, with the trades it produced.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PlaceResult {
+    /// Trades executed by the order, in matching order
+    pub trades: Trades,
+    /// Whether matching stopped at the per-order fill cap while the order
+    /// could still trade; the unfilled remainder was discarded
+    pub truncated: bool,
+    /// The order as it now rests in the book, with its remaining quantity
+    /// and assigned timestamp, or `None` if nothing rested
+    pub resting: Option<Order>,
+}
+
+/// Groups trades by the taker order that produced them.
+///
+/// Works on any collection of trades, such as the `Trades` returned from a
+/// placement or a book's retained trade history. Execution order is
+/// preserved within each group.
+pub fn fills_by_taker<'a>(
+    trades: impl IntoIterator<Item = &'a Trade>,
+) -> HashMap<Id, Vec<&'a Trade>> {
+    let mut grouped: HashMap<Id, Vec<&Trade>> = HashMap::new();
+    for trade in trades {
+        grouped.entry(trade.taker_id).or_default().push(trade);
+    }
+    grouped
+}
+
+/// Open, high, low and close trade prices and traded volume over a window.
+///
+/// A book feeds every trade into its accumulator, see
+/// [`crate::OrderBook::ohlc`]; the window runs until [`OhlcAccumulator::reset`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OhlcAccumulator {
+    open: Option<Price>,
+    high: Option<Price>,
+    low: Option<Price>,
+    close: Option<Price>,
+    volume: Quantity,
+}
+
+impl OhlcAccumulator {
+    /// Adds a trade of `quantity` at `price` to the window.
+    pub fn record(&mut self, price: Price, quantity: Quantity) {
+        self.open.get_or_insert(price);
+        self.high = self.high.max(Some(price));
+        self.low = Some(self.low.map_or(price, |low| low.min(price)));
+        self.close = Some(price);
+        self.volume += quantity;
+    }
+
+    /// Price of the window's first trade, `None` before any trade.
+    pub fn open(&self) -> Option<Price> {
+        self.open
+    }
+
+    /// Highest trade price in the window.
+    pub fn high(&self) -> Option<Price> {
+        self.high
+    }
+
+    /// Lowest trade price in the window.
+    pub fn low(&self) -> Option<Price> {
+        self.low
+    }
+
+    /// Price of the window's most recent trade.
+    pub fn close(&self) -> Option<Price> {
+        self.close
+    }
+
+    /// Total quantity traded in the window.
+    pub fn volume(&self) -> Quantity {
+        self.volume
+    }
+
+    /// Starts a new, empty window.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Cumulative activity counters maintained by an order book.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BookStats {
+    /// Orders accepted by the book
+    pub orders_placed: u64,
+    /// Resting orders cancelled
+    pub orders_cancelled: u64,
+    /// Resting orders amended
+    pub orders_amended: u64,
+    /// Trades executed
+    pub trades: u64,
+    /// Work done matching the most recent limit placement
+    #[cfg(feature = "match-metrics")]
+    pub last_match: MatchMetrics,
+    /// Number of limit placements keyed by how many price levels they visited
+    #[cfg(feature = "match-metrics")]
+    pub levels_visited_histogram: std::collections::BTreeMap<u64, u64>,
+}
+
+/// Work done by the matching loop for a single placement.
+#[cfg(feature = "match-metrics")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MatchMetrics {
+    /// Opposite-side price levels the incoming order matched against
+    pub levels_visited: u64,
+    /// Resting orders the matching loop examined, whether or not they traded
+    pub orders_visited: u64,
+}
+
+impl BookStats {
+    /// Returns the ratio of order messages (placements, cancels and amends) to trades.
+    ///
+    /// Exchanges monitor this to detect quote stuffing.
+    ///
+    /// # Returns
+    ///
+    /// `None` if no trades have occurred
+    pub fn order_to_trade_ratio(&self) -> Option<f64> {
+        if self.trades == 0 {
+            return None;
+        }
+        let messages = self.orders_placed + self.orders_cancelled + self.orders_amended;
+        Some(messages as f64 / self.trades as f64)
+    }
+}
+
+/// Best buy and sell prices as sampled into a book's BBO history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BboSample {
+    /// Sequence number from which these prices held
+    pub timestamp: Timestamp,
+    /// Best buy price, if any
+    pub bid: Option<Price>,
+    /// Best sell price, if any
+    pub ask: Option<Price>,
+}
+
+impl BboSample {
+    /// Returns the floored midpoint of the two prices, if both sides were present.
+    pub fn mid_price(&self) -> Option<Price> {
+        let (bid, ask) = (self.bid?, self.ask?);
+        Some(bid / 2 + ask / 2 + (bid % 2 + ask % 2) / 2)
+    }
+}
+
+/// Fixed-depth top of book published by [`crate::OrderBook::on_l2_update`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct L2Update {
+    /// Sequence number from which this depth held
+    pub sequence: Timestamp,
+    /// Top buy levels, best first, or `None` if they did not change
+    pub bids: Option<Vec<PriceAndQuantity>>,
+    /// Top sell levels, best first, or `None` if they did not change
+    pub asks: Option<Vec<PriceAndQuantity>>,
+}
+
+/// A single resting order as captured by [`crate::OrderBook::dump`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OrderDump {
+    /// Order ID
+    pub id: Id,
+    /// Remaining resting quantity
+    pub quantity: Quantity,
+    /// Logical timestamp the order was accepted at
+    pub timestamp: Timestamp,
+}
+
+/// A price level and its orders as captured by [`crate::OrderBook::dump`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LevelDump {
+    /// Level price
+    pub price: Price,
+    /// Aggregate quantity tracked by the level
+    pub total_quantity: Quantity,
+    /// Orders in time priority, front of the queue first
+    pub orders: Vec<OrderDump>,
+}
+
+/// Full diagnostic view of a book's state.
+///
+/// Includes the cached best prices next to the levels they are derived from,
+/// so the two can be cross-checked when debugging.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BookDump {
+    /// Instrument the book trades
+    pub instrument: Instrument,
+    /// Buy levels, best (highest) first
+    pub buy_levels: Vec<LevelDump>,
+    /// Sell levels, best (lowest) first
+    pub sell_levels: Vec<LevelDump>,
+    /// Cached best buy price and quantity
+    pub best_buy: Option<PriceAndQuantity>,
+    /// Cached best sell price and quantity
+    pub best_sell: Option<PriceAndQuantity>,
+    /// Logical timestamp the next accepted order will receive
+    pub next_timestamp: Timestamp,
+}
+
+/// Persistable copy of a book's resting orders.
+///
+/// Produced by [`crate::OrderBook::snapshot`] and turned back into a book by
+/// [`crate::OrderBook::restore`]. Only the lit book is captured: the midpoint
+/// pool, trade history and the book's configuration are not.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BookSnapshot {
+    /// Instrument the book trades
+    pub instrument: Instrument,
+    /// Buy levels, best (highest) first, each with its orders in time priority
+    pub buy_side: Vec<(Price, Vec<Order>)>,
+    /// Sell levels, best (lowest) first, each with its orders in time priority
+    pub sell_side: Vec<(Price, Vec<Order>)>,
+    /// Logical timestamp the next accepted order will receive
+    pub next_timestamp: Timestamp,
+}
+
 /// Error type for order book operations
 #[derive(Display, Debug, Clone, PartialEq, Eq)]
 pub enum OrderBookError {
@@ -205,6 +1016,105 @@ pub enum OrderBookError {
     /// Order quantity is zero
     #[display("Order {} quantity {} is 0, no order placed", id, quantity)]
     ZeroQuantity { id: Id, quantity: Quantity },
+    /// Order price or quantity exceeds the book's configured absolute cap
+    #[display("Order {} value {} exceeds maximum {}", id, value, max)]
+    ValueTooLarge { id: Id, value: u128, max: u128 },
+    /// No resting order matches the given side, price and quantity
+    #[display(
+        "No resting {} order at price {} with quantity {}",
+        side,
+        price,
+        quantity
+    )]
+    NoMatchingOrder {
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+    },
+    /// Placement attempted while the market is closed
+    #[display("Order {} rejected: market is closed", id)]
+    MarketClosed { id: Id },
+    /// Taker-only order cannot be filled in full; `remaining` would have rested
+    #[display("Order {} would rest {} unfilled", id, remaining)]
+    WouldRest { id: Id, remaining: Quantity },
+    /// Market order found no liquidity to fill against
+    #[display("Market order requested {} but only {} could fill", requested, filled)]
+    InsufficientLiquidity {
+        requested: Quantity,
+        filled: Quantity,
+    },
+    /// Post-only order is priced through the best opposite price
+    #[display(
+        "Post-only order at {} would cross best opposite price {}",
+        price,
+        best_opposite
+    )]
+    WouldCross { price: Price, best_opposite: Price },
+    /// Maker-only order would have traded on arrival
+    #[display("Order {} would take liquidity", id)]
+    WouldTake { id: Id },
+    /// Maker-improving order is priced behind the best on its own side
+    #[display("Order {} at {} is behind the best price {}", id, price, best)]
+    BehindBest { id: Id, price: Price, best: Price },
+    /// Order would rest too many ticks away from the best on its own side
+    #[display(
+        "Order {} at {} is more than {} ticks from the best price {}",
+        id,
+        price,
+        max_ticks,
+        best
+    )]
+    PriceGapTooLarge {
+        id: Id,
+        price: Price,
+        best: Price,
+        max_ticks: u128,
+    },
+    /// Order has not rested long enough to be cancelled
+    #[display(
+        "Order {} has rested {} of the required {} time units",
+        id,
+        rested,
+        required
+    )]
+    MinRestingTimeNotMet {
+        id: Id,
+        rested: Timestamp,
+        required: Timestamp,
+    },
+    /// No resting order has this ID
+    #[display("Order {} not found", _0)]
+    UnknownOrderId(Id),
+    /// Replacement instrument cannot be used with the book's resting orders
+    #[display("Instrument {} is not compatible with the book", _0)]
+    IncompatibleInstrument(Box<Instrument>),
+    /// Order rests in the book but not at the best price on `side`
+    #[display("Order {} is not at the best {} price", id, side)]
+    NotAtBest { id: Id, side: Side },
+    /// Fill notification fraction outside `(0, 1]`
+    #[display("Order {} fill fraction {} is not in (0, 1]", id, fraction)]
+    InvalidFillFraction { id: Id, fraction: Decimal },
+    /// Order notional is below the book's minimum
+    #[display("Order {} notional {} is below minimum {}", id, notional, min)]
+    BelowMinNotional { id: Id, notional: Price, min: Price },
+    /// Order notional is above the book's maximum
+    #[display("Order {} notional {} exceeds maximum {}", id, notional, max)]
+    AboveMaxNotional { id: Id, notional: Price, max: Price },
+    /// `price * quantity` does not fit in a `u128`, so no notional can be computed
+    #[display("Notional of {} at price {} overflows", quantity, price)]
+    NotionalOverflow { price: Price, quantity: Quantity },
+    /// Price or quantity is not a multiple of the instrument's tick or lot size
+    #[display("Value {} is not a multiple of {}", value, step)]
+    Misaligned { value: u128, step: u128 },
+    /// Randomized iceberg peak range is empty (`min` above `max`)
+    #[display("Peak range {}..={} is empty", min, max)]
+    InvalidPeakRange { min: Quantity, max: Quantity },
+    /// A pre-trade risk check refused the order for its account's position
+    #[display("Order {} rejected by risk check at position {}", id, position)]
+    RiskLimitExceeded { id: Id, position: i128 },
+    /// Internal book structures disagree; indicates a bug in a mutation path
+    #[display("Book invariant violated: {}", _0)]
+    InvariantViolated(String),
 }
 
 #[cfg(test)]
@@ -225,6 +1135,66 @@ mod tests {
         assert_eq!(usdt.decimals, 2);
     }
 
+    // ---------- Instrument ----------
+
+    #[test]
+    fn instrument_try_new_rejects_same_asset() {
+        let instrument = Instrument::try_new(Asset::new("BTC", 6), Asset::new("USDT", 2)).unwrap();
+        assert_eq!(format!("{}", instrument), "BTC/USDT");
+
+        let errors = Instrument::try_new(Asset::new("BTC", 6), Asset::new("BTC", 6)).unwrap_err();
+        assert_eq!(errors.errors().len(), 1);
+        assert!(Instrument::try_new(Asset::new("BTC", 8), Asset::new("BTC", 6)).is_err());
+        assert!(Instrument::new(Asset::new("BTC", 6), Asset::new("BTC", 6))
+            .validate()
+            .is_err());
+        assert!(Instrument::new(Asset::new("BTC", 6), Asset::new("USDT", 2))
+            .with_tick_size(0)
+            .validate()
+            .is_err());
+    }
+
+    // ---------- fills_by_taker ----------
+
+    #[test]
+    fn fills_by_taker_groups_in_execution_order() {
+        let trades: Trades = vec![
+            Trade::new(100, 5, 1, 10),
+            Trade::new(101, 3, 2, 11),
+            Trade::new(102, 4, 3, 10),
+            Trade::new(103, 1, 4, 11),
+            Trade::new(104, 2, 5, 12),
+        ];
+
+        let grouped = fills_by_taker(&trades);
+        assert_eq!(grouped.len(), 3);
+        let makers = |taker: Id| {
+            grouped[&taker]
+                .iter()
+                .map(|t| t.maker_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(makers(10), vec![1, 3]);
+        assert_eq!(makers(11), vec![2, 4]);
+        assert_eq!(makers(12), vec![5]);
+
+        assert!(fills_by_taker(&Trades::new()).is_empty());
+    }
+
+    // ---------- BookStats ----------
+
+    #[test]
+    fn book_stats_order_to_trade_ratio() {
+        let mut stats = BookStats::default();
+        assert_eq!(stats.order_to_trade_ratio(), None);
+
+        stats.orders_placed = 6;
+        stats.orders_cancelled = 1;
+        stats.orders_amended = 1;
+        stats.trades = 4;
+        assert_eq!(stats.order_to_trade_ratio(), Some(2.0));
+    }
+
     // ---------- PriceLevel (with your Order) ----------
 
     fn mk_order(id: Id, qty: Quantity) -> Order {
@@ -261,6 +1231,23 @@ mod tests {
         assert_eq!(lvl.total_quantity, 50);
     }
 
+    #[test]
+    fn price_level_remove_at_keeps_fifo_and_totals() {
+        let mut lvl = PriceLevel::new(7);
+        lvl.add_order(mk_order(1, 10));
+        lvl.add_order(mk_order(2, 20));
+        lvl.add_order(mk_order(3, 30));
+
+        let removed = lvl.remove_order_at(1).expect("middle exists");
+        assert_eq!(removed.id, 2);
+        assert_eq!(lvl.total_quantity, 40);
+        assert_eq!(lvl.orders.front().unwrap().id, 1);
+        assert_eq!(lvl.orders.back().unwrap().id, 3);
+
+        assert!(lvl.remove_order_at(5).is_none());
+        assert_eq!(lvl.total_quantity, 40);
+    }
+
     #[test]
     fn price_level_remove_and_update_front() {
         let mut lvl = PriceLevel::new(99);
@@ -269,7 +1256,7 @@ mod tests {
         lvl.add_order(mk_order(2, 25));
 
         // Partial fill of front order: 10 -> 4
-        lvl.update_front_order_quantity(4);
+        lvl.update_front_order_quantity(1, 4);
         assert_eq!(lvl.orders.front().unwrap().quantity, 4);
         assert_eq!(lvl.total_quantity, 4 + 25);
 
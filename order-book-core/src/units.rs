@@ -1,4 +1,4 @@
-use rust_decimal::Decimal;
+use rust_decimal::{Decimal, RoundingStrategy};
 use rust_decimal::prelude::{ToPrimitive, FromPrimitive};
 use crate::types::{Asset, Price, Quantity};
 
@@ -8,46 +8,308 @@ fn pow10(n: u32) -> Decimal {
     Decimal::from_i128_with_scale(1, 0) * Decimal::from_i128_with_scale(10_i128.pow(n), 0)
 }
 
+/// How a decimal value with more precision than its asset is brought to
+/// whole minor units.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RoundingMode {
+    /// Drop the excess digits, rounding toward zero
+    #[default]
+    Truncate,
+    /// Round to the nearest minor unit, halfway values away from zero
+    HalfUp,
+    /// Round toward negative infinity
+    Floor,
+    /// Round toward positive infinity
+    Ceil,
+}
+
+impl From<RoundingMode> for RoundingStrategy {
+    fn from(mode: RoundingMode) -> Self {
+        match mode {
+            RoundingMode::Truncate => RoundingStrategy::ToZero,
+            RoundingMode::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+            RoundingMode::Floor => RoundingStrategy::ToNegativeInfinity,
+            RoundingMode::Ceil => RoundingStrategy::ToPositiveInfinity,
+        }
+    }
+}
+
 #[inline]
 pub(crate) fn to_minor_units(val: Decimal, decimals: u8) -> Option<u128> {
+    to_minor_units_with(val, decimals, RoundingMode::Truncate)
+}
+
+/// Returns `None` for negative values: prices and quantities are never
+/// negative, and rounding could otherwise turn e.g. `-0.001` into 0.
+#[inline]
+pub(crate) fn to_minor_units_with(val: Decimal, decimals: u8, mode: RoundingMode) -> Option<u128> {
+    if val < Decimal::ZERO {
+        return None;
+    }
     let m = pow10(decimals as u32);
-    (val * m).trunc().to_u128()
+    val.checked_mul(m)?.round_dp_with_strategy(0, mode.into()).to_u128()
 }
 
+/// Returns `None` if `units` is beyond `Decimal`'s 96-bit range.
 #[inline]
-pub(crate) fn from_minor_units(units: u128, decimals: u8) -> Decimal {
+pub(crate) fn from_minor_units(units: u128, decimals: u8) -> Option<Decimal> {
     let m = pow10(decimals as u32);
-    Decimal::from_u128(units).unwrap() / m
+    Decimal::from_u128(units)?.checked_div(m)
+}
+
+/// Renders minor units as an exact decimal string without going through
+/// `Decimal`, for values outside its range.
+pub(crate) fn minor_units_to_string(units: u128, decimals: u8) -> String {
+    match 10u128.checked_pow(decimals as u32) {
+        Some(1) => units.to_string(),
+        Some(scale) => {
+            format!("{}.{:0width$}", units / scale, units % scale, width = decimals as usize)
+        }
+        None => format!("{}e-{}", units, decimals),
+    }
+}
+
+/// Renders minor units in decimal form, exactly even beyond `Decimal`'s range.
+pub(crate) fn display_minor_units(units: u128, decimals: u8) -> String {
+    match from_minor_units(units, decimals) {
+        Some(value) => value.to_string(),
+        None => minor_units_to_string(units, decimals),
+    }
 }
 
 /// Converts a decimal price to minor units for the given quote asset
+///
+/// Digits beyond the asset's precision are truncated; see
+/// [`price_to_minor_units_with`] to round instead. Prices must be
+/// non-negative; negative ones return `None`.
 pub fn price_to_minor_units(price: Decimal, quote_asset: &Asset) -> Option<Price> {
     to_minor_units(price, quote_asset.decimals)
 }
 
 /// Converts a decimal quantity to minor units for the given base asset
+///
+/// Digits beyond the asset's precision are truncated; see
+/// [`quantity_to_minor_units_with`] to round instead. Quantities must be
+/// non-negative; negative ones return `None`.
 pub fn quantity_to_minor_units(quantity: Decimal, base_asset: &Asset) -> Option<Quantity> {
     to_minor_units(quantity, base_asset.decimals)
 }
 
+/// Converts a decimal price to minor units, rounding excess digits by `mode`
+pub fn price_to_minor_units_with(
+    price: Decimal,
+    quote_asset: &Asset,
+    mode: RoundingMode,
+) -> Option<Price> {
+    to_minor_units_with(price, quote_asset.decimals, mode)
+}
+
+/// Converts a decimal quantity to minor units, rounding excess digits by `mode`
+pub fn quantity_to_minor_units_with(
+    quantity: Decimal,
+    base_asset: &Asset,
+    mode: RoundingMode,
+) -> Option<Quantity> {
+    to_minor_units_with(quantity, base_asset.decimals, mode)
+}
+
 /// Converts minor units price back to decimal for the given quote asset
-pub fn price_from_minor_units(price: Price, quote_asset: &Asset) -> Decimal {
+///
+/// Returns `None` if the price is too large to represent as a `Decimal`.
+pub fn price_from_minor_units(price: Price, quote_asset: &Asset) -> Option<Decimal> {
     from_minor_units(price, quote_asset.decimals)
 }
 
 /// Converts minor units quantity back to decimal for the given base asset
-pub fn quantity_from_minor_units(quantity: Quantity, base_asset: &Asset) -> Decimal {
+///
+/// Returns `None` if the quantity is too large to represent as a `Decimal`.
+pub fn quantity_from_minor_units(quantity: Quantity, base_asset: &Asset) -> Option<Decimal> {
     from_minor_units(quantity, base_asset.decimals)
 }
 
+/// Snaps a minor-unit price to the nearest multiple of `tick`.
+///
+/// Intended for sanitizing prices from float-sourced feeds before placement,
+/// where upstream rounding can leave a price a minor unit off the tick grid.
+/// Values exactly halfway between two ticks round up. A `tick` of 0 leaves the
+/// price unchanged, and a price too close to `u128::MAX` to round up is
+/// rounded down instead.
+pub fn snap_to_tick(price_units: u128, tick: u128) -> u128 {
+    snap_to_tick_with(price_units, tick, RoundingMode::HalfUp)
+}
+
+/// Snaps a minor-unit price onto the `tick` grid using the given rounding mode.
+///
+/// `Truncate` and `Floor` both round down, since prices are never negative.
+/// Like [`snap_to_tick`], a `tick` of 0 leaves the price unchanged and a price
+/// too close to `u128::MAX` to round up is rounded down instead.
+pub fn snap_to_tick_with(price_units: u128, tick: u128, mode: RoundingMode) -> u128 {
+    if tick == 0 {
+        return price_units;
+    }
+    let remainder = price_units % tick;
+    let floor = price_units - remainder;
+    let round_up = match mode {
+        RoundingMode::Truncate | RoundingMode::Floor => false,
+        RoundingMode::HalfUp => remainder >= tick - remainder,
+        RoundingMode::Ceil => remainder > 0,
+    };
+    if round_up {
+        floor.checked_add(tick).unwrap_or(floor)
+    } else {
+        floor
+    }
+}
+
+/// Converts a raw `price * quantity` product into quote minor units.
+///
+/// Prices are quoted per whole base unit, so the product of a minor-unit price
+/// and a minor-unit quantity carries `base_asset.decimals` extra decimal places.
+/// The result is truncated. Returns `None` on overflow.
+pub(crate) fn scale_notional(raw: u128, base_asset: &Asset) -> Option<Price> {
+    Some(raw / 10u128.checked_pow(base_asset.decimals as u32)?)
+}
+
+/// Computes `a * b / c` rounded down, with a 256-bit intermediate product.
+///
+/// Returns `None` if `c` is 0 or the quotient does not fit in a `u128`.
+pub(crate) fn mul_div_floor(a: u128, b: u128, c: u128) -> Option<u128> {
+    if c == 0 {
+        return None;
+    }
+    if let Some(product) = a.checked_mul(b) {
+        return Some(product / c);
+    }
+    // Schoolbook product of the 64-bit halves into `high:low`
+    const MASK: u128 = u64::MAX as u128;
+    let (a_hi, a_lo, b_hi, b_lo) = (a >> 64, a & MASK, b >> 64, b & MASK);
+    let (lo_lo, hi_hi) = (a_lo * b_lo, a_hi * b_hi);
+    let (cross, cross_carry) = (a_hi * b_lo).overflowing_add(a_lo * b_hi);
+    let (low, low_carry) = lo_lo.overflowing_add(cross << 64);
+    let high = hi_hi + (cross >> 64) + ((cross_carry as u128) << 64) + low_carry as u128;
+    if high >= c {
+        return None;
+    }
+    // Long division of `high:low` by `c`, one bit at a time; `high < c`
+    // keeps the quotient within 128 bits
+    let (mut remainder, mut quotient) = (high, 0u128);
+    for bit in (0..128).rev() {
+        let overflow = remainder >> 127 == 1;
+        remainder = (remainder << 1) | ((low >> bit) & 1);
+        quotient <<= 1;
+        if overflow || remainder >= c {
+            remainder = remainder.wrapping_sub(c);
+            quotient |= 1;
+        }
+    }
+    Some(quotient)
+}
+
+/// Computes the notional value of `quantity` at `price` in quote minor units.
+///
+/// Returns `None` if the intermediate product overflows.
+pub fn notional(price: Price, quantity: Quantity, base_asset: &Asset) -> Option<Price> {
+    scale_notional(price.checked_mul(quantity)?, base_asset)
+}
+
 /// Formats a price in minor units for display with the quote asset symbol
+///
+/// Prices beyond `Decimal`'s range are still formatted exactly.
 pub fn format_price(price: Price, quote_asset: &Asset) -> String {
-    let decimal_price = price_from_minor_units(price, quote_asset);
+    let decimal_price = display_minor_units(price, quote_asset.decimals);
     format!("{} {}", decimal_price, quote_asset.symbol)
 }
 
-/// Formats a quantity in minor units for display with the base asset symbol  
+/// Formats a quantity in minor units for display with the base asset symbol
+///
+/// Quantities beyond `Decimal`'s range are still formatted exactly.
 pub fn format_quantity(quantity: Quantity, base_asset: &Asset) -> String {
-    let decimal_quantity = quantity_from_minor_units(quantity, base_asset);
+    let decimal_quantity = display_minor_units(quantity, base_asset.decimals);
     format!("{} {}", decimal_quantity, base_asset.symbol)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snap_to_tick_rounds_to_nearest() {
+        // Just below and just above the 10_000 boundary on a 5-unit tick
+        assert_eq!(snap_to_tick(9_999, 5), 10_000);
+        assert_eq!(snap_to_tick(10_001, 5), 10_000);
+        assert_eq!(snap_to_tick(10_002, 5), 10_000);
+        assert_eq!(snap_to_tick(10_003, 5), 10_005);
+        // Already aligned
+        assert_eq!(snap_to_tick(10_005, 5), 10_005);
+        // Halfway rounds up
+        assert_eq!(snap_to_tick(15, 10), 20);
+    }
+
+    #[test]
+    fn from_minor_units_out_of_range_does_not_panic() {
+        let usdt = Asset::new("USDT", 2);
+        assert_eq!(price_from_minor_units(u128::MAX, &usdt), None);
+        assert_eq!(quantity_from_minor_units(u128::MAX, &usdt), None);
+        assert_eq!(
+            format_price(u128::MAX, &usdt),
+            "3402823669209384634633746074317682114.55 USDT"
+        );
+        assert_eq!(format_price(10_050, &usdt), "100.50 USDT");
+    }
+
+    #[test]
+    fn to_minor_units_rounds_half_boundary_by_mode() {
+        let usdt = Asset::new("USDT", 2);
+        let half = Decimal::new(100_005, 3);
+        let convert = |mode| price_to_minor_units_with(half, &usdt, mode);
+        assert_eq!(convert(RoundingMode::Truncate), Some(10_000));
+        assert_eq!(convert(RoundingMode::HalfUp), Some(10_001));
+        assert_eq!(convert(RoundingMode::Floor), Some(10_000));
+        assert_eq!(convert(RoundingMode::Ceil), Some(10_001));
+        // Plain conversion keeps truncating
+        assert_eq!(price_to_minor_units(Decimal::new(100_999, 3), &usdt), Some(10_099));
+        let btc = Asset::new("BTC", 6);
+        let lot = Decimal::new(15, 7);
+        assert_eq!(quantity_to_minor_units_with(lot, &btc, RoundingMode::HalfUp), Some(2));
+    }
+
+    #[test]
+    fn to_minor_units_rejects_negative_values() {
+        let usdt = Asset::new("USDT", 2);
+        assert_eq!(to_minor_units(Decimal::new(-1, 2), 2), None);
+        assert_eq!(to_minor_units(Decimal::from(-1), 2), None);
+        // Would otherwise truncate to zero
+        assert_eq!(price_to_minor_units(Decimal::new(-1, 3), &usdt), None);
+        for mode in [RoundingMode::HalfUp, RoundingMode::Floor, RoundingMode::Ceil] {
+            assert_eq!(price_to_minor_units_with(Decimal::new(-1, 2), &usdt, mode), None);
+        }
+        assert_eq!(to_minor_units(Decimal::ZERO, 2), Some(0));
+    }
+
+    #[test]
+    fn snap_to_tick_with_respects_mode() {
+        assert_eq!(snap_to_tick_with(10_003, 5, RoundingMode::Floor), 10_000);
+        assert_eq!(snap_to_tick_with(10_003, 5, RoundingMode::Truncate), 10_000);
+        assert_eq!(snap_to_tick_with(10_001, 5, RoundingMode::Ceil), 10_005);
+        assert_eq!(snap_to_tick_with(10_005, 5, RoundingMode::Ceil), 10_005);
+        assert_eq!(snap_to_tick_with(u128::MAX, 10, RoundingMode::Ceil), u128::MAX - 5);
+    }
+
+    #[test]
+    fn mul_div_floor_widens_the_product() {
+        assert_eq!(mul_div_floor(7, 5, 3), Some(11));
+        assert_eq!(mul_div_floor(u128::MAX, u128::MAX, u128::MAX), Some(u128::MAX));
+        assert_eq!(mul_div_floor(u128::MAX, 3, u128::MAX - 1), Some(3));
+        assert_eq!(mul_div_floor(u128::MAX / 2, u128::MAX / 3, u128::MAX), Some(u128::MAX / 6));
+        assert_eq!(mul_div_floor(1 << 100, 1 << 100, 1 << 90), Some(1 << 110));
+        assert_eq!(mul_div_floor(u128::MAX, 2, 1), None);
+        assert_eq!(mul_div_floor(1, 1, 0), None);
+    }
+
+    #[test]
+    fn snap_to_tick_edge_cases() {
+        assert_eq!(snap_to_tick(1_234, 0), 1_234);
+        assert_eq!(snap_to_tick(1_234, 1), 1_234);
+        assert_eq!(snap_to_tick(u128::MAX, 10), u128::MAX - 5);
+    }
+}